@@ -0,0 +1,104 @@
+//! Module containing functions for analysing audio, rather than processing it.
+//! detect_pitch estimates the fundamental frequency of a sample using a normalized autocorrelation.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+/// The lowest frequency in Hz that pitch detection will search for
+const MIN_FREQUENCY: f32 = 40.0;
+/// The highest frequency in Hz that pitch detection will search for
+const MAX_FREQUENCY: f32 = 4000.0;
+/// The maximum number of samples analysed, taken from the start of the input
+const MAX_ANALYSIS_LENGTH: usize = 8192;
+/// How correlated the signal must be with itself at the detected period to count as pitched, between 0 and 1
+const CLARITY_THRESHOLD: f32 = 0.8;
+
+/// Estimates the fundamental frequency of a mono sample, returning `None` if the input is unpitched or noisy.
+///
+/// Uses the normalized square difference function (a normalized autocorrelation),
+/// picking the first peak which is close to the highest one to avoid detecting an octave below the fundamental.
+/// The peak is refined with parabolic interpolation for sub-sample accuracy.
+/// Stereo input should be split into a single channel first, for example from `IntSamples::get_frames`.
+/// # Parameters
+/// * `samples`: The mono samples to analyse, only the first 8192 are used
+/// * `sample_rate`: The sample rate of the input in Hz
+pub fn detect_pitch(samples: &[i16], sample_rate: f32) -> Option<f32> {
+    let input: Vec<f32> = samples
+        .iter()
+        .take(MAX_ANALYSIS_LENGTH)
+        .map(|sample| *sample as f32 / i16::MAX as f32)
+        .collect();
+
+    let min_lag = (sample_rate / MAX_FREQUENCY) as usize;
+    let max_lag = ((sample_rate / MIN_FREQUENCY) as usize).min(input.len() / 2);
+    if min_lag == 0 || max_lag <= min_lag + 1 {
+        return None;
+    }
+
+    // normalized square difference for every lag, 1 means perfectly periodic at that lag
+    let nsdf: Vec<f32> = (0..=max_lag)
+        .map(|lag| {
+            let (correlation, energy) = input
+                .iter()
+                .zip(input[lag..].iter())
+                .fold((0.0, 0.0), |(correlation, energy), (a, b)| {
+                    (correlation + a * b, energy + a * a + b * b)
+                });
+            match energy {
+                energy if energy > 0.0 => 2.0 * correlation / energy,
+                _ => 0.0,
+            }
+        })
+        .collect();
+
+    // local maxima between the lag limits, once the function has first dipped below 0
+    let first_negative = nsdf.iter().position(|value| *value < 0.0)?;
+    let peaks: Vec<usize> = (first_negative.max(min_lag)..max_lag)
+        .filter(|&lag| nsdf[lag] > nsdf[lag - 1] && nsdf[lag] >= nsdf[lag + 1])
+        .collect();
+
+    let highest = peaks.iter().map(|&lag| nsdf[lag]).fold(f32::MIN, f32::max);
+    if highest < CLARITY_THRESHOLD {
+        return None;
+    }
+
+    let lag = *peaks.iter().find(|&&lag| nsdf[lag] >= highest * 0.9)?;
+
+    // fit a parabola through the peak and its neighbours to find the true peak between samples
+    let (left, centre, right) = (nsdf[lag - 1], nsdf[lag], nsdf[lag + 1]);
+    let denominator = left - 2.0 * centre + right;
+    let offset = match denominator {
+        denominator if denominator.abs() > f32::EPSILON => 0.5 * (left - right) / denominator,
+        _ => 0.0,
+    };
+
+    Some(sample_rate / (lag as f32 + offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::detect_pitch;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_detect_sine() {
+        for frequency in [82.4, 440.0, 1234.5] {
+            let samples: Vec<i16> = (0..8192)
+                .map(|n| ((2.0 * PI * frequency * n as f32 / 44100.0).sin() * 16000.0) as i16)
+                .collect();
+            let detected = detect_pitch(&samples, 44100.0).unwrap();
+            assert!(
+                (detected - frequency).abs() < frequency * 0.005,
+                "detected {detected}Hz for a {frequency}Hz sine"
+            );
+        }
+    }
+
+    #[test]
+    fn test_noise_is_unpitched() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let samples: Vec<i16> = (0..8192).map(|_| rng.gen_range(-16000..16000)).collect();
+        assert_eq!(detect_pitch(&samples, 44100.0), None);
+    }
+}
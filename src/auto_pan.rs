@@ -0,0 +1,105 @@
+//! Module containing an auto-pan effect, which sweeps a signal between the left and right channels.
+//! AutoPan drives an equal power pan law from a free running or tempo synced MMLFO.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use crate::lfo::{LFOMode, MMLFO};
+use crate::multi_channel::equal_power_pan;
+use crate::timing::Timing;
+use std::f32::consts::SQRT_2;
+
+/// A struct which pans a stereo or mono signal back and forth, driven by an LFO.
+/// ## Attributes:
+/// * `lfo`: The bipolar LFO deciding the pan position, its output of -1 to 1 is mapped from left to right
+///
+/// * `depth`: How far the signal is panned from the center, from 0 (always centered) to 1 (hard left to hard right)
+pub struct AutoPan {
+    lfo: MMLFO,
+    depth: f32,
+}
+
+impl AutoPan {
+    /// Constructor for the auto-pan, with a 1Hz sine LFO at full depth
+    /// # Parameters
+    /// * `sample_rate`: The sample rate of the input in Hz
+    pub fn new(sample_rate: f32) -> Self {
+        let mut lfo = MMLFO::new(false, LFOMode::Sine);
+        lfo.set_sample_rate(sample_rate);
+        lfo.set_frequency_hz(1.0);
+        lfo.set_bipolar(true);
+        Self { lfo, depth: 1.0 }
+    }
+
+    /// Setter for the pan rate in Hz, turning off tempo sync
+    pub fn set_rate(&mut self, rate_hz: f32) {
+        self.lfo.set_frequency_hz(rate_hz);
+    }
+
+    /// Setter for a tempo synced pan rate, where one full sweep left and right takes the length of the timing
+    pub fn set_sync(&mut self, timing: Timing) {
+        self.lfo.set_timing(timing);
+        self.lfo.set_sync(true);
+    }
+
+    /// Setter for the pan depth, between 0 (centered) and 1 (hard left to hard right)
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Setter for the shape of the pan sweep, such as `LFOMode::Square` for hard switching between sides
+    pub fn set_shape(&mut self, shape: LFOMode) {
+        self.lfo.set_mode(shape);
+    }
+
+    /// Processes a single pair of samples, returning the panned (left, right) pair.
+    /// For a mono signal, pass the same sample to both channels.
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let pan = self.depth * self.lfo.get_next_value();
+        let (left_gain, right_gain) = equal_power_pan(pan);
+        // the pan law gives -3dB in the center, so scale back up to leave a centered signal unchanged
+        (left * left_gain * SQRT_2, right * right_gain * SQRT_2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auto_pan::AutoPan;
+    use crate::lfo::LFOMode;
+
+    #[test]
+    fn test_sweep_rate() {
+        let mut auto_pan = AutoPan::new(44100.0);
+        auto_pan.set_shape(LFOMode::Sine);
+        auto_pan.set_rate(2.0);
+
+        let output: Vec<(f32, f32)> = (0..44100).map(|_| auto_pan.process(1.0, 1.0)).collect();
+
+        // the signal crosses the center twice per cycle of the LFO
+        let crossings = output
+            .windows(2)
+            .filter(|pair| (pair[0].0 > pair[0].1) != (pair[1].0 > pair[1].1))
+            .count();
+        assert!((3..=5).contains(&crossings), "{crossings} center crossings");
+
+        // at full depth each channel is silent at its extreme
+        let quietest_left = output
+            .iter()
+            .map(|(left, _)| *left)
+            .fold(f32::MAX, f32::min);
+        let quietest_right = output
+            .iter()
+            .map(|(_, right)| *right)
+            .fold(f32::MAX, f32::min);
+        assert!(quietest_left < 0.01 && quietest_right < 0.01);
+    }
+
+    #[test]
+    fn test_zero_depth_is_centered() {
+        let mut auto_pan = AutoPan::new(44100.0);
+        auto_pan.set_depth(0.0);
+        for _ in 0..1000 {
+            let (left, right) = auto_pan.process(0.5, 0.5);
+            assert!((left - 0.5).abs() < 1e-5 && (right - 0.5).abs() < 1e-5);
+        }
+    }
+}
@@ -3,6 +3,11 @@
 //! A module containing a circular buffer which can read at a specified delay behind the write pointer.
 //! Reads and writes float samples.
 
+use crate::interpolators::lerp;
+
+/// The longest latency in samples that a dry signal can be delayed by to align it with a wet signal
+pub const MAX_DRY_COMPENSATION_SAMPLES: usize = 4096;
+
 #[derive(Debug, Clone)]
 /// A circular buffer with read and write functionality based on a delay
 pub struct DelayBuffer {
@@ -38,6 +43,39 @@ impl DelayBuffer {
         };
         self.buffer[offset] // return the sample from the buffer at the offset.
     }
+
+    /// A function to silence every sample in the buffer and return the write pointer to the start
+    pub fn clear(&mut self) {
+        self.buffer.fill(0.0);
+        self.index = 0;
+    }
+
+    /// A function to change the capacity of the buffer (at least 1) while audio is running,
+    /// keeping the most recently written samples so that reads within the new capacity carry on without a dropout
+    pub fn resize(&mut self, new_capacity: usize) {
+        let new_capacity = new_capacity.max(1);
+        let kept = self.buffer.len().min(new_capacity);
+
+        let mut buffer = vec![0.0; new_capacity];
+        // the kept samples are laid out oldest first from the start of the new buffer
+        for (position, delay) in (0..kept).rev().enumerate() {
+            buffer[position] = self.read(delay);
+        }
+        self.buffer = buffer;
+        self.index = kept % new_capacity;
+    }
+
+    /// A function to read several taps at once, returning one sample per delay (each given as usize) in the same order
+    pub fn read_taps(&self, delays: &[usize]) -> Vec<f32> {
+        delays.iter().map(|delay| self.read(*delay)).collect()
+    }
+
+    /// A function to read data at a fractional delay behind the write pointer,
+    /// linearly interpolating between the samples either side of it
+    pub fn read_fractional(&self, delay: f32) -> f32 {
+        let whole = delay as usize;
+        lerp(self.read(whole), self.read(whole + 1), delay.fract())
+    }
 }
 
 #[cfg(test)]
@@ -87,4 +125,64 @@ mod tests {
         assert_eq!(delay_buffer.read(1), 4.0);
         assert_eq!(delay_buffer.read(2), 3.0);
     }
+
+    #[test]
+    fn test_read_fractional() {
+        let mut delay_buffer = DelayBuffer::new(5);
+        delay_buffer.write(1.0);
+        delay_buffer.write(2.0);
+        delay_buffer.write(4.0);
+        assert_eq!(delay_buffer.read_fractional(0.0), 4.0);
+        assert_eq!(delay_buffer.read_fractional(0.5), 3.0);
+        assert_eq!(delay_buffer.read_fractional(1.25), 1.75);
+    }
+
+    #[test]
+    fn test_read_taps() {
+        let mut delay_buffer = DelayBuffer::new(5);
+        for value in 1..=7 {
+            delay_buffer.write(value as f32);
+        }
+        // the taps wrap around the start of the buffer the same as single reads
+        assert_eq!(
+            delay_buffer.read_taps(&[0, 2, 1, 4]),
+            vec![7.0, 5.0, 6.0, 3.0]
+        );
+        assert!(delay_buffer.read_taps(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut delay_buffer = DelayBuffer::new(5);
+        for value in 1..=7 {
+            delay_buffer.write(value as f32);
+        }
+
+        // growing keeps every sample, with silence further back
+        delay_buffer.resize(8);
+        assert_eq!(
+            delay_buffer.read_taps(&[0, 1, 4, 5, 7]),
+            vec![7.0, 6.0, 3.0, 0.0, 0.0]
+        );
+        delay_buffer.write(8.0);
+        assert_eq!(delay_buffer.read_taps(&[0, 1, 5]), vec![8.0, 7.0, 3.0]);
+
+        // shrinking keeps the most recent samples
+        delay_buffer.resize(3);
+        assert_eq!(delay_buffer.buffer.len(), 3);
+        assert_eq!(delay_buffer.read_taps(&[0, 1, 2]), vec![8.0, 7.0, 6.0]);
+        delay_buffer.write(9.0);
+        assert_eq!(delay_buffer.read_taps(&[0, 1, 2]), vec![9.0, 8.0, 7.0]);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut delay_buffer = DelayBuffer::new(5);
+        for value in 1..=3 {
+            delay_buffer.write(value as f32);
+        }
+        delay_buffer.clear();
+        assert_eq!(delay_buffer.buffer, vec![0.0; 5]);
+        assert_eq!(delay_buffer.index, 0);
+    }
 }
@@ -3,27 +3,48 @@
 //! A module containing structs for a delay line and delay processor.
 //! Delay line implements a delay line with dynamic delay times and a first order low-pass in the feedback loop.
 //! Stereo Delay implements a delay processor that has 2 delay independently timed delay lines and processes stereo sample pairs.
-//! Both use f32 samples
+//! Delay Bank chains multiple stereo delays together, either in series or in parallel.
+//! All use f32 samples
 
-use crate::delay_buffer::DelayBuffer;
+use crate::delay_buffer::{DelayBuffer, MAX_DRY_COMPENSATION_SAMPLES};
+use crate::envelope::EnvelopeFollower;
 use crate::filter::LowpassFilter;
+use crate::oversampling::{OversampleFactor, Oversampled};
+use crate::quality::Quality;
 use crate::saturation::Saturator;
-use crate::timing::Timing;
+use crate::timing::{Timing, TransportSync};
+
+/// The attack time in milliseconds of the follower deciding how far the feedback is ducked
+const DUCKING_ATTACK_MS: f32 = 5.0;
+
+/// The release time in milliseconds of the ducking follower, which sets how quickly repeats bloom after playing stops
+const DUCKING_RELEASE_MS: f32 = 50.0;
+
+/// The input level, relative to a full scale of 1, at which the feedback is ducked by the whole ducking amount
+const DUCKING_FULL_LEVEL: f32 = 0.25;
 
 /// A delay line which can process inputs with internal feedback and internal filtering as well as dry/wet mix control
 /// # Attributes
 /// * `buffer`: A delay buffer object storing samples
 /// * `delay_samples`: Number of samples to delay input by
+/// * `delay_fraction`: The fractional part of the delay time in samples, between 0 and 1, which is interpolated over
 /// * `internal_feedback`: Internal feedback multiplier **do not exceed 1 - may create infinite feedback and clipping**
+/// * `feedback_duck`: A multiplier on the feedback between 0 and 1, lowered while the input is ducking the feedback
 /// * `mix_ratio`: Ratio of dry to wet (ratio of 1 is 100% wet) **do not exceed 1**
 /// * `filter`: A lowpass filter applied in the feedback loop
+/// * `dry_delay`: A delay buffer used to delay the dry signal, keeping it aligned with a wet signal which has latency
+/// * `dry_compensation_samples`: Number of samples to delay the dry signal by before mixing
 #[derive(Debug)]
 pub struct DelayLine {
     buffer: DelayBuffer,
     delay_samples: usize,
+    delay_fraction: f32,
     internal_feedback: f32,
+    feedback_duck: f32,
     mix_ratio: f32,
     filter: LowpassFilter,
+    dry_delay: DelayBuffer,
+    dry_compensation_samples: usize,
 }
 
 impl DelayLine {
@@ -42,9 +63,13 @@ impl DelayLine {
         Self {
             buffer: DelayBuffer::new(max_delay_samples),
             delay_samples,
+            delay_fraction: 0.0,
             internal_feedback,
+            feedback_duck: 1.0,
             mix_ratio,
             filter: LowpassFilter::new(5000.0, 44100.0, max_delay_samples),
+            dry_delay: DelayBuffer::new(MAX_DRY_COMPENSATION_SAMPLES + 1),
+            dry_compensation_samples: 0,
         }
     }
 
@@ -52,20 +77,62 @@ impl DelayLine {
     /// # Parameters
     /// * `xn`: The input sample to be processed, named this way because of the nomenclature on block diagrams and difference equations
     pub fn process_with_feedback(&mut self, xn: f32, do_filtering: bool) -> (f32, f32) {
-        let delay_signal: f32 = self.buffer.read(self.delay_samples);
+        let (delay_signal, feedback_signal) = self.read_feedback(do_filtering);
+        let yn = self.write_and_mix(xn, feedback_signal, delay_signal);
+        (yn, yn)
+    }
+
+    /// Reads the delayed signal, returning a tuple of (delayed signal, feedback signal).
+    /// The feedback signal is scaled by the feedback gain and filtered if `do_filtering` is true,
+    /// ready to be written back by `write_and_mix`, possibly into another delay line.
+    pub fn read_feedback(&mut self, do_filtering: bool) -> (f32, f32) {
+        // whole sample delays are read directly, so they pass the signal through unfiltered
+        let delay_signal: f32 = match self.delay_fraction == 0.0 {
+            true => self.buffer.read(self.delay_samples),
+            false => self
+                .buffer
+                .read_fractional(self.delay_samples as f32 + self.delay_fraction),
+        };
+        let feedback = self.internal_feedback * self.feedback_duck;
         let feedback_signal: f32 = match do_filtering {
-            true => self.filter.process(delay_signal) * self.internal_feedback,
-            false => delay_signal * self.internal_feedback,
+            true => self.filter.process(delay_signal) * feedback,
+            false => delay_signal * feedback,
         };
+        (delay_signal, feedback_signal)
+    }
 
+    /// Writes the input and a feedback signal into the delay, returning the output mixed from the delayed signal
+    /// read by `read_feedback` and the dry input
+    pub fn write_and_mix(&mut self, xn: f32, feedback_signal: f32, delay_signal: f32) -> f32 {
         self.buffer.write(xn + feedback_signal);
 
+        // the dry signal is delayed to line up with any latency in the wet path
+        self.dry_delay.write(xn);
+        let dry_signal = self.dry_delay.read(self.dry_compensation_samples);
+
         let wet_lvl = self.mix_ratio;
         let dry_lvl = 1.0 - self.mix_ratio;
 
         // yn is the output notation from block diagrams
-        let yn = (wet_lvl * delay_signal) + (dry_lvl * xn);
-        (yn, yn)
+        (wet_lvl * delay_signal) + (dry_lvl * dry_signal)
+    }
+
+    /// Setter for the number of samples the dry signal is delayed by before mixing,
+    /// which should match the latency of the wet path. Limited to `MAX_DRY_COMPENSATION_SAMPLES`
+    pub fn set_dry_compensation_samples(&mut self, samples: usize) {
+        self.dry_compensation_samples = samples.min(MAX_DRY_COMPENSATION_SAMPLES);
+    }
+
+    /// Clears the history of the feedback filter, so it doesn't carry over into the next signal
+    pub fn reset_filter(&mut self) {
+        self.filter.reset();
+    }
+
+    /// Silences the delay and dry compensation buffers and the feedback filter, removing any delay tail
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.dry_delay.clear();
+        self.filter.reset();
     }
 
     #[allow(missing_docs)]
@@ -80,12 +147,21 @@ impl DelayLine {
 
     #[allow(missing_docs)]
     pub fn get_delay_seconds(&self) -> f32 {
-        self.delay_samples as f32 / 44100_f32
+        (self.delay_samples as f32 + self.delay_fraction) / 44100_f32
     }
 
     #[allow(missing_docs)]
     pub fn set_delay_samples(&mut self, delay_samples: usize) {
         self.delay_samples = delay_samples;
+        self.delay_fraction = 0.0;
+    }
+
+    /// Setter for a delay time which may fall between samples, where the output is linearly interpolated
+    /// so that modulating the time sweeps smoothly rather than snapping between samples
+    pub fn set_delay_samples_fractional(&mut self, delay_samples: f32) {
+        let delay_samples = delay_samples.max(0.0);
+        self.delay_samples = delay_samples as usize;
+        self.delay_fraction = delay_samples.fract();
     }
 
     #[allow(missing_docs)]
@@ -93,18 +169,35 @@ impl DelayLine {
         self.internal_feedback = internal_feedback;
     }
 
+    /// Setter for the multiplier on the feedback used for ducking, between 0 (no feedback) and 1 (not ducked)
+    pub fn set_feedback_duck(&mut self, duck: f32) {
+        self.feedback_duck = duck.clamp(0.0, 1.0);
+    }
+
     #[allow(missing_docs)]
     pub fn set_mix_ratio(&mut self, mix_ratio: f32) {
         self.mix_ratio = mix_ratio;
     }
 }
 
+/// The widest stereo width of the echoes, where the side signal is doubled
+const MAX_WIDTH: f32 = 2.0;
+
 /// A struct capturing full delay functionality with independent left and right delay lines.
 pub struct StereoDelay {
     left_dl: DelayLine,
     right_dl: DelayLine,
     sample_rate: f32,
     saturator: Saturator,
+    oversampled_x2: [Oversampled<Saturator>; 2],
+    oversampled_x4: [Oversampled<Saturator>; 2],
+    quality: Quality,
+    ducking: f32,
+    ducking_follower: EnvelopeFollower,
+    cross_feedback: f32,
+    timing_left: Option<Timing>,
+    timing_right: Option<Timing>,
+    width: f32,
 }
 
 impl StereoDelay {
@@ -130,11 +223,25 @@ impl StereoDelay {
 
         let left_dl = DelayLine::new(max_delay_samples, delay_samples_l, feedback, mix);
         let right_dl = DelayLine::new(max_delay_samples, delay_samples_r, feedback, mix);
+        let saturator = Saturator::new(i16::MAX as f32 / 64.0, 0.5);
         Self {
             left_dl,
             right_dl,
             sample_rate,
-            saturator: Saturator::new(i16::MAX as f32 / 64.0, 0.5),
+            oversampled_x2: Self::build_oversampled(&saturator, OversampleFactor::X2, sample_rate),
+            oversampled_x4: Self::build_oversampled(&saturator, OversampleFactor::X4, sample_rate),
+            saturator,
+            quality: Quality::Low,
+            ducking: 0.0,
+            cross_feedback: 0.0,
+            timing_left: None,
+            timing_right: None,
+            width: 1.0,
+            ducking_follower: EnvelopeFollower::new(
+                DUCKING_ATTACK_MS,
+                DUCKING_RELEASE_MS,
+                sample_rate,
+            ),
         }
     }
 
@@ -168,11 +275,25 @@ impl StereoDelay {
 
         let left_dl = DelayLine::new(max_delay_samples, delay_samples_l, feedback, mix);
         let right_dl = DelayLine::new(max_delay_samples, delay_samples_r, feedback, mix);
+        let saturator = Saturator::new(i16::MAX as f32 / 64.0, 0.5);
         Self {
             left_dl,
             right_dl,
             sample_rate,
-            saturator: Saturator::new(i16::MAX as f32 / 64.0, 0.5),
+            oversampled_x2: Self::build_oversampled(&saturator, OversampleFactor::X2, sample_rate),
+            oversampled_x4: Self::build_oversampled(&saturator, OversampleFactor::X4, sample_rate),
+            saturator,
+            quality: Quality::Low,
+            ducking: 0.0,
+            cross_feedback: 0.0,
+            timing_left: Some(timing_left),
+            timing_right: Some(timing_right),
+            width: 1.0,
+            ducking_follower: EnvelopeFollower::new(
+                DUCKING_ATTACK_MS,
+                DUCKING_RELEASE_MS,
+                sample_rate,
+            ),
         }
     }
 
@@ -184,44 +305,177 @@ impl StereoDelay {
         do_filtering: bool,
         saturate: bool,
     ) -> (f32, f32) {
-        let (l_in, r_in) = match saturate {
-            true => (
+        let oversampled = match self.quality.oversample_factor() {
+            Some(OversampleFactor::X2) => Some(&mut self.oversampled_x2),
+            Some(OversampleFactor::X4) => Some(&mut self.oversampled_x4),
+            None => None,
+        };
+        let (l_in, r_in) = match (saturate, oversampled) {
+            (true, Some([left, right])) => (left.process(in_sample_l), right.process(in_sample_r)),
+            (true, None) => (
                 self.saturator.process(in_sample_l),
                 self.saturator.process(in_sample_r),
             ),
-            false => (in_sample_l, in_sample_r),
+            (false, _) => (in_sample_l, in_sample_r),
         };
 
-        let (out_left, _) = self.left_dl.process_with_feedback(l_in, do_filtering);
+        if self.ducking > 0.0 {
+            // the feedback is ducked by the level of the dry input, so repeats build up in the gaps in playing
+            let level = self
+                .ducking_follower
+                .process(in_sample_l.abs().max(in_sample_r.abs()));
+            let duck = 1.0 - self.ducking * (level / DUCKING_FULL_LEVEL).min(1.0);
+            self.left_dl.set_feedback_duck(duck);
+            self.right_dl.set_feedback_duck(duck);
+        }
 
-        let (out_right, _) = self.right_dl.process_with_feedback(r_in, do_filtering);
+        // both feedback signals are read before either is written, so each can be routed into the other line
+        let (delay_left, feedback_left) = self.left_dl.read_feedback(do_filtering);
+        let (delay_right, feedback_right) = self.right_dl.read_feedback(do_filtering);
+
+        // the width only scales the side of the echoes, the dry signal and the feedback are left alone
+        let mid = 0.5 * (delay_left + delay_right);
+        let side = 0.5 * (delay_left - delay_right) * self.width;
+        let (delay_left, delay_right) = (mid + side, mid - side);
+
+        let cross = self.cross_feedback;
+        let out_left = self.left_dl.write_and_mix(
+            l_in,
+            (1.0 - cross) * feedback_left + cross * feedback_right,
+            delay_left,
+        );
+        let out_right = self.right_dl.write_and_mix(
+            r_in,
+            (1.0 - cross) * feedback_right + cross * feedback_left,
+            delay_right,
+        );
 
         (out_left, out_right)
     }
 
-    /// Setter for left delay line time in seconds
+    /// Setter for the stereo width of the echoes, clamped between 0 and `MAX_WIDTH`.
+    /// 0 collapses the echoes to mono, 1 leaves them untouched and values above 1 widen them
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width.clamp(0.0, MAX_WIDTH);
+    }
+
+    /// Setter for left delay line time in seconds, which stops the left line following the tempo
     pub fn set_time_left(&mut self, time_s: f32) {
-        self.left_dl.delay_samples = (self.sample_rate * time_s) as usize
+        self.timing_left = None;
+        self.left_dl
+            .set_delay_samples_fractional(self.sample_rate * time_s)
     }
 
-    /// Setter for right delay line time in seconds
+    /// Setter for right delay line time in seconds, which stops the right line following the tempo
     pub fn set_time_right(&mut self, time_s: f32) {
-        self.right_dl.delay_samples = (self.sample_rate * time_s) as usize
+        self.timing_right = None;
+        self.right_dl
+            .set_delay_samples_fractional(self.sample_rate * time_s)
+    }
+
+    /// Setter for left delay line time as a tempo synced timing, which follows later changes from `set_bpm`
+    pub fn set_timing_left(&mut self, timing: Timing) {
+        self.left_dl
+            .set_delay_samples_fractional(self.sample_rate * timing.to_seconds());
+        self.timing_left = Some(timing);
+    }
+
+    /// Setter for right delay line time as a tempo synced timing, which follows later changes from `set_bpm`
+    pub fn set_timing_right(&mut self, timing: Timing) {
+        self.right_dl
+            .set_delay_samples_fractional(self.sample_rate * timing.to_seconds());
+        self.timing_right = Some(timing);
+    }
+
+    /// Setter for the tempo, which re-derives the time of each line set by a timing.
+    /// Lines set by a time in seconds are left unchanged.
+    pub fn set_bpm(&mut self, bpm: i16) {
+        if let Some(mut timing) = self.timing_left.take() {
+            timing.set_bpm(bpm);
+            self.set_timing_left(timing);
+        }
+        if let Some(mut timing) = self.timing_right.take() {
+            timing.set_bpm(bpm);
+            self.set_timing_right(timing);
+        }
+    }
+
+    /// Follows the host's tempo from the transport, so lines set by a timing repeat in time with the song
+    /// rather than at the tempo they were set with. Lines set by a time in seconds are left unchanged.
+    pub fn align(&mut self, transport: &TransportSync) {
+        let bpm = transport.tempo().round() as i16;
+        let synced_bpm = self
+            .timing_left
+            .as_ref()
+            .or(self.timing_right.as_ref())
+            .map(|timing| timing.bpm());
+        match synced_bpm {
+            Some(synced_bpm) if synced_bpm != bpm => self.set_bpm(bpm),
+            _ => {}
+        }
     }
 
     /// Sets the saturation factor as a fraction of the sample maximum (i16::MAX)
     pub fn set_saturation_factor(&mut self, factor: f32) {
         self.saturator
             .set_threshold(i16::MAX as f32 / (2.0 * factor));
+        self.sync_oversampled_saturators();
     }
 
     pub fn set_saturation_mix(&mut self, mix: f32) {
         self.saturator.set_mix_ratio(mix.clamp(0.0, 1.0));
+        self.sync_oversampled_saturators();
+    }
+
+    /// Builds a (left, right) pair of oversampled saturators at a factor.
+    /// Both factors are built with the delay, so changing quality never allocates on the audio thread
+    fn build_oversampled(
+        saturator: &Saturator,
+        factor: OversampleFactor,
+        sample_rate: f32,
+    ) -> [Oversampled<Saturator>; 2] {
+        [
+            Oversampled::new(saturator.clone(), factor, sample_rate),
+            Oversampled::new(saturator.clone(), factor, sample_rate),
+        ]
+    }
+
+    /// Setter for the quality level, which decides how much the saturator is oversampled by.
+    /// Switches between the oversampled saturators built with the delay, clearing the history of the one
+    /// switched to, so it can be called every block without allocating.
+    pub fn set_quality(&mut self, quality: Quality) {
+        if quality == self.quality {
+            return;
+        }
+        self.quality = quality;
+        let oversampled = match quality.oversample_factor() {
+            Some(OversampleFactor::X2) => self.oversampled_x2.iter_mut(),
+            Some(OversampleFactor::X4) => self.oversampled_x4.iter_mut(),
+            None => return,
+        };
+        for saturator in oversampled {
+            saturator.reset();
+        }
+    }
+
+    /// Copies the saturator settings into every oversampled saturator
+    fn sync_oversampled_saturators(&mut self) {
+        for saturator in self
+            .oversampled_x2
+            .iter_mut()
+            .chain(self.oversampled_x4.iter_mut())
+        {
+            *saturator.processor_mut() = self.saturator.clone();
+        }
     }
 
     pub fn set_filter_cutoff(&mut self, cutoff_freq: f32) {
-        self.left_dl.filter.set_cutoff(cutoff_freq, 44100.0);
-        self.right_dl.filter.set_cutoff(cutoff_freq, 44100.0);
+        self.left_dl
+            .filter
+            .set_cutoff(cutoff_freq, self.sample_rate);
+        self.right_dl
+            .filter
+            .set_cutoff(cutoff_freq, self.sample_rate);
     }
 
     pub fn set_mix(&mut self, mix: f32) {
@@ -236,6 +490,37 @@ impl StereoDelay {
         self.right_dl.set_internal_feedback(clamped);
     }
 
+    /// Setter for the cross feedback, from 0 where each line feeds back into itself,
+    /// to 1 where each line feeds back into the other, bouncing repeats between the sides.
+    ///
+    /// Each line's feedback is a blend of the two, whose gains always sum to 1,
+    /// so the repeats never grow louder than the feedback setting allows at any amount.
+    pub fn set_cross_feedback(&mut self, amount: f32) {
+        self.cross_feedback = amount.clamp(0.0, 1.0);
+    }
+
+    /// Setter for how much the feedback is ducked while there is input, from 0 (disabled) to 1 (no feedback while
+    /// playing loudly). The feedback recovers over around 50ms once the input stops, so the repeats bloom in the gaps.
+    pub fn set_feedback_ducking(&mut self, amount: f32) {
+        self.ducking = amount.clamp(0.0, 1.0);
+        if self.ducking == 0.0 {
+            self.left_dl.set_feedback_duck(1.0);
+            self.right_dl.set_feedback_duck(1.0);
+        }
+    }
+
+    /// Clears the history of both feedback filters, such as when the transport stops
+    pub fn reset_filters(&mut self) {
+        self.left_dl.reset_filter();
+        self.right_dl.reset_filter();
+    }
+
+    /// Silences both delay lines, so old echoes don't reappear when playback restarts
+    pub fn clear(&mut self) {
+        self.left_dl.clear();
+        self.right_dl.clear();
+    }
+
     /// Getter for the delay times as a pair, to avoid repeating the get time function for both delay lines
     pub fn get_times(&self) -> (f32, f32) {
         (
@@ -244,3 +529,330 @@ impl StereoDelay {
         )
     }
 }
+
+/// An enum for the ways a `DelayBank` can route audio through its delays
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum BankMode {
+    #[default]
+    /// The output of each delay is fed into the input of the next
+    Serial,
+    /// Every delay receives the same input and their outputs are summed
+    Parallel,
+}
+
+/// A struct holding multiple stereo delays which are processed together, used to create multi-tap rhythmic delays.
+/// # Attributes
+/// * `delays`: The vector of `StereoDelay` objects, each with their own time, feedback and mix
+/// * `mode`: The `BankMode` deciding whether the delays are cascaded or summed
+/// * `do_filtering`: Whether each delay filters its feedback loop
+/// * `saturate`: Whether each delay saturates its input
+pub struct DelayBank {
+    delays: Vec<StereoDelay>,
+    mode: BankMode,
+    do_filtering: bool,
+    saturate: bool,
+}
+
+impl DelayBank {
+    /// Constructor for a delay bank given a vector of delays and the routing mode.
+    /// Filtering and saturation are disabled by default.
+    pub fn new(delays: Vec<StereoDelay>, mode: BankMode) -> Self {
+        Self {
+            delays,
+            mode,
+            do_filtering: false,
+            saturate: false,
+        }
+    }
+
+    /// Add another delay to the end of the bank
+    pub fn push(&mut self, delay: StereoDelay) {
+        self.delays.push(delay);
+    }
+
+    /// Get a mutable reference to the delay at an index, used to set its parameters independently
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut StereoDelay> {
+        self.delays.get_mut(index)
+    }
+
+    /// Setter for the routing mode of the bank
+    pub fn set_mode(&mut self, mode: BankMode) {
+        self.mode = mode;
+    }
+
+    /// Setter for toggling the feedback filter of every delay
+    pub fn set_filtering(&mut self, on_off: bool) {
+        self.do_filtering = on_off;
+    }
+
+    /// Setter for toggling the saturation of every delay
+    pub fn set_saturation(&mut self, on_off: bool) {
+        self.saturate = on_off;
+    }
+
+    /// Returns a tuple of samples (left, right) which have been processed through all the delays in the bank.
+    ///
+    /// In parallel mode the outputs are summed without any normalization, so each delay's dry signal is summed too.
+    /// Fully wet delays give a bank whose taps each keep their own level.
+    pub fn process(&mut self, in_sample_l: f32, in_sample_r: f32) -> (f32, f32) {
+        if self.delays.is_empty() {
+            return (in_sample_l, in_sample_r);
+        }
+
+        match self.mode {
+            BankMode::Serial => {
+                let mut out = (in_sample_l, in_sample_r);
+                for delay in self.delays.iter_mut() {
+                    out = delay.process(out.0, out.1, self.do_filtering, self.saturate);
+                }
+                out
+            }
+            BankMode::Parallel => {
+                let mut sum_l = 0.0;
+                let mut sum_r = 0.0;
+                for delay in self.delays.iter_mut() {
+                    let (l, r) =
+                        delay.process(in_sample_l, in_sample_r, self.do_filtering, self.saturate);
+                    sum_l += l;
+                    sum_r += r;
+                }
+                (sum_l, sum_r)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delay_line::{BankMode, DelayBank, DelayLine, StereoDelay};
+    use crate::timing::{NoteModifier, TimeDiv, Timing, TransportSync};
+    use test_case::test_case;
+
+    #[test]
+    fn test_dry_compensation() {
+        // the wet signal arrives one sample after the delay time, as the buffer is read before it is written
+        let mut delay_line = DelayLine::new(1000, 100, 0.0, 0.5);
+        delay_line.set_dry_compensation_samples(101);
+
+        let output: Vec<f32> = (0..500)
+            .map(|n| {
+                let impulse = match n {
+                    0 => 1.0,
+                    _ => 0.0,
+                };
+                delay_line.process_with_feedback(impulse, false).0
+            })
+            .collect();
+
+        // dry and wet line up into a single peak at full level
+        assert_eq!(output[101], 1.0);
+        assert_eq!(output.iter().filter(|sample| **sample != 0.0).count(), 1);
+    }
+
+    #[test]
+    fn test_parallel_bank() {
+        let mut bank = DelayBank::new(
+            vec![
+                StereoDelay::new(44100.0, 0.1, 0.1, 0.0, 1.0),
+                StereoDelay::new(44100.0, 0.25, 0.25, 0.0, 1.0),
+            ],
+            BankMode::Parallel,
+        );
+
+        let mut out: Vec<f32> = Vec::new();
+        for index in 0..(44100 / 2) {
+            let xn = match index {
+                0 => 1.0,
+                _ => 0.0,
+            };
+            out.push(bank.process(xn, xn).0);
+        }
+
+        // a delay of N samples outputs the impulse N + 1 samples later, as the read happens before the write
+        let echoes: Vec<usize> = (0..out.len()).filter(|index| out[*index] != 0.0).collect();
+        assert_eq!(echoes, vec![4411, 11026]);
+        assert_eq!(out[4411], 1.0);
+        assert_eq!(out[11026], 1.0);
+    }
+
+    #[test]
+    fn test_fractional_time() {
+        let mut delay = StereoDelay::new(44100.0, 0.1, 0.1, 0.0, 1.0);
+        // 100.25 samples on the left, a whole 100 samples on the right
+        delay.set_time_left(100.25 / 44100.0);
+        delay.set_time_right(100.0 / 44100.0);
+
+        let out: Vec<(f32, f32)> = (0..200)
+            .map(|n| match n {
+                0 => delay.process(1.0, 1.0, false, false),
+                _ => delay.process(0.0, 0.0, false, false),
+            })
+            .collect();
+
+        // the left impulse is split between the samples either side of the delay time
+        assert!((out[101].0 - 0.75).abs() < 1e-3, "{}", out[101].0);
+        assert!((out[102].0 - 0.25).abs() < 1e-3, "{}", out[102].0);
+        assert_eq!(out[101].1, 1.0);
+        assert_eq!(out.iter().filter(|(_, right)| *right != 0.0).count(), 1);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut delay = StereoDelay::new(44100.0, 0.1, 0.1, 0.5, 1.0);
+        delay.process(1.0, 1.0, true, false);
+        delay.clear();
+
+        for _ in 0..44100 {
+            assert_eq!(delay.process(0.0, 0.0, true, false), (0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_cross_feedback() {
+        // an impulse on the left only, returning the (left, right) output at each repeat
+        let repeats = |cross: f32| -> Vec<(f32, f32)> {
+            let mut delay = StereoDelay::new(44100.0, 0.1, 0.1, 0.8, 1.0);
+            delay.set_cross_feedback(cross);
+            let out: Vec<(f32, f32)> = (0..44100)
+                .map(|n| match n {
+                    0 => delay.process(1.0, 0.0, false, false),
+                    _ => delay.process(0.0, 0.0, false, false),
+                })
+                .collect();
+            (1..=4).map(|repeat| out[repeat * 4411]).collect()
+        };
+
+        // independent lines keep the repeats on the left
+        for (left, right) in repeats(0.0) {
+            assert!(left > 0.0 && right == 0.0);
+        }
+
+        // full cross routing bounces each repeat to the other side
+        let ping_pong = repeats(1.0);
+        assert_eq!(ping_pong[0], (1.0, 0.0));
+        assert!(ping_pong[1].0 == 0.0 && (ping_pong[1].1 - 0.8).abs() < 1e-6);
+        assert!((ping_pong[2].0 - 0.64).abs() < 1e-6 && ping_pong[2].1 == 0.0);
+
+        // a partial amount spreads the repeats without the total growing past the feedback gain
+        let mut previous = 1.0;
+        for (left, right) in repeats(0.5).iter().skip(1) {
+            assert!((left + right - 0.8 * previous).abs() < 1e-5);
+            previous = left + right;
+        }
+    }
+
+    #[test_case(0.0, (0.25, 0.25) ; "mono")]
+    #[test_case(1.0, (0.5, 0.0) ; "unchanged")]
+    #[test_case(2.0, (0.75, -0.25) ; "wide")]
+    fn test_width(width: f32, expected_echo: (f32, f32)) {
+        let delay_samples = 4410;
+        let mut delay = StereoDelay::new(44100.0, 0.1, 0.1, 0.0, 0.5);
+        delay.set_width(width);
+
+        // the echo arrives one sample after the delay time, as the buffer is read before it is written
+        let outputs: Vec<(f32, f32)> = (0..=delay_samples + 1)
+            .map(|n| match n {
+                0 => delay.process(1.0, 0.0, false, false),
+                _ => delay.process(0.0, 0.0, false, false),
+            })
+            .collect();
+
+        // the dry impulse passes through on the left only, whatever the width
+        assert_eq!(outputs[0], (0.5, 0.0));
+        let (left, right) = outputs[delay_samples + 1];
+        assert!((left - expected_echo.0).abs() < 1e-6, "left echo {left}");
+        assert!((right - expected_echo.1).abs() < 1e-6, "right echo {right}");
+    }
+
+    #[test]
+    fn test_tempo_sync_setters() {
+        let mut delay = StereoDelay::new_sync(
+            44100.0,
+            Timing::new(TimeDiv::Quarter, 120, NoteModifier::Regular),
+            Timing::new(TimeDiv::Eighth, 120, NoteModifier::Dotted),
+            0.5,
+            0.5,
+        );
+        let assert_times = |delay: &StereoDelay, left: f32, right: f32| {
+            let (actual_left, actual_right) = delay.get_times();
+            assert!((actual_left - left).abs() < 1e-3, "left time {actual_left}");
+            assert!(
+                (actual_right - right).abs() < 1e-3,
+                "right time {actual_right}"
+            );
+        };
+        assert_times(&delay, 0.5, 0.375);
+
+        // halving the tempo doubles both times
+        delay.set_bpm(60);
+        assert_times(&delay, 1.0, 0.75);
+
+        delay.set_timing_left(Timing::new(TimeDiv::Half, 60, NoteModifier::Regular));
+        delay.set_time_right(0.2);
+        assert_times(&delay, 2.0, 0.2);
+
+        // a time set in seconds doesn't follow the tempo
+        delay.set_bpm(120);
+        assert_times(&delay, 1.0, 0.2);
+    }
+
+    #[test]
+    fn test_align_to_transport() {
+        let mut transport = TransportSync::new(44100.0);
+        transport.set_position(0.0, 90.0, true);
+
+        let mut synced = StereoDelay::new_sync(
+            44100.0,
+            Timing::new(TimeDiv::Quarter, 120, NoteModifier::Regular),
+            Timing::new(TimeDiv::Eighth, 120, NoteModifier::Regular),
+            0.5,
+            0.5,
+        );
+        synced.align(&transport);
+        // both lines follow the host's tempo
+        let (left, right) = synced.get_times();
+        assert!((left - 2.0 / 3.0).abs() < 1e-3, "left time {left}");
+        assert!((right - 1.0 / 3.0).abs() < 1e-3, "right time {right}");
+
+        // a delay set in seconds keeps its times
+        let mut free = StereoDelay::new(44100.0, 0.2, 0.3, 0.5, 0.5);
+        free.align(&transport);
+        let (left, right) = free.get_times();
+        assert!((left - 0.2).abs() < 1e-3 && (right - 0.3).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_feedback_ducking() {
+        // one second of steady input, then a second of silence, returning the wet output
+        let run = |ducking: f32| -> Vec<f32> {
+            let mut delay = StereoDelay::new(44100.0, 0.25, 0.25, 0.8, 1.0);
+            delay.set_feedback_ducking(ducking);
+            (0..88200)
+                .map(|n| {
+                    let xn = match n < 44100 {
+                        true => 0.5,
+                        false => 0.0,
+                    };
+                    delay.process(xn, xn, false, false).0
+                })
+                .collect()
+        };
+        let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+
+        // without ducking the repeats pile up on top of the input
+        let plain = run(0.0);
+        assert!(peak(&plain[22050..44100]) > 1.2);
+
+        // with ducking only the first repeat of the input is heard while playing
+        let ducked = run(1.0);
+        assert!(peak(&ducked[22050..44100]) < 0.51);
+        // then the feedback recovers and the repeats carry on through the silence
+        let first_repeat = peak(&ducked[44100..55125]);
+        let second_repeat = peak(&ducked[55126..66150]);
+        assert!(
+            second_repeat > 0.3,
+            "second repeat peaked at {second_repeat}"
+        );
+        assert!(second_repeat < first_repeat);
+    }
+}
@@ -1,6 +1,44 @@
 //! A module implementing an ADSR envelope and its associated functions.
+//! Also contains an EnvelopeFollower, which tracks the level of a signal rather than generating one.
 use fast_math::exp;
 
+/// Curve parameters closer to 0 than this give a linear stage, as the exponential curve equation divides 0 by 0 at 0
+const LINEAR_CURVE_THRESHOLD: f32 = 1e-3;
+
+/// The shape of an envelope stage, rising from 0 to 1 as `progress` goes from 0 to 1.
+/// Uses the curve equation (e^(curve * progress) - 1) / (e^curve - 1), or a straight line when the curve is near 0.
+fn curve_shape(curve: f32, progress: f32) -> f32 {
+    match curve.abs() < LINEAR_CURVE_THRESHOLD {
+        true => progress,
+        false => (exp(curve * progress) - 1.0) / (exp(curve) - 1.0),
+    }
+}
+
+/// An enum of the behaviours of the envelope when the gate is turned on while it is already on
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum RetriggerMode {
+    #[default]
+    /// Every gate on restarts the attack stage from the beginning
+    Reset,
+    /// A gate on while the gate is already on is ignored, so the envelope continues from its current level
+    Legato,
+}
+
+/// An enum of the stages the envelope can be in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EnvStage {
+    /// The gate is off and the release has finished, or the envelope has never been triggered
+    Idle,
+    /// The envelope is rising towards its peak
+    Attack,
+    /// The envelope is falling from its peak towards the sustain level
+    Decay,
+    /// The gate is held and the envelope is at the sustain level
+    Sustain,
+    /// The gate is off and the envelope is falling towards 0
+    Release,
+}
+
 /// A 4-stage Attack-Decay-Sustain-Release envelope, triggered by gate
 /// # Attributes
 /// * `current_index`: The current index being used to access the discrete samples of either AD or R stages
@@ -28,6 +66,14 @@ use fast_math::exp;
 /// * `finished_ad_stage`: A boolean determining how the interrupt behaviour should work, if the attack and decay stage finished
 ///
 /// * `gate`: A gate boolean, used for triggering and sustain
+///
+/// * `retrigger_mode`: Decides whether turning the gate on while it is already on restarts the attack
+///
+/// * `looping`: When true, the attack and decay stages repeat for as long as the gate is held instead of sustaining
+///
+/// * `sample_rate`: The sample rate in Hz used to convert the stage times into samples
+///
+/// * `velocity`: A scalar between 0 and 1 applied to the whole contour, set when the gate is turned on
 pub struct ADSREnvelope {
     current_index: usize,
     last_value: f32,
@@ -42,13 +88,19 @@ pub struct ADSREnvelope {
     r_discrete: Vec<f32>,
     finished_ad_stage: bool,
     gate: bool,
+    retrigger_mode: RetriggerMode,
+    looping: bool,
+    sample_rate: f32,
+    velocity: f32,
 }
 
 impl ADSREnvelope {
     /// The constructor for the ADSR envelope,
     /// given an attack time, decay time, sustain level and release time,
     /// all in seconds, and the sustain level between 0 and 1.
+    /// The sample rate defaults to 44100 Hz.
     pub fn new(attack_time: f32, decay_time: f32, sustain_level: f32, release_time: f32) -> Self {
+        let sample_rate = 44100.0;
         Self {
             current_index: 0,
             last_value: 0.0,
@@ -59,10 +111,14 @@ impl ADSREnvelope {
             sustain_level,
             release_time,
             release_curve: 8.0,
-            ad_discrete: Vec::with_capacity((attack_time + decay_time) as usize * 44100),
-            r_discrete: Vec::with_capacity(release_time as usize * 44100),
+            ad_discrete: Vec::with_capacity(((attack_time + decay_time) * sample_rate) as usize),
+            r_discrete: Vec::with_capacity((release_time * sample_rate) as usize),
             finished_ad_stage: false,
             gate: false,
+            retrigger_mode: RetriggerMode::Reset,
+            looping: false,
+            sample_rate,
+            velocity: 1.0,
         }
     }
 
@@ -90,40 +146,68 @@ impl ADSREnvelope {
         self.setup();
     }
 
+    /// Setter for the curve of the attack stage. Values near 0 (including 0 itself) give a linear curve,
+    /// positive values an exponential curve and negative values a logarithmic curve
+    pub fn set_attack_curve(&mut self, curve: f32) {
+        self.attack_curve = curve;
+        self.setup_ad();
+    }
+
+    /// Setter for the curve of the decay stage, with the same effect as the attack curve
+    pub fn set_decay_curve(&mut self, curve: f32) {
+        self.decay_curve = curve;
+        self.setup_ad();
+    }
+
+    /// Setter for the curve of the release stage, with the same effect as the attack curve
+    pub fn set_release_curve(&mut self, curve: f32) {
+        self.release_curve = curve;
+        self.setup_release();
+    }
+
+    /// Setter for the sample rate in Hz, which rebuilds the discrete buffers so stage times are kept in seconds
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.setup();
+    }
+
     /// Populates the discrete function vectors based off the parameters and the equations.
     /// Fills AD and R discrete buffers.
     pub fn setup(&mut self) {
-        // populate attack buffer using equation (shown in doc)
-        for i in 0..((self.attack_time * 44100.0) as usize) {
-            let numerator_power = (self.attack_curve * (i as f32 / 44100.0)) / self.attack_time;
-            let denominator_power = self.attack_curve;
-            let numerator = (exp(numerator_power)) - 1.0;
-            let denominator = (exp(denominator_power)) - 1.0;
+        self.setup_ad();
+        self.setup_release();
+    }
 
-            self.ad_discrete.push(numerator / denominator)
+    /// Refills the AD discrete buffer from the attack and decay parameters
+    fn setup_ad(&mut self) {
+        self.ad_discrete.clear();
+
+        // populate attack buffer using equation (shown in doc)
+        for i in 0..((self.attack_time * self.sample_rate) as usize) {
+            let progress = (i as f32 / self.sample_rate) / self.attack_time;
+            self.ad_discrete
+                .push(curve_shape(self.attack_curve, progress))
         }
 
         // populate decay buffer using equation (shown in doc)
-        for i in 0..((self.decay_time * 44100.0) as usize) {
-            let numerator_power =
-                -1.0 * (self.decay_curve * (i as f32 / 44100.0)) / (self.decay_time);
-            let denominator_power = -1.0 * self.decay_curve;
-            let numerator = (exp(numerator_power)) - 1.0;
-            let denominator = (exp(denominator_power)) - 1.0;
+        for i in 0..((self.decay_time * self.sample_rate) as usize) {
+            let progress = (i as f32 / self.sample_rate) / self.decay_time;
             let scalar = self.sustain_level - 1.0;
             self.ad_discrete
-                .push(((numerator / denominator) * (scalar)) + 1.0)
+                .push((curve_shape(-1.0 * self.decay_curve, progress) * (scalar)) + 1.0)
         }
+    }
+
+    /// Refills the R discrete buffer from the release parameters
+    fn setup_release(&mut self) {
+        self.r_discrete.clear();
 
         // populate release buffer using equation (shown in doc)
-        for i in 0..((self.release_time * 44100.0) as usize) {
-            let numerator_power =
-                -1.0 * (self.release_curve * (i as f32 / 44100.0)) / (self.release_time);
-            let denominator_power = -1.0 * self.release_curve;
-            let numerator = (exp(numerator_power)) - 1.0;
-            let denominator = (exp(denominator_power)) - 1.0;
+        for i in 0..((self.release_time * self.sample_rate) as usize) {
+            let progress = (i as f32 / self.sample_rate) / self.release_time;
             self.r_discrete.push(
-                ((numerator / denominator) * -1.0 * (self.sustain_level)) + self.sustain_level,
+                (curve_shape(-1.0 * self.release_curve, progress) * -1.0 * (self.sustain_level))
+                    + self.sustain_level,
             )
         }
     }
@@ -136,8 +220,18 @@ impl ADSREnvelope {
         let value: f32;
 
         if self.gate {
+            // attack - decay finished while looping, restart the attack stage
+            if self.looping
+                && !self.ad_discrete.is_empty()
+                && self.current_index >= self.ad_discrete.len() - 1
+            {
+                self.current_index = 0;
+                value = self.ad_discrete[self.current_index];
+            }
             // attack - decay finished, give sustain sample
-            if self.ad_discrete.is_empty() || (self.current_index >= self.ad_discrete.len() - 1) {
+            else if self.ad_discrete.is_empty()
+                || (self.current_index >= self.ad_discrete.len() - 1)
+            {
                 self.finished_ad_stage = true;
                 value = self.sustain_level;
             }
@@ -152,7 +246,7 @@ impl ADSREnvelope {
             }
 
             // if release not finished, release sample
-            if self.ad_discrete.is_empty() || (self.current_index >= self.r_discrete.len() - 1) {
+            if self.r_discrete.is_empty() || (self.current_index >= self.r_discrete.len() - 1) {
                 value = 0.0;
             }
             // if release finished, 0.0
@@ -164,51 +258,323 @@ impl ADSREnvelope {
         // increment index (will be reset by sustain setter)
         self.current_index += 1;
         self.last_value = value;
-        value
+        value * self.velocity
     }
 
-    /// Trigger on or off the gate of the envelope, also resets the index.
+    /// Setter for the retrigger mode, deciding how a gate on is handled while the gate is already on
+    pub fn set_retrigger_mode(&mut self, mode: RetriggerMode) {
+        self.retrigger_mode = mode;
+    }
+
+    /// Setter for looping, when on the attack and decay stages cycle while the gate is held,
+    /// turning the envelope into a gate synced AD modulator
+    pub fn set_loop(&mut self, on: bool) {
+        self.looping = on;
+    }
+
+    /// Trigger on or off the gate of the envelope at full velocity, also resets the index.
+    ///
+    /// In legato mode, turning the gate on while it is already on does nothing.
     pub fn trigger_gate(&mut self, on_off: bool) {
+        self.trigger_gate_with_velocity(on_off, 1.0);
+    }
+
+    /// Trigger on or off the gate of the envelope, scaling the whole contour by a velocity between 0 and 1.
+    /// The velocity is only stored when the gate is turned on, so releases keep the velocity of their note.
+    ///
+    /// In legato mode, turning the gate on while it is already on does nothing.
+    pub fn trigger_gate_with_velocity(&mut self, on_off: bool, velocity: f32) {
+        if on_off && self.gate && self.retrigger_mode == RetriggerMode::Legato {
+            return;
+        }
         self.current_index = 0;
         if on_off {
             self.finished_ad_stage = false;
+            self.velocity = velocity.clamp(0.0, 1.0);
         }
         self.gate = on_off;
     }
 
-    /// A binary search algorithm that finds a value of similar amplitude (accuracy of MAX_DELTA) in the release buffer
+    /// Returns the stage the envelope is in, derived from the gate and the position in the discrete buffers
+    pub fn current_stage(&self) -> EnvStage {
+        let attack_samples = (self.attack_time * self.sample_rate) as usize;
+        match self.gate {
+            true => {
+                let reached_sustain = self.finished_ad_stage
+                    || (!self.looping && self.current_index + 1 >= self.ad_discrete.len());
+                match reached_sustain {
+                    true => EnvStage::Sustain,
+                    false if self.current_index < attack_samples => EnvStage::Attack,
+                    false => EnvStage::Decay,
+                }
+            }
+            false => {
+                // the gate has been on at some point if either stage was reached or a value was output
+                let triggered = self.finished_ad_stage || self.last_value > 0.0;
+                match triggered && self.current_index + 1 < self.r_discrete.len() {
+                    true => EnvStage::Release,
+                    false => EnvStage::Idle,
+                }
+            }
+        }
+    }
+
+    /// A binary search algorithm that finds the first index in the release buffer at or below the amplitude.
     /// Used to allow smooth interruption of Attack stage transition into Release if gate is released.
+    /// The search is bounded to MAX_ITERATIONS so it can never stall the audio thread.
     fn find_same_amp_release(&self, amplitude: f32) -> usize {
-        if amplitude > self.sustain_level {
+        if self.r_discrete.is_empty() || amplitude > self.sustain_level {
             return 0;
         }
 
+        const MAX_ITERATIONS: usize = 64;
         let mut lb: usize = 0;
-        let mut ub: usize = self.r_discrete.len();
-        const MAX_DELTA: f32 = 0.01;
-        let mut found_index = false;
-        let mut mid = (ub + lb) / 2;
-
-        while !found_index {
-            mid = (ub + lb) / 2;
-            if (self.r_discrete[mid] - amplitude).abs() <= MAX_DELTA {
-                found_index = true;
-            } else if self.r_discrete[mid] > amplitude {
-                lb = mid;
-            } else {
-                ub = mid
+        let mut ub: usize = self.r_discrete.len() - 1;
+        let mut iterations = 0;
+
+        // the release buffer is descending, so values above the amplitude lie before the match
+        while lb < ub && iterations < MAX_ITERATIONS {
+            let mid = (ub + lb) / 2;
+            match self.r_discrete[mid] > amplitude {
+                true => lb = mid + 1,
+                false => ub = mid,
             }
+            iterations += 1;
         }
-        mid
+        lb
+    }
+}
+
+/// An envelope follower, which tracks the level of a signal by smoothing its absolute value,
+/// rising with the attack time and falling with the release time.
+/// # Attributes
+/// * `attack_coeff`: The one-pole coefficient used while the level is rising
+///
+/// * `release_coeff`: The one-pole coefficient used while the level is falling
+///
+/// * `level`: The current level of the signal
+pub struct EnvelopeFollower {
+    attack_coeff: f32,
+    release_coeff: f32,
+    level: f32,
+}
+
+impl EnvelopeFollower {
+    /// Constructor for the follower, given its attack and release times in milliseconds and the sample rate in Hz
+    pub fn new(attack_ms: f32, release_ms: f32, sample_rate: f32) -> Self {
+        let coeff = |ms: f32| match ms > 0.0 {
+            true => (-1.0 / (ms * 0.001 * sample_rate)).exp(),
+            false => 0.0,
+        };
+        Self {
+            attack_coeff: coeff(attack_ms),
+            release_coeff: coeff(release_ms),
+            level: 0.0,
+        }
+    }
+
+    /// Takes the next input sample and returns the updated level
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let target = xn.abs();
+        let coeff = match target > self.level {
+            true => self.attack_coeff,
+            false => self.release_coeff,
+        };
+        self.level = target + coeff * (self.level - target);
+        self.level
+    }
+
+    /// Getter for the current level
+    pub fn level(&self) -> f32 {
+        self.level
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::envelope::ADSREnvelope;
+    use crate::envelope::{ADSREnvelope, EnvStage, EnvelopeFollower, RetriggerMode};
     use crate::samples::PhonicMode;
     use crate::write_wav;
 
+    /// Triggers an envelope, runs it partway through the attack, then triggers it again,
+    /// returning the (value before, value after) the second trigger
+    fn double_trigger(mode: RetriggerMode) -> (f32, f32) {
+        let mut env = ADSREnvelope::new(0.5, 0.5, 0.5, 0.5);
+        env.setup();
+        env.set_retrigger_mode(mode);
+
+        env.trigger_gate(true);
+        let mut before = 0.0;
+        for _ in 0..11025 {
+            before = env.get_next_sample();
+        }
+
+        env.trigger_gate(true);
+        (before, env.get_next_sample())
+    }
+
+    #[test]
+    fn test_legato_retrigger() {
+        let (before, after) = double_trigger(RetriggerMode::Legato);
+        assert!(before > 0.0);
+        assert!(after >= before);
+
+        let (before, after) = double_trigger(RetriggerMode::Reset);
+        assert!(before > 0.0);
+        assert_eq!(after, 0.0);
+    }
+
+    #[test]
+    fn test_release_matching() {
+        let mut env = ADSREnvelope::new(0.1, 0.1, 0.5, 0.1);
+        env.setup();
+        for amplitude in [0.5, 0.4, 0.25, 0.1, 0.01, 0.0] {
+            let index = env.find_same_amp_release(amplitude);
+            assert!((env.r_discrete[index] - amplitude).abs() < 0.01);
+        }
+
+        // a very short release jumps by more than the old tolerance between samples, which used to hang
+        env.set_release(0.0005);
+        let index = env.find_same_amp_release(0.3);
+        assert!(env.r_discrete[index] <= 0.3);
+        assert!(index == 0 || env.r_discrete[index - 1] > 0.3);
+
+        // an empty release buffer matches the start rather than panicking
+        env.set_release(0.0);
+        assert_eq!(env.find_same_amp_release(0.3), 0);
+    }
+
+    #[test]
+    fn test_velocity() {
+        let mut full = ADSREnvelope::new(0.1, 0.1, 0.5, 0.1);
+        let mut soft = ADSREnvelope::new(0.1, 0.1, 0.5, 0.1);
+        full.setup();
+        soft.setup();
+
+        full.trigger_gate(true);
+        soft.trigger_gate_with_velocity(true, 0.25);
+        for _ in 0..10000 {
+            assert_eq!(soft.get_next_sample(), full.get_next_sample() * 0.25);
+        }
+
+        // the release keeps the velocity of the note
+        full.trigger_gate(false);
+        soft.trigger_gate(false);
+        for _ in 0..4000 {
+            assert_eq!(soft.get_next_sample(), full.get_next_sample() * 0.25);
+        }
+    }
+
+    #[test]
+    fn test_current_stage() {
+        let mut env = ADSREnvelope::new(0.1, 0.1, 0.5, 0.1);
+        env.setup();
+        assert_eq!(env.current_stage(), EnvStage::Idle);
+
+        env.trigger_gate(true);
+        let mut stages = vec![env.current_stage()];
+        for _ in 0..10000 {
+            env.get_next_sample();
+            stages.push(env.current_stage());
+        }
+        assert_eq!(stages[4409], EnvStage::Attack);
+        assert_eq!(stages[4410], EnvStage::Decay);
+        assert_eq!(stages[8818], EnvStage::Decay);
+        assert_eq!(stages[8819], EnvStage::Sustain);
+        assert_eq!(stages[10000], EnvStage::Sustain);
+
+        env.trigger_gate(false);
+        assert_eq!(env.current_stage(), EnvStage::Release);
+        for _ in 0..4410 {
+            env.get_next_sample();
+        }
+        assert_eq!(env.current_stage(), EnvStage::Idle);
+    }
+
+    #[test]
+    fn test_curve_setters() {
+        let mut env = ADSREnvelope::new(1.0, 1.0, 0.5, 1.0);
+        env.setup();
+        let mut halfway_attack = |curve: f32| {
+            env.set_attack_curve(curve);
+            env.trigger_gate(true);
+            (0..22051).map(|_| env.get_next_sample()).last().unwrap()
+        };
+
+        // an exponential curve rises late, a logarithmic one early and a near linear one in between
+        let exponential = halfway_attack(5.0);
+        let linear = halfway_attack(0.001);
+        let logarithmic = halfway_attack(-5.0);
+        assert!(exponential < 0.1);
+        assert!((linear - 0.5).abs() < 0.01);
+        assert!(logarithmic > 0.9);
+
+        // a curve of exactly 0 is linear rather than dividing 0 by 0
+        let flat = halfway_attack(0.0);
+        assert!((flat - 0.5).abs() < 0.01);
+
+        // the same holds for the decay and release stages
+        env.set_decay_curve(0.0);
+        env.set_release_curve(0.0);
+        env.trigger_gate(true);
+        let attack_decay: Vec<f32> = (0..88200).map(|_| env.get_next_sample()).collect();
+        env.trigger_gate(false);
+        let release: Vec<f32> = (0..44100).map(|_| env.get_next_sample()).collect();
+        assert!(attack_decay
+            .iter()
+            .chain(release.iter())
+            .all(|x| x.is_finite()));
+        assert!((attack_decay[66150] - 0.75).abs() < 0.01);
+        assert!((release[22050] - 0.25).abs() < 0.01);
+
+        // changing the release curve leaves the attack untouched
+        env.set_attack_curve(-5.0);
+        env.set_release_curve(-5.0);
+        env.trigger_gate(true);
+        assert_eq!(
+            (0..22051).map(|_| env.get_next_sample()).last().unwrap(),
+            logarithmic
+        );
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut env = ADSREnvelope::new(0.5, 0.5, 0.5, 0.5);
+        env.setup();
+        env.set_sample_rate(48000.0);
+        env.trigger_gate(true);
+
+        // the attack peaks after half a second at the new rate
+        let values: Vec<f32> = (0..48000).map(|_| env.get_next_sample()).collect();
+        assert!(values[23999] > 0.99);
+        assert!(values[22050] < values[23999]);
+
+        // the decay has finished after one second, so the envelope is sustaining
+        assert_eq!(env.get_next_sample(), 0.5);
+    }
+
+    #[test]
+    fn test_looping_envelope() {
+        let mut env = ADSREnvelope::new(0.1, 0.1, 0.5, 0.1);
+        env.setup();
+        env.set_loop(true);
+        env.trigger_gate(true);
+
+        // the AD stage is 8820 samples long, the last of which is where the cycle restarts
+        let first: Vec<f32> = (0..8819).map(|_| env.get_next_sample()).collect();
+        let second: Vec<f32> = (0..8819).map(|_| env.get_next_sample()).collect();
+        assert!(second[0] < 0.01);
+        assert!(second[4410] > 0.99);
+        assert_eq!(first[..100], second[..100]);
+
+        // once looping is turned off the envelope settles at the sustain level
+        env.set_loop(false);
+        for _ in 0..8820 {
+            env.get_next_sample();
+        }
+        assert_eq!(env.get_next_sample(), 0.5);
+    }
+
     #[test]
     #[ignore]
     fn gen_env_example() {
@@ -235,4 +601,24 @@ mod tests {
 
         write_wav("tests/debug/env_adsr_2.wav", out, PhonicMode::Mono)
     }
+
+    #[test]
+    fn test_envelope_follower() {
+        let mut follower = EnvelopeFollower::new(1.0, 100.0, 44100.0);
+        // a full scale square wave is followed up quickly, without dropping between its edges
+        for n in 0..2205 {
+            let xn = match (n / 50) % 2 {
+                0 => 1.0,
+                _ => -1.0,
+            };
+            follower.process(xn);
+        }
+        assert!(follower.level() > 0.99);
+
+        // after one release time of silence it has fallen by around 63%
+        for _ in 0..4410 {
+            follower.process(0.0);
+        }
+        assert!((follower.level() - (-1.0_f32).exp()).abs() < 0.01);
+    }
 }
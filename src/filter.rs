@@ -1,9 +1,18 @@
 #![allow(dead_code)]
 #![warn(missing_docs)]
 //! Implementing a first order filter with transfer function H(S) = w_0 / s + w_0
+//! and its complementary first order highpass with H(S) = s / s + w_0
+//! Both take their cutoff in Hz, prewarped so that the digital filters are -3dB at exactly that frequency
 //! x, y and a0 ... are used due to their correspondence with difference equations
+//! ShelfFilter is a second order (biquad) shelving EQ using the RBJ audio EQ cookbook coefficients
+//! AntiAliasFilter is a steep lowpass made from a cascade of biquads, used when changing sample rate
+//! BiquadFilter is a resonant second order lowpass, highpass or bandpass filter
+//! AllpassFilter is a Schroeder allpass, the building block of diffusers and phasers
 
-#[derive(Debug)]
+use crate::delay_buffer::DelayBuffer;
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy)]
 /// The coefficients of a first order filter where a0 is normalized to 1
 pub struct LPCoefficients {
     a1: f32,
@@ -11,15 +20,43 @@ pub struct LPCoefficients {
     b1: f32,
 }
 
+/// The cutoff as an angular frequency multiplied by the sample period, for the bilinear transform.
+/// The cutoff is prewarped so that a first order digital filter is -3dB at exactly the cutoff frequency,
+/// and limited to just below Nyquist where the prewarping is undefined.
+fn warped_cutoff(cutoff_freq: f32, sample_rate: f32) -> f32 {
+    let cutoff_freq = cutoff_freq.clamp(0.0, 0.49 * sample_rate);
+    2.0 * (PI * cutoff_freq / sample_rate).tan()
+}
+
+/// Returns the log spaced frequencies from 20Hz to Nyquist paired with a filter's magnitude response at each
+fn response_curve(
+    points: usize,
+    sample_rate: f32,
+    magnitude_response: impl Fn(f32) -> f32,
+) -> Vec<(f32, f32)> {
+    let lowest: f32 = 20.0;
+    let highest = sample_rate * 0.5;
+    (0..points)
+        .map(|point| {
+            let fraction = match points {
+                1 => 0.0,
+                _ => point as f32 / (points - 1) as f32,
+            };
+            let freq = lowest * (highest / lowest).powf(fraction);
+            (freq, magnitude_response(freq))
+        })
+        .collect()
+}
+
 impl LPCoefficients {
-    /// A function that generates coefficients given cutoff frequency and sample rate
+    /// A function that generates coefficients given the cutoff frequency in Hz and sample rate
     pub fn new(cutoff_freq: f32, sample_rate: f32) -> Self {
-        let dt: f32 = 1.0 / sample_rate;
-        let a0 = (cutoff_freq * dt) + 2.0;
+        let wc_dt = warped_cutoff(cutoff_freq, sample_rate);
+        let a0 = wc_dt + 2.0;
         Self {
-            a1: (2.0 - cutoff_freq * dt) / a0,
-            b0: (cutoff_freq * dt) / a0,
-            b1: (cutoff_freq * dt) / a0,
+            a1: (2.0 - wc_dt) / a0,
+            b0: wc_dt / a0,
+            b1: wc_dt / a0,
         }
     }
 
@@ -30,21 +67,52 @@ impl LPCoefficients {
 
     /// Recompute the filter coefficients based on a change in cutoff frequency and or sample rate
     pub fn recompute(&mut self, cutoff_freq: f32, sample_rate: f32) {
-        let dt: f32 = 1.0 / sample_rate;
-        let a0: f32 = (cutoff_freq * dt) + 2.0;
-        self.a1 = (2.0 - cutoff_freq * dt) / a0;
-        self.b0 = (cutoff_freq * dt) / a0;
-        self.b1 = (cutoff_freq * dt) / a0;
+        *self = Self::new(cutoff_freq, sample_rate);
+    }
+
+    /// The magnitude of the filter's frequency response |H(e^jw)| at a frequency in Hz, where 1 is unity gain.
+    /// Evaluated from the stored coefficients, so the gain at a frequency can be checked without processing a signal
+    pub fn magnitude_at(&self, freq: f32, sample_rate: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate;
+        // H(z) = (b0 + b1 z^-1) / (1 - a1 z^-1), evaluated at z = e^jw
+        let numerator = (self.b0 + self.b1 * w.cos()).hypot(self.b1 * w.sin());
+        let denominator = (1.0 - self.a1 * w.cos()).hypot(self.a1 * w.sin());
+        numerator / denominator
+    }
+
+    /// The magnitude of the filter's frequency response at a frequency in Hz, named to match the other filters.
+    /// Equivalent to `magnitude_at`
+    pub fn magnitude_response(&self, freq: f32, sample_rate: f32) -> f32 {
+        self.magnitude_at(freq, sample_rate)
+    }
+
+    /// Move the coefficients linearly toward a target set, such that they arrive after `steps` calls
+    pub fn step_toward(&mut self, target: &LPCoefficients, steps: usize) {
+        let fraction = 1.0 / steps.max(1) as f32;
+        self.a1 += (target.a1 - self.a1) * fraction;
+        self.b0 += (target.b0 - self.b0) * fraction;
+        self.b1 += (target.b1 - self.b1) * fraction;
     }
 }
 
 #[derive(Debug)]
 /// A struct used to process input signals through a first order lowpass filter
+/// # Attributes
+/// * `x`: A circular buffer of previous inputs
+/// * `y`: A circular buffer of previous outputs
+/// * `n`: The current index into the buffers
+/// * `coeffs`: The coefficients currently used by the difference equation
+/// * `target_coeffs`: The coefficients being smoothed toward after a call to `set_cutoff_smooth`
+/// * `smooth_steps_remaining`: The number of samples left until `coeffs` reaches `target_coeffs`
+/// * `smooth_time_ms`: The time taken to move to a new cutoff when smoothing, in milliseconds
 pub struct LowpassFilter {
     x: Vec<f32>,
     y: Vec<f32>,
     n: usize,
     coeffs: LPCoefficients,
+    target_coeffs: LPCoefficients,
+    smooth_steps_remaining: usize,
+    smooth_time_ms: f32,
 }
 
 impl LowpassFilter {
@@ -55,6 +123,9 @@ impl LowpassFilter {
             y: vec![0.0; capacity],
             n: 1,
             coeffs: LPCoefficients::new(cutoff_freq, sample_rate),
+            target_coeffs: LPCoefficients::new(cutoff_freq, sample_rate),
+            smooth_steps_remaining: 0,
+            smooth_time_ms: 5.0,
         }
     }
 
@@ -68,6 +139,12 @@ impl LowpassFilter {
         // increase the index (with wrapping)
         self.advance();
 
+        if self.smooth_steps_remaining > 0 {
+            self.coeffs
+                .step_toward(&self.target_coeffs, self.smooth_steps_remaining);
+            self.smooth_steps_remaining -= 1;
+        }
+
         // assigning to local variables to shorten expressions
         let (a1, b0, b1) = self.coeffs.get_coeffs();
         let n = self.n;
@@ -85,15 +162,656 @@ impl LowpassFilter {
 
     /// Setter for filter cutoff frequency. Wrapper for recompute coefficients
     pub fn set_cutoff(&mut self, cutoff_freq: f32, sample_rate: f32) {
-        self.coeffs.recompute(cutoff_freq, sample_rate)
+        self.coeffs.recompute(cutoff_freq, sample_rate);
+        self.target_coeffs = self.coeffs;
+        self.smooth_steps_remaining = 0;
+    }
+
+    /// Setter for filter cutoff frequency which interpolates the coefficients toward the new cutoff
+    /// over the smoothing time, avoiding zipper noise when the cutoff is automated.
+    pub fn set_cutoff_smooth(&mut self, target: f32, sample_rate: f32) {
+        self.target_coeffs.recompute(target, sample_rate);
+        self.smooth_steps_remaining = ((self.smooth_time_ms * 0.001 * sample_rate) as usize).max(1);
+    }
+
+    /// Clears the input and output history without reallocating, so that a new signal starts from silence
+    pub fn reset(&mut self) {
+        self.x.fill(0.0);
+        self.y.fill(0.0);
+        self.n = 1;
+    }
+
+    /// Setter for the time taken to reach a new cutoff when using `set_cutoff_smooth`, in milliseconds
+    pub fn set_smooth_time(&mut self, smooth_time_ms: f32) {
+        self.smooth_time_ms = smooth_time_ms.max(0.0);
+    }
+
+    /// The magnitude of the filter's frequency response at a frequency in Hz, for drawing the filter curve
+    pub fn magnitude_response(&self, freq: f32, sample_rate: f32) -> f32 {
+        self.coeffs.magnitude_response(freq, sample_rate)
+    }
+
+    /// The magnitude response at `points` log spaced frequencies from 20Hz to Nyquist, as (frequency, magnitude) pairs
+    pub fn magnitude_response_curve(&self, points: usize, sample_rate: f32) -> Vec<(f32, f32)> {
+        response_curve(points, sample_rate, |freq| {
+            self.magnitude_response(freq, sample_rate)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The coefficients of a first order highpass filter where a0 is normalized to 1
+pub struct HPCoefficients {
+    a1: f32,
+    b0: f32,
+    b1: f32,
+}
+
+impl HPCoefficients {
+    /// A function that generates coefficients given the cutoff frequency in Hz and sample rate
+    pub fn new(cutoff_freq: f32, sample_rate: f32) -> Self {
+        let wc_dt = warped_cutoff(cutoff_freq, sample_rate);
+        let a0 = wc_dt + 2.0;
+        Self {
+            a1: (2.0 - wc_dt) / a0,
+            b0: 2.0 / a0,
+            b1: -2.0 / a0,
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn get_coeffs(&self) -> (f32, f32, f32) {
+        (self.a1, self.b0, self.b1)
+    }
+
+    /// Recompute the filter coefficients based on a change in cutoff frequency and or sample rate
+    pub fn recompute(&mut self, cutoff_freq: f32, sample_rate: f32) {
+        *self = Self::new(cutoff_freq, sample_rate);
+    }
+
+    /// The magnitude of the filter's frequency response |H(e^jw)| at a frequency in Hz, where 1 is unity gain
+    pub fn magnitude_response(&self, freq: f32, sample_rate: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate;
+        // H(z) = (b0 + b1 z^-1) / (1 - a1 z^-1), evaluated at z = e^jw
+        let numerator = (self.b0 + self.b1 * w.cos()).hypot(self.b1 * w.sin());
+        let denominator = (1.0 - self.a1 * w.cos()).hypot(self.a1 * w.sin());
+        numerator / denominator
+    }
+}
+
+#[derive(Debug)]
+/// A struct used to process input signals through a first order highpass filter, such as to remove low rumble
+/// # Attributes
+/// * `x`: A circular buffer of previous inputs
+/// * `y`: A circular buffer of previous outputs
+/// * `n`: The current index into the buffers
+/// * `coeffs`: The coefficients used by the difference equation
+pub struct HighpassFilter {
+    x: Vec<f32>,
+    y: Vec<f32>,
+    n: usize,
+    coeffs: HPCoefficients,
+}
+
+impl HighpassFilter {
+    /// A constructor for a new highpass filter given buffer capacity, cutoff frequency and sample rate
+    pub fn new(cutoff_freq: f32, sample_rate: f32, capacity: usize) -> Self {
+        Self {
+            x: vec![0.0; capacity],
+            y: vec![0.0; capacity],
+            n: 1,
+            coeffs: HPCoefficients::new(cutoff_freq, sample_rate),
+        }
+    }
+
+    /// Function to move the index through the buffer with wrapping to form a circular buffer
+    fn advance(&mut self) {
+        self.n = (self.n + 1) % self.x.len();
+    }
+
+    /// A function to process a single input (given as f32) through the highpass filter
+    pub fn process(&mut self, xn: f32) -> f32 {
+        // increase the index (with wrapping)
+        self.advance();
+
+        // assigning to local variables to shorten expressions
+        let (a1, b0, b1) = self.coeffs.get_coeffs();
+        let n = self.n;
+
+        self.x[n] = xn;
+        match n {
+            n if n == 0 => {
+                self.y[n] =
+                    a1 * self.y.last().unwrap() + b0 * self.x[n] + b1 * self.x.last().unwrap()
+            }
+            _ => self.y[n] = a1 * self.y[n - 1] + b0 * self.x[n] + b1 * self.x[n - 1],
+        };
+        self.y[n]
+    }
+
+    /// Setter for filter cutoff frequency. Wrapper for recompute coefficients
+    pub fn set_cutoff(&mut self, cutoff_freq: f32, sample_rate: f32) {
+        self.coeffs.recompute(cutoff_freq, sample_rate);
+    }
+
+    /// The magnitude of the filter's frequency response at a frequency in Hz, for drawing the filter curve
+    pub fn magnitude_response(&self, freq: f32, sample_rate: f32) -> f32 {
+        self.coeffs.magnitude_response(freq, sample_rate)
+    }
+}
+
+/// The Q of a 2nd order Butterworth section, two of which in series make one band of a Linkwitz-Riley crossover
+const BUTTERWORTH_2_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// A two band 4th order Linkwitz-Riley crossover which splits a signal into low and high bands.
+///
+/// Each band is two identical Butterworth biquads in series, so both bands are 6dB down at the crossover frequency
+/// and roll off at 24dB/octave. The bands are in phase with each other, so their sum has a flat magnitude response
+/// (it is an allpass of the input rather than an exact copy).
+///
+/// # Attributes
+///
+/// * `lowpass`: The two lowpass sections making the low band
+/// * `highpass`: The two highpass sections making the high band
+#[derive(Debug)]
+pub struct Crossover {
+    lowpass: [BiquadFilter; 2],
+    highpass: [BiquadFilter; 2],
+}
+
+impl Crossover {
+    /// Constructor for a crossover given the crossover frequency and sample rate
+    pub fn new(crossover_freq: f32, sample_rate: f32) -> Self {
+        let section = |mode| BiquadFilter::new(crossover_freq, BUTTERWORTH_2_Q, sample_rate, mode);
+        Self {
+            lowpass: [section(BiquadMode::Lowpass), section(BiquadMode::Lowpass)],
+            highpass: [section(BiquadMode::Highpass), section(BiquadMode::Highpass)],
+        }
+    }
+
+    /// Setter for the crossover frequency, keeping the filter history
+    pub fn set_crossover(&mut self, crossover_freq: f32, sample_rate: f32) {
+        for section in self.lowpass.iter_mut().chain(self.highpass.iter_mut()) {
+            section.sample_rate = sample_rate;
+            section.set_cutoff(crossover_freq);
+        }
+    }
+
+    /// Splits a single input sample, returning the (low, high) bands
+    pub fn process(&mut self, xn: f32) -> (f32, f32) {
+        let low = self
+            .lowpass
+            .iter_mut()
+            .fold(xn, |sample, section| section.process(sample));
+        let high = self
+            .highpass
+            .iter_mut()
+            .fold(xn, |sample, section| section.process(sample));
+        (low, high)
+    }
+}
+
+/// The type of shelving filter, deciding which side of the corner frequency is boosted or cut
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShelfType {
+    /// Frequencies below the corner have the gain applied
+    LowShelf,
+    /// Frequencies above the corner have the gain applied
+    HighShelf,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// The coefficients of a second order (biquad) filter where a0 is normalized to 1
+pub struct BiquadCoefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl BiquadCoefficients {
+    /// A function that generates shelving coefficients from the RBJ cookbook, with a shelf slope of 1
+    /// # Parameters
+    /// * `shelf_type`: Whether the gain is applied below or above the corner frequency
+    /// * `corner_freq`: The corner frequency in Hz, where the gain is half applied (in dB)
+    /// * `gain_db`: The gain of the shelf in decibels, negative values cut
+    /// * `sample_rate`: The sample rate in Hz
+    pub fn shelf(shelf_type: ShelfType, corner_freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10.0_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * corner_freq / sample_rate;
+        let cos_w0 = w0.cos();
+        // alpha for a shelf slope of 1, the steepest slope without overshoot
+        let alpha = w0.sin() / 2.0 * 2.0_f32.sqrt();
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match shelf_type {
+            ShelfType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+            ShelfType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// A function that generates lowpass coefficients from the RBJ cookbook
+    /// # Parameters
+    /// * `cutoff_freq`: The cutoff frequency in Hz
+    /// * `q`: The quality factor, 0.707 gives a flat passband and higher values a resonant peak at the cutoff
+    /// * `sample_rate`: The sample rate in Hz
+    pub fn lowpass(cutoff_freq: f32, q: f32, sample_rate: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff_freq.clamp(0.0, 0.49 * sample_rate) / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 - cos_w0) / 2.0 / a0,
+            b1: (1.0 - cos_w0) / a0,
+            b2: (1.0 - cos_w0) / 2.0 / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// A function that generates highpass coefficients from the RBJ cookbook
+    /// # Parameters
+    /// * `cutoff_freq`: The cutoff frequency in Hz
+    /// * `q`: The quality factor, 0.707 gives a flat passband and higher values a resonant peak at the cutoff
+    /// * `sample_rate`: The sample rate in Hz
+    pub fn highpass(cutoff_freq: f32, q: f32, sample_rate: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff_freq.clamp(0.0, 0.49 * sample_rate) / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 + cos_w0) / 2.0 / a0,
+            b1: -(1.0 + cos_w0) / a0,
+            b2: (1.0 + cos_w0) / 2.0 / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// A function that generates bandpass coefficients from the RBJ cookbook, with a peak gain of 0dB
+    /// # Parameters
+    /// * `center_freq`: The center frequency of the band in Hz
+    /// * `q`: The quality factor, higher values give a narrower band
+    /// * `sample_rate`: The sample rate in Hz
+    pub fn bandpass(center_freq: f32, q: f32, sample_rate: f32) -> Self {
+        let w0 = 2.0 * PI * center_freq.clamp(0.0, 0.49 * sample_rate) / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: alpha / a0,
+            b1: 0.0,
+            b2: -alpha / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+
+    /// The group delay of the filter at 0Hz in samples, how long low frequencies take to pass through it
+    pub fn dc_group_delay(&self) -> f32 {
+        // for H(z) = B(z) / A(z), the delay at DC is the centroid of b minus the centroid of a
+        let b_delay = (self.b1 + 2.0 * self.b2) / (self.b0 + self.b1 + self.b2);
+        let a_delay = (self.a1 + 2.0 * self.a2) / (1.0 + self.a1 + self.a2);
+        b_delay - a_delay
+    }
+
+    #[allow(missing_docs)]
+    pub fn get_coeffs(&self) -> (f32, f32, f32, f32, f32) {
+        (self.b0, self.b1, self.b2, self.a1, self.a2)
+    }
+
+    /// The magnitude of the filter's frequency response |H(e^jw)| at a frequency in Hz, where 1 is unity gain
+    pub fn magnitude_response(&self, freq: f32, sample_rate: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate;
+        // H(z) = (b0 + b1 z^-1 + b2 z^-2) / (1 + a1 z^-1 + a2 z^-2), evaluated at z = e^jw
+        let numerator = (self.b0 + self.b1 * w.cos() + self.b2 * (2.0 * w).cos())
+            .hypot(self.b1 * w.sin() + self.b2 * (2.0 * w).sin());
+        let denominator = (1.0 + self.a1 * w.cos() + self.a2 * (2.0 * w).cos())
+            .hypot(self.a1 * w.sin() + self.a2 * (2.0 * w).sin());
+        numerator / denominator
+    }
+}
+
+/// A shelving EQ which boosts or cuts frequencies above or below a corner frequency, for tone shaping
+/// # Attributes
+/// * `shelf_type`: Whether the gain is applied below or above the corner frequency
+/// * `corner_freq`: The corner frequency in Hz
+/// * `gain_db`: The gain of the shelf in decibels
+/// * `sample_rate`: The sample rate in Hz
+/// * `coeffs`: The biquad coefficients for the current settings
+/// * `x`: The previous two inputs, most recent first
+/// * `y`: The previous two outputs, most recent first
+#[derive(Debug)]
+pub struct ShelfFilter {
+    shelf_type: ShelfType,
+    corner_freq: f32,
+    gain_db: f32,
+    sample_rate: f32,
+    coeffs: BiquadCoefficients,
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl ShelfFilter {
+    /// Constructor for a shelf filter given its type, corner frequency, gain in dB and sample rate
+    pub fn new(shelf_type: ShelfType, corner_freq: f32, gain_db: f32, sample_rate: f32) -> Self {
+        Self {
+            shelf_type,
+            corner_freq,
+            gain_db,
+            sample_rate,
+            coeffs: BiquadCoefficients::shelf(shelf_type, corner_freq, gain_db, sample_rate),
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// Recompute the coefficients after a change in settings
+    fn recompute(&mut self) {
+        self.coeffs = BiquadCoefficients::shelf(
+            self.shelf_type,
+            self.corner_freq,
+            self.gain_db,
+            self.sample_rate,
+        );
+    }
+
+    /// Setter for the shelf type
+    pub fn set_shelf_type(&mut self, shelf_type: ShelfType) {
+        self.shelf_type = shelf_type;
+        self.recompute();
+    }
+
+    /// Setter for the corner frequency in Hz
+    pub fn set_corner(&mut self, corner_freq: f32) {
+        self.corner_freq = corner_freq;
+        self.recompute();
+    }
+
+    /// Setter for the shelf gain in dB
+    pub fn set_gain_db(&mut self, gain_db: f32) {
+        self.gain_db = gain_db;
+        self.recompute();
+    }
+
+    /// The magnitude of the filter's frequency response at a frequency in Hz, for drawing the filter curve
+    pub fn magnitude_response(&self, freq: f32) -> f32 {
+        self.coeffs.magnitude_response(freq, self.sample_rate)
+    }
+
+    /// The magnitude response at `points` log spaced frequencies from 20Hz to Nyquist, as (frequency, magnitude) pairs
+    pub fn magnitude_response_curve(&self, points: usize) -> Vec<(f32, f32)> {
+        response_curve(points, self.sample_rate, |freq| {
+            self.magnitude_response(freq)
+        })
+    }
+
+    /// A function to process a single input (given as f32) through the shelf filter
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let (b0, b1, b2, a1, a2) = self.coeffs.get_coeffs();
+        let yn = b0 * xn + b1 * self.x[0] + b2 * self.x[1] - a1 * self.y[0] - a2 * self.y[1];
+
+        self.x = [xn, self.x[0]];
+        self.y = [yn, self.y[0]];
+        yn
+    }
+}
+
+/// The response types of a `BiquadFilter`
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum BiquadMode {
+    /// Passes frequencies below the cutoff
+    #[default]
+    Lowpass,
+    /// Passes frequencies above the cutoff
+    Highpass,
+    /// Passes a band of frequencies around the cutoff
+    Bandpass,
+}
+
+/// A resonant second order filter, for synth style sweeps with a steeper rolloff than `LowpassFilter`
+/// # Attributes
+/// * `mode`: Whether the filter is a lowpass, highpass or bandpass
+/// * `cutoff_freq`: The cutoff (or center, for bandpass) frequency in Hz
+/// * `q`: The quality factor, deciding the height of the resonant peak at the cutoff
+/// * `sample_rate`: The sample rate in Hz
+/// * `coeffs`: The biquad coefficients for the current settings
+/// * `x`: The previous two inputs, most recent first
+/// * `y`: The previous two outputs, most recent first
+#[derive(Debug)]
+pub struct BiquadFilter {
+    mode: BiquadMode,
+    cutoff_freq: f32,
+    q: f32,
+    sample_rate: f32,
+    coeffs: BiquadCoefficients,
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl BiquadFilter {
+    /// Constructor for a biquad filter given its cutoff frequency in Hz, Q, sample rate and mode
+    pub fn new(cutoff_freq: f32, q: f32, sample_rate: f32, mode: BiquadMode) -> Self {
+        let q = q.max(0.01);
+        Self {
+            mode,
+            cutoff_freq,
+            q,
+            sample_rate,
+            coeffs: Self::coefficients(mode, cutoff_freq, q, sample_rate),
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// The coefficients of a filter mode with the given settings
+    fn coefficients(
+        mode: BiquadMode,
+        cutoff_freq: f32,
+        q: f32,
+        sample_rate: f32,
+    ) -> BiquadCoefficients {
+        match mode {
+            BiquadMode::Lowpass => BiquadCoefficients::lowpass(cutoff_freq, q, sample_rate),
+            BiquadMode::Highpass => BiquadCoefficients::highpass(cutoff_freq, q, sample_rate),
+            BiquadMode::Bandpass => BiquadCoefficients::bandpass(cutoff_freq, q, sample_rate),
+        }
+    }
+
+    /// Recompute the coefficients after a change in settings
+    fn recompute(&mut self) {
+        self.coeffs = Self::coefficients(self.mode, self.cutoff_freq, self.q, self.sample_rate);
+    }
+
+    /// Setter for the cutoff frequency in Hz
+    pub fn set_cutoff(&mut self, cutoff_freq: f32) {
+        self.cutoff_freq = cutoff_freq;
+        self.recompute();
+    }
+
+    /// Setter for the Q, limited to above 0
+    pub fn set_q(&mut self, q: f32) {
+        self.q = q.max(0.01);
+        self.recompute();
+    }
+
+    /// Setter for the filter mode
+    pub fn set_mode(&mut self, mode: BiquadMode) {
+        self.mode = mode;
+        self.recompute();
+    }
+
+    /// The magnitude of the filter's frequency response at a frequency in Hz, for drawing the filter curve
+    pub fn magnitude_response(&self, freq: f32) -> f32 {
+        self.coeffs.magnitude_response(freq, self.sample_rate)
+    }
+
+    /// A function to process a single input (given as f32) through the filter
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let (b0, b1, b2, a1, a2) = self.coeffs.get_coeffs();
+        let yn = b0 * xn + b1 * self.x[0] + b2 * self.x[1] - a1 * self.y[0] - a2 * self.y[1];
+
+        self.x = [xn, self.x[0]];
+        self.y = [yn, self.y[0]];
+        yn
+    }
+}
+
+/// The Q of each biquad section in an 8th order Butterworth lowpass, which together give a maximally flat passband
+const BUTTERWORTH_8_Q: [f32; 4] = [0.5098, 0.6013, 0.9000, 2.5629];
+
+/// A steep 8th order Butterworth lowpass filter, made from 4 biquad sections in series.
+/// Used to remove content above the Nyquist frequency of a lower sample rate before converting to it.
+/// # Attributes
+/// * `sections`: The coefficients of each biquad section
+/// * `x`: The previous two inputs of each section, most recent first
+/// * `y`: The previous two outputs of each section, most recent first
+#[derive(Debug)]
+pub struct AntiAliasFilter {
+    sections: [BiquadCoefficients; 4],
+    x: [[f32; 2]; 4],
+    y: [[f32; 2]; 4],
+}
+
+impl AntiAliasFilter {
+    /// Constructor for the filter given its cutoff frequency and the sample rate it runs at, both in Hz
+    pub fn new(cutoff_freq: f32, sample_rate: f32) -> Self {
+        Self {
+            sections: BUTTERWORTH_8_Q
+                .map(|q| BiquadCoefficients::lowpass(cutoff_freq, q, sample_rate)),
+            x: [[0.0; 2]; 4],
+            y: [[0.0; 2]; 4],
+        }
+    }
+
+    /// Setter for the cutoff frequency in Hz, keeping the filter's history so a moving cutoff doesn't click
+    pub fn set_cutoff(&mut self, cutoff_freq: f32, sample_rate: f32) {
+        self.sections =
+            BUTTERWORTH_8_Q.map(|q| BiquadCoefficients::lowpass(cutoff_freq, q, sample_rate));
+    }
+
+    /// Clears the input and output history of every section, so a new signal starts from silence
+    pub fn reset(&mut self) {
+        self.x = [[0.0; 2]; 4];
+        self.y = [[0.0; 2]; 4];
+    }
+
+    /// The group delay of the filter at 0Hz in samples
+    pub fn dc_group_delay(&self) -> f32 {
+        self.sections
+            .iter()
+            .map(|section| section.dc_group_delay())
+            .sum()
+    }
+
+    /// The magnitude of the filter's frequency response at a frequency in Hz, where 1 is unity gain
+    pub fn magnitude_response(&self, freq: f32, sample_rate: f32) -> f32 {
+        self.sections
+            .iter()
+            .map(|section| section.magnitude_response(freq, sample_rate))
+            .product()
+    }
+
+    /// A function to process a single input (given as f32) through every section of the filter
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let mut value = xn;
+        for ((section, x), y) in self
+            .sections
+            .iter()
+            .zip(self.x.iter_mut())
+            .zip(self.y.iter_mut())
+        {
+            let (b0, b1, b2, a1, a2) = section.get_coeffs();
+            let yn = b0 * value + b1 * x[0] + b2 * x[1] - a1 * y[0] - a2 * y[1];
+
+            *x = [value, x[0]];
+            *y = [yn, y[0]];
+            value = yn;
+        }
+        value
+    }
+}
+
+/// The largest magnitude of allpass gain, keeping its feedback loop stable
+const MAX_ALLPASS_GAIN: f32 = 0.99;
+
+/// A Schroeder allpass filter, which passes every frequency at unity gain but smears its phase over time.
+/// Uses the difference equation v[n] = x[n] + g v[n - d], y[n] = v[n - d] - g v[n]
+/// # Attributes
+/// * `buffer`: The delayed internal signal v
+/// * `delay`: The length of the delay d in samples
+/// * `gain`: The feedback and feedforward gain g, between -0.99 and 0.99
+#[derive(Debug, Clone)]
+pub struct AllpassFilter {
+    buffer: DelayBuffer,
+    delay: usize,
+    gain: f32,
+}
+
+impl AllpassFilter {
+    /// Constructor for an allpass filter given its delay in samples (at least 1) and gain
+    pub fn new(delay: usize, gain: f32) -> Self {
+        let delay = delay.max(1);
+        Self {
+            buffer: DelayBuffer::new(delay),
+            delay,
+            gain: gain.clamp(-MAX_ALLPASS_GAIN, MAX_ALLPASS_GAIN),
+        }
+    }
+
+    /// Setter for the feedback and feedforward gain, clamped between -0.99 and 0.99
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain.clamp(-MAX_ALLPASS_GAIN, MAX_ALLPASS_GAIN);
+    }
+
+    /// A function to process a single input (given as f32) through the filter
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let delayed = self.buffer.read(self.delay - 1);
+        let vn = xn + self.gain * delayed;
+        self.buffer.write(vn);
+        delayed - self.gain * vn
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::filter::LowpassFilter;
+    use crate::filter::{
+        AllpassFilter, AntiAliasFilter, BiquadFilter, BiquadMode, Crossover, HPCoefficients,
+        HighpassFilter, LPCoefficients, LowpassFilter, ShelfFilter, ShelfType,
+    };
     use crate::samples::PhonicMode;
     use crate::{load_wav, write_wav};
+    use rand::{thread_rng, Rng};
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+    use std::f32::consts::PI;
+    use test_case::test_case;
 
     #[test]
     fn test_lp() {
@@ -115,4 +833,317 @@ mod tests {
             PhonicMode::Stereo,
         )
     }
+
+    /// Sum of the absolute second differences of a signal, a measure of discontinuities in its slope
+    fn roughness(samples: &[f32]) -> f32 {
+        samples
+            .windows(3)
+            .map(|w| (w[2] - 2.0 * w[1] + w[0]).abs())
+            .sum()
+    }
+
+    #[test]
+    fn test_smooth_cutoff_automation() {
+        let mut instant = LowpassFilter::new(20000.0, 44100.0, 2);
+        let mut smoothed = LowpassFilter::new(20000.0, 44100.0, 2);
+        let mut instant_out = Vec::new();
+        let mut smoothed_out = Vec::new();
+
+        for n in 0..44100 {
+            // jump the cutoff every 64 samples
+            if n % 64 == 0 {
+                let cutoff = match (n / 64) % 2 {
+                    0 => 2000.0,
+                    _ => 80000.0,
+                };
+                instant.set_cutoff(cutoff, 44100.0);
+                smoothed.set_cutoff_smooth(cutoff, 44100.0);
+            }
+            let xn = (2.0 * PI * 200.0 * n as f32 / 44100.0).sin();
+            instant_out.push(instant.process(xn));
+            smoothed_out.push(smoothed.process(xn));
+        }
+
+        assert!(roughness(&smoothed_out) < roughness(&instant_out));
+    }
+
+    /// Energy of the spectrum of a signal between two frequencies in Hz
+    fn band_energy(samples: &[f32], low: f32, high: f32) -> f32 {
+        let mut spectrum: Vec<Complex<f32>> =
+            samples.iter().map(|x| Complex::new(*x, 0.0)).collect();
+        FftPlanner::new()
+            .plan_fft_forward(spectrum.len())
+            .process(&mut spectrum);
+
+        let bin_width = 44100.0 / samples.len() as f32;
+        spectrum[..samples.len() / 2]
+            .iter()
+            .enumerate()
+            .filter(|(bin, _)| (low..high).contains(&(*bin as f32 * bin_width)))
+            .map(|(_, value)| value.norm_sqr())
+            .sum()
+    }
+
+    #[test]
+    fn test_high_shelf_boost() {
+        let mut rng = thread_rng();
+        let noise: Vec<f32> = (0..16384).map(|_| rng.gen_range(-0.5..0.5)).collect();
+
+        let mut shelf = ShelfFilter::new(ShelfType::HighShelf, 2000.0, 12.0, 44100.0);
+        let boosted: Vec<f32> = noise.iter().map(|xn| shelf.process(*xn)).collect();
+
+        // well above the corner the full 12dB boost applies, almost 16x the energy
+        let high_ratio =
+            band_energy(&boosted, 8000.0, 20000.0) / band_energy(&noise, 8000.0, 20000.0);
+        // well below the corner the signal is unchanged
+        let low_ratio = band_energy(&boosted, 20.0, 300.0) / band_energy(&noise, 20.0, 300.0);
+
+        assert!(high_ratio > 10.0, "high band energy ratio was {high_ratio}");
+        assert!(
+            (0.8..1.25).contains(&low_ratio),
+            "low band energy ratio was {low_ratio}"
+        );
+    }
+
+    #[test_case(100.0 ; "low cutoff")]
+    #[test_case(1000.0 ; "mid cutoff")]
+    #[test_case(10000.0 ; "high cutoff")]
+    fn test_lowpass_response(cutoff: f32) {
+        let filter = LowpassFilter::new(cutoff, 44100.0, 2);
+        let at_cutoff = 20.0 * filter.magnitude_response(cutoff, 44100.0).log10();
+        assert!(
+            (at_cutoff + 3.01).abs() < 0.05,
+            "{at_cutoff}dB at the cutoff"
+        );
+        assert!((filter.magnitude_response(0.0, 44100.0) - 1.0).abs() < 0.001);
+
+        let curve = filter.magnitude_response_curve(64, 44100.0);
+        assert_eq!(curve.len(), 64);
+        assert!(curve.windows(2).all(|pair| pair[1].1 <= pair[0].1));
+    }
+
+    #[test]
+    fn test_lp_coefficients_magnitude_at_cutoff() {
+        for cutoff in [50.0, 500.0, 5000.0, 15000.0] {
+            let coeffs = LPCoefficients::new(cutoff, 44100.0);
+            let at_cutoff = 20.0 * coeffs.magnitude_at(cutoff, 44100.0).log10();
+            assert!(
+                (at_cutoff + 3.01).abs() < 0.05,
+                "{at_cutoff}dB at the {cutoff}Hz cutoff"
+            );
+            assert!((coeffs.magnitude_at(0.0, 44100.0) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_lp_coefficients_response_matches_process() {
+        // the response computed from the coefficients should agree with the gain of a sine run through the filter
+        let coeffs = LPCoefficients::new(2000.0, 44100.0);
+        for freq in [200.0, 2000.0, 8000.0] {
+            let mut filter = LowpassFilter::new(2000.0, 44100.0, 2);
+            let peak = (0..44100)
+                .map(|n| filter.process((2.0 * PI * freq * n as f32 / 44100.0).sin()))
+                .skip(4410)
+                .fold(0.0_f32, |peak, x| peak.max(x.abs()));
+            let predicted = coeffs.magnitude_at(freq, 44100.0);
+            assert!(
+                (peak - predicted).abs() < 0.01,
+                "{peak} != {predicted} at {freq}Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn test_shelf_response() {
+        let shelf = ShelfFilter::new(ShelfType::HighShelf, 2000.0, 12.0, 44100.0);
+        let high = 20.0 * shelf.magnitude_response(18000.0).log10();
+        let low = 20.0 * shelf.magnitude_response(20.0).log10();
+        // the shelf is half its gain in dB at the corner
+        let corner = 20.0 * shelf.magnitude_response(2000.0).log10();
+        assert!((high - 12.0).abs() < 0.5 && low.abs() < 0.1 && (corner - 6.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_anti_alias_response() {
+        let filter = AntiAliasFilter::new(20000.0, 88200.0);
+        // flat through the audible band, then dropping steeply past the cutoff
+        assert!((filter.magnitude_response(1000.0, 88200.0) - 1.0).abs() < 0.01);
+        assert!((filter.magnitude_response(20000.0, 88200.0) - 0.707).abs() < 0.01);
+        assert!(filter.magnitude_response(30000.0, 88200.0) < 0.01);
+    }
+
+    #[test]
+    fn test_lowpass_reset() {
+        let mut filter = LowpassFilter::new(1000.0, 44100.0, 2);
+        let render = |filter: &mut LowpassFilter| -> Vec<f32> {
+            (0..1000)
+                .map(|n| filter.process((2.0 * PI * 440.0 * n as f32 / 44100.0).sin()))
+                .collect()
+        };
+
+        let first = render(&mut filter);
+        // a loud sample left in the history would otherwise bleed into the next output
+        filter.process(1.0);
+        filter.reset();
+        assert_eq!(filter.process(0.0), 0.0);
+        filter.reset();
+        assert_eq!(render(&mut filter), first);
+    }
+
+    #[test]
+    fn test_hp_coefficients() {
+        let coeffs = HPCoefficients::new(200.0, 44100.0);
+        assert!((20.0 * coeffs.magnitude_response(200.0, 44100.0).log10() + 3.01).abs() < 0.05);
+        assert!(coeffs.magnitude_response(5.0, 44100.0) < 0.03);
+        assert!((coeffs.magnitude_response(15000.0, 44100.0) - 1.0).abs() < 0.001);
+
+        // the lowpass and highpass at the same cutoff are complementary, summing to the input
+        let (hp_a1, hp_b0, hp_b1) = coeffs.get_coeffs();
+        let (lp_a1, lp_b0, lp_b1) = LPCoefficients::new(200.0, 44100.0).get_coeffs();
+        assert!((hp_a1 - lp_a1).abs() < 1e-6);
+        assert!((hp_b0 + lp_b0 - 1.0).abs() < 1e-6);
+        assert!((hp_b1 + lp_b1 + hp_a1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_highpass_removes_rumble() {
+        let mut filter = HighpassFilter::new(100.0, 44100.0, 2);
+        let rms = |filter: &mut HighpassFilter, frequency: f32| {
+            let squares: f32 = (0..44100)
+                .map(|n| filter.process((2.0 * PI * frequency * n as f32 / 44100.0).sin()))
+                .skip(4410)
+                .map(|x| x * x)
+                .sum();
+            (squares / (44100.0 - 4410.0)).sqrt()
+        };
+
+        // a 10Hz rumble is cut by around 20dB, while a 2kHz tone passes
+        assert!(rms(&mut filter, 10.0) < 0.1);
+        filter.set_cutoff(100.0, 44100.0);
+        assert!((rms(&mut filter, 2000.0) - 0.707).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_biquad_resonance() {
+        // the peak of a sine at the cutoff, once the filter has settled
+        let peak_at_cutoff = |filter: &mut BiquadFilter| {
+            (0..44100)
+                .map(|n| filter.process((2.0 * PI * 1000.0 * n as f32 / 44100.0).sin()))
+                .skip(22050)
+                .fold(0.0_f32, |peak, x| peak.max(x.abs()))
+        };
+
+        // at the cutoff, lowpass and highpass gains equal the Q
+        let mut lowpass = BiquadFilter::new(1000.0, 4.0, 44100.0, BiquadMode::Lowpass);
+        assert!((peak_at_cutoff(&mut lowpass) - 4.0).abs() < 0.05);
+        lowpass.set_q(0.707);
+        assert!((peak_at_cutoff(&mut lowpass) - 0.707).abs() < 0.01);
+
+        let mut highpass = BiquadFilter::new(1000.0, 4.0, 44100.0, BiquadMode::Highpass);
+        assert!((peak_at_cutoff(&mut highpass) - 4.0).abs() < 0.05);
+        assert!(highpass.magnitude_response(100.0) < 0.02);
+
+        // the bandpass passes its center frequency at unity gain and rejects both sides
+        let mut bandpass = BiquadFilter::new(1000.0, 4.0, 44100.0, BiquadMode::Bandpass);
+        assert!((peak_at_cutoff(&mut bandpass) - 1.0).abs() < 0.01);
+        assert!(bandpass.magnitude_response(100.0) < 0.05);
+        assert!(bandpass.magnitude_response(10000.0) < 0.05);
+
+        // the lowpass rolls off at 12dB per octave, well past the cutoff
+        lowpass.set_cutoff(500.0);
+        let ratio = lowpass.magnitude_response(8000.0) / lowpass.magnitude_response(16000.0);
+        assert!(ratio > 3.5, "octave ratio was {ratio}");
+    }
+
+    #[test]
+    fn test_allpass_impulse_energy() {
+        // an allpass keeps all of the energy of an impulse, spread out over time
+        let mut allpass = AllpassFilter::new(50, 0.6);
+        let energy: f32 = (0..10000)
+            .map(|n| {
+                let impulse = match n {
+                    0 => 1.0,
+                    _ => 0.0,
+                };
+                allpass.process(impulse).powi(2)
+            })
+            .sum();
+        assert!((energy - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_allpass_chain_is_flat() {
+        let mut chain: Vec<AllpassFilter> = [113, 163, 229]
+            .iter()
+            .map(|delay| AllpassFilter::new(*delay, 0.5))
+            .collect();
+        chain[1].set_gain(-0.7);
+
+        let block_len = 8192;
+        let mut spectrum: Vec<Complex<f32>> = (0..block_len)
+            .map(|n| {
+                let impulse = match n {
+                    0 => 1.0,
+                    _ => 0.0,
+                };
+                let yn = chain
+                    .iter_mut()
+                    .fold(impulse, |sample, allpass| allpass.process(sample));
+                Complex::new(yn, 0.0)
+            })
+            .collect();
+        FftPlanner::new()
+            .plan_fft_forward(block_len)
+            .process(&mut spectrum);
+
+        // the chain smears the impulse, but every frequency still passes at unity gain
+        for bin in spectrum[..block_len / 2].iter() {
+            assert!((bin.norm() - 1.0).abs() < 0.01, "gain of {}", bin.norm());
+        }
+    }
+
+    #[test]
+    fn test_crossover_bands() {
+        let sample_rate = 44100.0;
+        let crossover_freq = 1000.0;
+        let block_len = 8192;
+        let mut crossover = Crossover::new(crossover_freq, sample_rate);
+
+        let mut low: Vec<Complex<f32>> = Vec::with_capacity(block_len);
+        let mut high: Vec<Complex<f32>> = Vec::with_capacity(block_len);
+        for n in 0..block_len {
+            let impulse = match n {
+                0 => 1.0,
+                _ => 0.0,
+            };
+            let (low_n, high_n) = crossover.process(impulse);
+            low.push(Complex::new(low_n, 0.0));
+            high.push(Complex::new(high_n, 0.0));
+        }
+        let mut sum: Vec<Complex<f32>> =
+            low.iter().zip(high.iter()).map(|(l, h)| *l + *h).collect();
+
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(block_len);
+        fft.process(&mut low);
+        fft.process(&mut high);
+        fft.process(&mut sum);
+
+        let to_db = |gain: f32| 20.0 * gain.log10();
+        let bin = |freq: f32| (freq * block_len as f32 / sample_rate).round() as usize;
+
+        // both bands are 6dB down at the crossover
+        let crossover_bin = bin(crossover_freq);
+        assert!((to_db(low[crossover_bin].norm()) + 6.02).abs() < 0.2);
+        assert!((to_db(high[crossover_bin].norm()) + 6.02).abs() < 0.2);
+
+        // an octave either side of the crossover, the opposite band has fallen by well over 12dB
+        assert!(to_db(high[bin(crossover_freq / 2.0)].norm()) < -20.0);
+        assert!(to_db(low[bin(crossover_freq * 2.0)].norm()) < -20.0);
+
+        // the recombined bands pass every frequency at unity gain
+        for bin in sum[1..block_len / 2].iter() {
+            assert!((bin.norm() - 1.0).abs() < 0.01, "gain of {}", bin.norm());
+        }
+    }
 }
@@ -0,0 +1,244 @@
+//! Module containing a flanger effect, which sweeps a comb filter up and down the spectrum.
+//! Flanger mixes the input with a copy of itself through a short delay modulated by an MMLFO,
+//! with feedback to deepen the notches and an optional through-zero mode.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use crate::delay_buffer::DelayBuffer;
+use crate::lfo::{LFOMode, MMLFO};
+
+/// The longest distance in seconds the modulated delay sweeps over
+const MAX_SWEEP_SECONDS: f32 = 0.005;
+
+/// The shortest modulated delay in seconds, outside of through-zero mode
+const MIN_DELAY_SECONDS: f32 = 0.0001;
+
+/// The feedback is kept below 1 in magnitude so that the comb filter can't self-oscillate
+const MAX_FEEDBACK: f32 = 0.95;
+
+/// A struct for a stereo flanger, where a delay of a few milliseconds is swept by an LFO,
+/// moving the notches of the comb filter created when the delayed signal is mixed with the dry signal.
+/// ## Attributes:
+/// * `left`: The delay buffer for the left channel
+///
+/// * `right`: The delay buffer for the right channel
+///
+/// * `dry_left`: The buffer delaying the dry left channel in through-zero mode
+///
+/// * `dry_right`: The buffer delaying the dry right channel in through-zero mode
+///
+/// * `lfo`: The LFO sweeping the delay time, its output of 0 to 1 is mapped across the sweep
+///
+/// * `sample_rate`: The sample rate of the input in Hz
+///
+/// * `depth`: The fraction of the maximum sweep covered by the LFO, from 0 to 1
+///
+/// * `feedback`: The amount of the delayed signal fed back into the delay, negative values invert it
+///
+/// * `mix`: The proportion of delayed signal in the output, 0.5 gives the deepest notches
+///
+/// * `through_zero`: Whether the dry signal is delayed to the middle of the sweep,
+/// so the delayed signal passes through it and the notches sweep out past Nyquist
+pub struct Flanger {
+    left: DelayBuffer,
+    right: DelayBuffer,
+    dry_left: DelayBuffer,
+    dry_right: DelayBuffer,
+    lfo: MMLFO,
+    sample_rate: f32,
+    depth: f32,
+    feedback: f32,
+    mix: f32,
+    through_zero: bool,
+}
+
+impl Flanger {
+    /// Constructor for the flanger, with a 0.5Hz sine LFO at full depth, no feedback and an even mix
+    /// # Parameters
+    /// * `sample_rate`: The sample rate of the input in Hz
+    pub fn new(sample_rate: f32) -> Self {
+        let mut lfo = MMLFO::new(false, LFOMode::Sine);
+        lfo.set_sample_rate(sample_rate);
+        lfo.set_frequency_hz(0.5);
+        // space for the full sweep, the minimum delay and the sample either side of a fractional read
+        let capacity = ((MAX_SWEEP_SECONDS + MIN_DELAY_SECONDS) * sample_rate) as usize + 2;
+        Self {
+            left: DelayBuffer::new(capacity),
+            right: DelayBuffer::new(capacity),
+            dry_left: DelayBuffer::new(capacity),
+            dry_right: DelayBuffer::new(capacity),
+            lfo,
+            sample_rate,
+            depth: 1.0,
+            feedback: 0.0,
+            mix: 0.5,
+            through_zero: false,
+        }
+    }
+
+    /// Setter for the sweep rate in Hz
+    pub fn set_rate(&mut self, rate_hz: f32) {
+        self.lfo.set_frequency_hz(rate_hz);
+    }
+
+    /// Setter for the sweep depth, between 0 (a static comb filter) and 1 (the full sweep)
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Setter for the feedback, clamped between -0.95 and 0.95
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(-MAX_FEEDBACK, MAX_FEEDBACK);
+    }
+
+    /// Setter for the dry / wet mix, between 0 (dry) and 1 (wet)
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Setter for through-zero mode
+    pub fn set_through_zero(&mut self, through_zero: bool) {
+        self.through_zero = through_zero;
+    }
+
+    /// Returns the (modulated, dry) delays in samples for an LFO value between 0 and 1
+    fn delays(&self, lfo_value: f32) -> (f32, f32) {
+        let sweep = MAX_SWEEP_SECONDS * self.sample_rate;
+        match self.through_zero {
+            true => {
+                // the dry signal sits in the middle of the sweep, so the delay swings either side of it
+                let center = 0.5 * sweep;
+                (
+                    center + self.depth * center * (2.0 * lfo_value - 1.0),
+                    center,
+                )
+            }
+            false => (
+                MIN_DELAY_SECONDS * self.sample_rate + self.depth * sweep * lfo_value,
+                0.0,
+            ),
+        }
+    }
+
+    /// Processes one channel through its delay buffers, returning the mixed output
+    fn process_channel(
+        buffer: &mut DelayBuffer,
+        dry_buffer: &mut DelayBuffer,
+        xn: f32,
+        delay: f32,
+        dry_delay: f32,
+        feedback: f32,
+        mix: f32,
+    ) -> f32 {
+        let wet = buffer.read_fractional(delay);
+        buffer.write(xn + feedback * wet);
+
+        let dry = match dry_delay > 0.0 {
+            true => dry_buffer.read_fractional(dry_delay),
+            false => xn,
+        };
+        dry_buffer.write(xn);
+
+        (1.0 - mix) * dry + mix * wet
+    }
+
+    /// Processes a single pair of samples, returning the flanged (left, right) pair.
+    /// For a mono signal, pass the same sample to both channels.
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let lfo_value = self.lfo.get_next_value();
+        let (delay, dry_delay) = self.delays(lfo_value);
+        let left_out = Self::process_channel(
+            &mut self.left,
+            &mut self.dry_left,
+            left,
+            delay,
+            dry_delay,
+            self.feedback,
+            self.mix,
+        );
+        let right_out = Self::process_channel(
+            &mut self.right,
+            &mut self.dry_right,
+            right,
+            delay,
+            dry_delay,
+            self.feedback,
+            self.mix,
+        );
+        (left_out, right_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flanger::Flanger;
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    /// The length of each analysed block, each starting with an impulse
+    const BLOCK_LEN: usize = 2048;
+
+    /// Returns the first frequency bin of an impulse response block which is notched out by the comb filter
+    fn first_notch(block: &[f32]) -> usize {
+        let mut spectrum: Vec<Complex<f32>> = block.iter().map(|x| Complex::new(*x, 0.0)).collect();
+        FftPlanner::new()
+            .plan_fft_forward(BLOCK_LEN)
+            .process(&mut spectrum);
+        // the dry and wet impulses each have a gain of 0.5, so the peaks reach 1
+        spectrum[1..BLOCK_LEN / 2]
+            .iter()
+            .position(|bin| bin.norm() < 0.2)
+            .map(|index| index + 1)
+            .unwrap_or(BLOCK_LEN / 2)
+    }
+
+    /// Feeds an impulse into the flanger at the start of each of 16 blocks spanning one sweep,
+    /// returning the first notch of each block
+    fn sweep_notches(flanger: &mut Flanger) -> Vec<usize> {
+        flanger.set_rate(44100.0 / (16 * BLOCK_LEN) as f32);
+        (0..16)
+            .map(|_| {
+                let block: Vec<f32> = (0..BLOCK_LEN)
+                    .map(|n| match n {
+                        0 => flanger.process(1.0, 1.0).0,
+                        _ => flanger.process(0.0, 0.0).0,
+                    })
+                    .collect();
+                first_notch(&block)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_notches_sweep() {
+        let notches = sweep_notches(&mut Flanger::new(44100.0));
+
+        // the first notch sits at half the reciprocal of the delay, which sweeps from about 0.1ms to 5ms
+        let lowest = *notches.iter().min().unwrap();
+        let highest = *notches.iter().max().unwrap();
+        assert!(lowest <= 6, "lowest notch at bin {lowest}");
+        assert!(highest >= 50, "highest notch at bin {highest}");
+
+        // the notches move down and back up once per sweep, rather than jumping around
+        let mut distinct = notches.clone();
+        distinct.dedup();
+        let turns = distinct
+            .windows(3)
+            .filter(|bins| (bins[0] < bins[1]) != (bins[1] < bins[2]))
+            .count();
+        assert!(turns <= 2, "notches at bins {notches:?}");
+    }
+
+    #[test]
+    fn test_through_zero() {
+        let mut flanger = Flanger::new(44100.0);
+        flanger.set_through_zero(true);
+        let notches = sweep_notches(&mut flanger);
+
+        // the delays cross twice per sweep, and as they do the notches sweep up towards Nyquist,
+        // beyond the first notch of the shortest delay outside of through-zero mode
+        let crossings = notches.iter().filter(|bin| **bin > BLOCK_LEN / 8).count();
+        assert_eq!(crossings, 2, "{notches:?}");
+        assert!(*notches.iter().min().unwrap() <= 12, "{notches:?}");
+    }
+}
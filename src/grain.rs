@@ -1,8 +1,9 @@
-//! A module containing 3 Structs and an Enum
+//! A module containing 4 Structs and an Enum
 //! Grain:
 //!     The Grain struct represents a grain of audio data that can be played back.
 //!     It contains various fields including an audio buffer, upper and lower index values,
 //!     grain ID, a reverse flag, a smoother object, and others.
+//!     Grains (and Grain Managers) read i16 buffers by default, or f32 buffers as `Grain<f32>`.
 //!
 //! Id Manager:
 //!     The IdManager struct manages IDs by keeping track of the next available ID in its next_id field.
@@ -23,11 +24,21 @@
 //!             For Cascade mode, the audio buffer is divided into equal-sized chunks,
 //!             and each grain is assigned a lower and upper index based on its position in the cascade.
 //!
+//! Grain Morph:
+//!     The GrainMorph struct runs two GrainManagers over the same buffer, usually in different modes,
+//!     and crossfades between their outputs so one texture can evolve into the other.
+//!
 use crate::envelope::ADSREnvelope;
+use crate::filter::AntiAliasFilter;
 use crate::interpolators::lerp;
-use crate::resample::{semitone_to_hz_ratio, LinearResampler};
-use crate::smoothers::{HannSmoother, Smoother};
-use rand::prelude::{thread_rng, Rng, SliceRandom};
+use crate::midi::{note_to_semitones, quantize_semitones, Scale};
+use crate::multi_channel::equal_power_pan;
+use crate::quality::Quality;
+use crate::resample::{interpolate_at, semitone_to_hz_ratio, LinearResampler, ResampleMethod};
+use crate::samples::AudioSample;
+use crate::smoothers::{GrainWindow, HannSmoother, OnePoleSmoother, Smoother};
+use rand::prelude::{Rng, SeedableRng, SliceRandom, StdRng};
+use std::cmp::Ordering;
 
 //  * re-pitch
 //     - Resample the audio at the correct rate for pitch shifting and linearly interpolate
@@ -48,6 +59,23 @@ use rand::prelude::{thread_rng, Rng, SliceRandom};
 //  * Loop
 //  * Smooth (windowing)
 
+/// The most source samples the anti-aliasing filter will catch up on between reads,
+/// any larger jump is treated as the grain looping or seeking
+const MAX_ANTIALIAS_CATCHUP: usize = 16;
+
+/// The time in milliseconds taken for a change of morph amount to take effect, avoiding clicks
+const MORPH_SMOOTH_MS: f32 = 50.0;
+
+/// The time in milliseconds a grain added to or removed from a cloud or cascade takes to fade in or out
+const GRAIN_FADE_MS: f32 = 10.0;
+
+/// The sample rate grain managers assume until `set_sample_rate` is called
+const DEFAULT_SAMPLE_RATE: f32 = 44100.0;
+
+/// The cutoff of the anti-aliasing filter as a fraction of the sample rate, before dividing by the pitch ratio,
+/// leaving room below the resampled Nyquist frequency for the filter's transition band
+const ANTIALIAS_CUTOFF: f32 = 0.45;
+
 /// Struct used to assign an index to an object, keeping track of a sequence of objects using a next_id variable
 /// Increments ID by 1 each time.
 #[derive(Default)]
@@ -69,15 +97,25 @@ impl IdManager {
         self.next_id += 1;
         id
     }
+
+    /// Hands back the last ID given out, so it is given out again next, used when the last object is removed
+    pub fn release_id(&mut self) {
+        self.next_id = self.next_id.saturating_sub(1);
+    }
+
+    /// Starts giving out IDs from 0 again
+    pub fn reset(&mut self) {
+        self.next_id = 0;
+    }
 }
 
 /// Struct used to store a fragment of an audio buffer, without copying it's data.
 /// Can have parameters set with fairly low overhead.
 /// ## Attributes
-/// * `audio_buffer`: A reference to a static audio buffer using i16 samples
+/// * `audio_buffer`: A reference to a static audio buffer, of i16 samples by default or any other `AudioSample` type such as f32
 ///
-/// * `upper_index`: The last index stored in the grain, must be greater than lower_index,
-///     and must be less than the audio buffer length
+/// * `upper_index`: The index one past the last sample stored in the grain, must be greater than lower_index,
+///     and must be at most the audio buffer length
 ///
 /// * `lower_index`: The first index stored in the grain, must be lesser than upper_index,
 ///     and must be greater than 0
@@ -104,8 +142,28 @@ impl IdManager {
 /// * `next_upper`: Used with the `lock_playback` bool to store the next upper index to set, which will be applied once the grain finishes playback
 ///
 /// * `next_lower`: Used with the `lock_playback` bool to store the next lower index to set, which will be applied once the grain finishes playback
-pub struct Grain {
-    audio_buffer: &'static Vec<i16>,
+///
+/// * `pan`: The stereo position of the grain, from -1 (left) to 1 (right), used by stereo playback
+///
+/// * `next_reverse`: Stores a reverse setting made during playback, which will be applied once the grain finishes playback
+///
+/// * `silent`: Whether the grain is muted for its current loop, used when cloud grains fail to respawn
+///
+/// * `loop_start`: Whether the grain is at the start of a loop, set when it finishes one and cleared once it plays a sample
+///
+/// * `next_progress`: Used with the `lock_playback` bool to store a seek made during playback, which will be applied once the grain finishes playback
+///
+/// * `antialias`: Whether source samples are lowpass filtered before being resampled when the grain is pitched up
+///
+/// * `antialias_filter`: The steep lowpass filter used for anti-aliasing, with its cutoff just below the Nyquist frequency divided by the pitch ratio
+///
+/// * `antialias_index`: The last buffer index fed through the anti-aliasing filter, so skipped samples can be caught up
+///
+/// * `antialias_outputs`: The (previous, current) outputs of the anti-aliasing filter, interpolated between for the fractional position
+///
+/// * `interpolation`: The method used to read the buffer between samples when the grain is pitched
+pub struct Grain<S: AudioSample = i16> {
+    audio_buffer: &'static Vec<S>,
     upper_index: usize,
     lower_index: usize,
     grain_id: usize,
@@ -119,11 +177,21 @@ pub struct Grain {
     lock_playback: bool,
     next_upper: usize,
     next_lower: usize,
-    resampler: LinearResampler<'static>,
+    resampler: LinearResampler<'static, S>,
     pitch_enable: bool,
+    pan: f32,
+    next_reverse: bool,
+    silent: bool,
+    loop_start: bool,
+    next_progress: Option<f32>,
+    antialias: bool,
+    antialias_filter: AntiAliasFilter,
+    antialias_index: Option<usize>,
+    antialias_outputs: (f32, f32),
+    interpolation: ResampleMethod,
 }
 
-impl Grain {
+impl<S: AudioSample> Grain<S> {
     /// Constructor for a grain, accepts a reference to a static audio buffer,
     /// an ID to assign and the index_mod (number of grains used for sequence playback)
     /// ## Default settings:
@@ -141,7 +209,9 @@ impl Grain {
     ///
     /// * lock_playback: false (enabled in populate grains function)
     ///
-    pub fn new(audio_buffer: &'static Vec<i16>, id: usize, index_mod: usize, pitch: i8) -> Self {
+    /// * pan: 0 (center)
+    ///
+    pub fn new(audio_buffer: &'static Vec<S>, id: usize, index_mod: usize, pitch: i8) -> Self {
         Self {
             audio_buffer,
             upper_index: audio_buffer.len(),
@@ -162,12 +232,26 @@ impl Grain {
                 semitone_to_hz_ratio(pitch) as f64,
             ),
             pitch_enable: true,
+            pan: 0.0,
+            next_reverse: false,
+            silent: false,
+            loop_start: true,
+            next_progress: None,
+            antialias: false,
+            antialias_filter: AntiAliasFilter::new(
+                ANTIALIAS_CUTOFF * DEFAULT_SAMPLE_RATE,
+                DEFAULT_SAMPLE_RATE,
+            ),
+            antialias_index: None,
+            antialias_outputs: (0.0, 0.0),
+            interpolation: ResampleMethod::Linear,
         }
     }
 
     /// Return the next sample of playback, may be from a sequential grain or multiple grains, with output averaged.
     /// Optional smoothing through the `smoothed` Boolean
-    pub fn get_next_sample(&mut self, smoothed: bool) -> i16 {
+    pub fn get_next_sample(&mut self, smoothed: bool) -> S {
+        self.loop_start = false;
         match self.pitch_enable {
             false => {
                 let index = match self.reverse {
@@ -176,20 +260,18 @@ impl Grain {
                 };
 
                 let value = match smoothed {
-                    true => {
-                        (self.audio_buffer[index] as f32
+                    true => S::from_f32(
+                        self.audio_buffer[index].to_f32()
                             * ((self.smooth_factor * self.smoother.get_index(self.current_index))
-                                + (1.0 - self.smooth_factor))) as i16
-                    }
+                                + (1.0 - self.smooth_factor)),
+                    ),
                     false => self.audio_buffer[index],
                 };
                 self.current_index = (self.current_index + 1) % (self.len());
 
                 // runs after 1 full loop of the grain
                 if self.current_index == 0 {
-                    self.lower_index = self.next_lower;
-                    self.upper_index = self.next_upper;
-                    self.smoother.set_length(self.len());
+                    self.finish_loop();
                 }
 
                 value
@@ -200,11 +282,10 @@ impl Grain {
                     false => self.lower_index as f32 + self.resampler.get_position() as f32,
                 };
 
-                let sample = lerp(
-                    self.audio_buffer[index.floor() as usize] as f32,
-                    self.audio_buffer[index.floor() as usize + 1] as f32,
-                    index.fract(),
-                );
+                let sample = match self.antialias && self.get_pitch_freq() > 1.0 {
+                    true => self.antialiased_sample(index),
+                    false => interpolate_at(self.audio_buffer, index, self.interpolation),
+                };
 
                 let smooth_value = lerp(
                     self.smoother.get_index(index.floor() as usize),
@@ -213,19 +294,15 @@ impl Grain {
                 );
 
                 let value = match smoothed {
-                    true => {
-                        (sample
-                            * ((self.smooth_factor * smooth_value) + (1.0 - self.smooth_factor)))
-                            as i16
-                    }
-                    false => sample as i16,
+                    true => S::from_f32(
+                        sample * ((self.smooth_factor * smooth_value) + (1.0 - self.smooth_factor)),
+                    ),
+                    false => S::from_f32(sample),
                 };
 
                 // runs after 1 full loop of the grain
                 if self.resampler.increment() {
-                    self.lower_index = self.next_lower;
-                    self.upper_index = self.next_upper;
-                    self.smoother.set_length(self.len());
+                    self.finish_loop();
                 }
 
                 value
@@ -233,6 +310,75 @@ impl Grain {
         }
     }
 
+    /// Applies the changes deferred while the grain was playing, once it reaches the end of a loop
+    fn finish_loop(&mut self) {
+        self.loop_start = true;
+        self.lower_index = self.next_lower;
+        self.upper_index = self.next_upper;
+        self.reverse = self.next_reverse;
+        self.smoother.set_length(self.len());
+        self.resampler
+            .set_buffer(&self.audio_buffer[self.lower_index..self.upper_index]);
+        if let Some(progress) = self.next_progress.take() {
+            self.seek(progress);
+        }
+    }
+
+    /// Reads the buffer at a fractional index through the anti-aliasing filter.
+    ///
+    /// Pitching up skips over source samples, so every sample passed since the last read is fed through the filter
+    /// to remove content which would alias, then the filter outputs either side of the index are interpolated.
+    fn antialiased_sample(&mut self, index: f32) -> f32 {
+        let target = index.floor() as usize;
+        // the first sample to feed, and how many to feed in playback order
+        let (first, count) = match (self.antialias_index, self.reverse) {
+            (Some(last), false) if last < target && target - last <= MAX_ANTIALIAS_CATCHUP => {
+                (last + 1, target - last)
+            }
+            (Some(last), true) if last > target && last - target <= MAX_ANTIALIAS_CATCHUP => {
+                (last - 1, last - target)
+            }
+            (Some(last), _) if last == target => (target, 0),
+            // the grain looped or was seeked, so start again from this sample
+            _ => (target, 1),
+        };
+
+        for step in 0..count {
+            let source_index = match self.reverse {
+                true => first - step,
+                false => first + step,
+            };
+            let filtered = self
+                .antialias_filter
+                .process(self.audio_buffer[source_index].to_f32());
+            self.antialias_outputs = (self.antialias_outputs.1, filtered);
+        }
+        self.antialias_index = Some(target);
+
+        let (previous, current) = self.antialias_outputs;
+        match self.reverse {
+            true => lerp(current, previous, index.fract()),
+            false => lerp(previous, current, index.fract()),
+        }
+    }
+
+    /// Setter for anti-aliasing when pitching up. When on, the source is lowpass filtered at the Nyquist frequency
+    /// divided by the pitch ratio before resampling, so high pitched grains don't alias. Has no effect when pitching down.
+    pub fn set_antialias(&mut self, on: bool) {
+        self.antialias = on;
+        self.antialias_index = None;
+        self.update_antialias_cutoff();
+    }
+
+    /// Moves the anti-aliasing filter cutoff to just below the Nyquist frequency of the resampled output
+    fn update_antialias_cutoff(&mut self) {
+        let ratio = self.get_pitch_freq().max(1.0);
+        self.antialias_filter.set_cutoff(
+            ANTIALIAS_CUTOFF * DEFAULT_SAMPLE_RATE / ratio,
+            DEFAULT_SAMPLE_RATE,
+        );
+    }
+
     /// Setter for next grain ID to be loaded after a full playback
     fn set_next_id(&mut self, id: usize) {
         self.next_id = id;
@@ -241,11 +387,77 @@ impl Grain {
     /// Set the read index for samples from this grain
     pub fn set_sample_index(&mut self, index: usize) {
         self.current_index = index;
+        self.loop_start = index == 0;
     }
 
-    /// Toggle the reverse status of the grain on or off
+    /// Toggle the reverse status of the grain on or off.
+    ///
+    /// If the grain is partway through playback, the change is deferred until the grain finishes,
+    /// as flipping direction mid grain would jump the read position and cause a click.
     pub fn set_reverse(&mut self, on_off: bool) {
-        self.reverse = on_off;
+        self.next_reverse = on_off;
+        if self.at_loop_start() {
+            self.reverse = on_off;
+        }
+    }
+
+    /// Returns true if the grain is at the start of playback, either before it has played or just after finishing a loop
+    pub fn at_loop_start(&self) -> bool {
+        self.loop_start
+    }
+
+    /// The playback position through the current loop of the grain, from 0 (start) towards 1 (end),
+    /// independent of the grain length and pitch
+    pub fn progress(&self) -> f32 {
+        match self.pitch_enable {
+            true => match self.resampler.loop_len() {
+                0 => 0.0,
+                loop_len => (self.resampler.get_position() / loop_len as f64) as f32,
+            },
+            false => match self.len() {
+                0 => 0.0,
+                len => self.current_index as f32 / len as f32,
+            },
+        }
+    }
+
+    /// The index in the audio buffer of the next sample the grain reads, such as to draw a playback cursor.
+    /// Pitched grains read between samples, so this is the sample at or before the read position
+    pub fn buffer_position(&self) -> usize {
+        let offset = match self.pitch_enable {
+            true => self.resampler.get_position().floor() as usize,
+            false => self.current_index,
+        };
+        match self.reverse {
+            true => self.upper_index.saturating_sub(offset),
+            false => self.lower_index + offset,
+        }
+    }
+
+    /// Seek to a fractional position within the grain, from 0 (start) to 1 (end).
+    ///
+    /// If playback is locked, the seek is applied once the grain finishes its current loop.
+    pub fn set_progress(&mut self, progress: f32) {
+        let progress = progress.clamp(0.0, 1.0);
+        match self.lock_playback {
+            true => self.next_progress = Some(progress),
+            false => self.seek(progress),
+        }
+    }
+
+    /// Move the read position to a fraction of the way through the grain
+    fn seek(&mut self, progress: f32) {
+        self.loop_start = progress == 0.0;
+        match self.pitch_enable {
+            true => {
+                let loop_len = self.resampler.loop_len() as f64;
+                self.resampler.set_position(progress as f64 * loop_len);
+            }
+            false => {
+                self.current_index =
+                    ((progress * self.len() as f32) as usize).min(self.len().saturating_sub(1));
+            }
+        }
     }
 
     /// Lock playback, meaning that setting an index will not occur until after grain playback has completed
@@ -258,6 +470,13 @@ impl Grain {
         self.lock_playback = false;
     }
 
+    /// Setter for the number of grains in the sequence, relinking the next grain so the sequence wraps to the first.
+    /// Used when grains are added to or removed from a manager
+    fn set_index_mod(&mut self, index_mod: usize) {
+        self.index_mod = index_mod;
+        self.set_looping(self.looping);
+    }
+
     /// Set the looping boolean to on or off and adjust the next_id field accordingly:
     ///     enabled => instance ID (causing it to restart once finished)
     ///     disabled => instance ID + 1 (causing it to jump to next grain in sequence once finished)
@@ -285,6 +504,12 @@ impl Grain {
         self.smoother.set_length(self.len());
     }
 
+    /// Setter for the window shape of the grain, swapping in the smoother for that shape
+    pub fn set_window(&mut self, window: GrainWindow) {
+        self.smoother = window.smoother();
+        self.update_smoother();
+    }
+
     /// Setter for the smoothing factor as a percentage between 0 and 1
     pub fn set_smooth_factor(&mut self, factor: f32) {
         self.smooth_factor = factor;
@@ -298,8 +523,9 @@ impl Grain {
                 self.upper_index = upper_index;
                 self.next_upper = upper_index;
                 self.smoother.set_length(self.len());
+                // the upper index is exclusive, the same as when a deferred change is applied
                 self.resampler
-                    .set_buffer(&self.audio_buffer[self.lower_index..=self.upper_index])
+                    .set_buffer(&self.audio_buffer[self.lower_index..self.upper_index])
             }
         }
     }
@@ -318,58 +544,96 @@ impl Grain {
         }
     }
 
+    /// Set the lower and upper index of the grain together, including logic for locked playback.
+    ///
+    /// The indices are set in whichever order keeps the grain's range valid in between,
+    /// so a grain can be moved to a range which doesn't overlap its current one.
+    pub fn set_bounds(&mut self, lower_index: usize, upper_index: usize) {
+        match lower_index > self.upper_index {
+            true => {
+                self.set_upper_index(upper_index);
+                self.set_lower_index(lower_index);
+            }
+            false => {
+                self.set_lower_index(lower_index);
+                self.set_upper_index(upper_index);
+            }
+        }
+    }
+
+    /// Drops any index changes waiting for the grain to finish its loop, so it keeps its current bounds
+    pub fn cancel_pending_bounds(&mut self) {
+        self.next_lower = self.lower_index;
+        self.next_upper = self.upper_index;
+    }
+
     /// Update the length of the smoother object with the grains current length.
     /// Usually called after a setter method is run.
     pub fn update_smoother(&mut self) {
         self.smoother.set_length(self.len());
     }
 
+    /// Setter for the method used to read the buffer between samples when the grain is pitched
+    pub fn set_interpolation(&mut self, method: ResampleMethod) {
+        self.interpolation = method;
+    }
+
     /// Set the resamplers pitch as a number of semitones
     pub fn set_pitch(&mut self, pitch: i8) {
         self.resampler
             .set_factor(semitone_to_hz_ratio(pitch) as f64);
+        self.update_antialias_cutoff();
     }
 
     /// Set the resamplers pitch as a frequency ratio
     pub fn set_pitch_freq(&mut self, freq: f32) {
         self.resampler.set_factor(freq as f64);
+        self.update_antialias_cutoff();
+    }
+
+    /// Get the resamplers pitch as a frequency ratio
+    pub fn get_pitch_freq(&self) -> f32 {
+        self.resampler.get_pitch_freq() as f32
+    }
+
+    /// Setter for the stereo position of the grain, from -1 (left) to 1 (right)
+    pub fn set_pan(&mut self, pan: f32) {
+        self.pan = pan.clamp(-1.0, 1.0);
+    }
+
+    /// Getter for the stereo position of the grain
+    pub fn get_pan(&self) -> f32 {
+        self.pan
     }
 
     /// Set the grain position in the sample without changing length.
     ///
     /// Abides playback lock setting.
     ///
-    /// If the pos with the current length would put the upper index outside the audio buffer range,
-    /// The position closest to the end with allowed length (last index being last index of audio buffer)
-    /// is used.
+    /// If the pos with the current length would put the grain past the end of the audio buffer,
+    /// the grain is placed against the end of the buffer instead, keeping its length.
     pub fn set_pos(&mut self, pos: usize) {
         let len = self.len();
+        let lower = pos.min(self.audio_buffer.len() - len);
         match self.lock_playback {
             true => {
-                if (pos + len) >= self.audio_buffer.len() - 1 {
-                    self.next_lower = self.audio_buffer.len() - len;
-                    self.next_upper = self.audio_buffer.len() - 1;
-                } else {
-                    self.next_lower = pos;
-                    self.next_upper = pos + len;
-                }
+                self.next_lower = lower;
+                self.next_upper = lower + len;
             }
             false => {
-                if (pos + len) >= self.audio_buffer.len() - 1 {
-                    self.lower_index = self.audio_buffer.len() - len;
-                    self.upper_index = self.audio_buffer.len() - 1;
-                } else {
-                    self.lower_index = pos;
-                    self.upper_index = pos + len;
-                }
+                self.lower_index = lower;
+                self.upper_index = lower + len;
+                self.next_lower = lower;
+                self.next_upper = lower + len;
                 self.resampler
-                    .set_buffer(&self.audio_buffer[self.lower_index..=self.upper_index])
+                    .set_buffer(&self.audio_buffer[self.lower_index..self.upper_index])
             }
         }
     }
 }
 
 /// An enum for storing the different modes of the granular manager and their associated metadata
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum GrainMode {
     /// Playback in order of grain ID, grains are read one at a time
     Sequence,
@@ -386,6 +650,26 @@ pub enum GrainMode {
     Cloud(usize, f32, usize), //(grain length, variation, start_pos)
 }
 
+impl GrainMode {
+    /// The window shape grains use by default in the mode, applied when grains are populated.
+    /// Overlapping cloud grains use a Gaussian window for a smooth texture, other modes use Hann
+    pub fn default_window(&self) -> GrainWindow {
+        match self {
+            GrainMode::Cloud(_, _, _) => GrainWindow::Gaussian,
+            GrainMode::Sequence | GrainMode::Cascade(_, _) => GrainWindow::Hann,
+        }
+    }
+}
+
+/// An enum for how far cloud grains are randomly pitched from the original when populated, in semitones
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PitchSpray {
+    /// Any whole number of semitones up to the spray up or down, drawn uniformly
+    Uniform(i8),
+    /// Exactly the step up, the step down, or the original pitch, such as jumping by octaves with a step of 12
+    Stepped(i8),
+}
+
 /// A struct used to orchestrate and manage multiple grain objects as well as synchronize their playback.
 /// ## Attributes:
 /// * `id_manager`: An instance of `IdManager` used to assign an index to each grain, usually done in sequence
@@ -401,24 +685,80 @@ pub enum GrainMode {
 ///
 /// * `mode`: A grain mode variant, determines how to populate grains and how to read the next sample
 ///
-/// * `makeup_gain`: The output makeup gain of the system,
+/// * `makeup_gain`: A smoother holding the output makeup gain of the system, which the applied gain approaches,
 ///     short grains can cause reduced volume and therefore can be compensated
 ///
-pub struct GrainManager {
+/// * `makeup_smooth_ms`: The time in milliseconds makeup gain changes are smoothed over, 0 means changes are instant
+///
+/// * `sample_rate`: The sample rate in Hz, used to convert smoothing and envelope times into samples
+///
+/// * `scale`: The musical scale which randomized grain pitches are quantized to
+///
+/// * `scale_root`: The midi note of the root of the scale, only the pitch class (note name) is used
+///
+/// * `pitch_pan_spread`: The amount each grain is panned by per octave of its pitch, used by stereo playback
+///
+/// * `buffer_region`: An optional (start, end) window of the audio buffer which all grain indices are confined to
+///
+/// * `spawn_probability`: The chance, from 0 to 1, that a cloud grain plays its next loop rather than staying silent
+///
+/// * `seed`: An optional seed for the random number generator used when populating grains, for reproducible clouds
+///
+/// * `spawn_rng`: The random number generator deciding whether cloud grains respawn, seeded along with `seed`
+///
+/// * `quality`: The quality level deciding how grains interpolate their buffers when pitched
+///
+/// * `pitch_spray`: The `PitchSpray` deciding how far cloud grains are randomly shifted up or down in pitch
+///
+/// * `reverse_probability`: The chance, from 0 to 1, that each cloud grain plays in reverse
+///
+/// * `global_position`: The last position set for every grain by `set_global_position`, if any
+///
+/// * `frozen`: Whether position changes are held back, so the grains keep playing the material they are on
+///
+/// * `fading_in`: Whether the last grain was just added and is fading in, in cloud and cascade modes
+///
+/// * `fading_out`: A grain which was just removed and is fading out, still mixed in until the fade finishes
+///
+/// * `fade_len`: The length in samples of the current fade of an added or removed grain
+///
+/// * `fade_remaining`: The number of samples until the current fade finishes
+///
+pub struct GrainManager<S: AudioSample = i16> {
     id_manager: IdManager,
-    grains: Vec<Grain>,
+    grains: Vec<Grain<S>>,
     grain_index: usize,
     sample_index: usize,
     grain_count: usize,
     mode: GrainMode,
-    makeup_gain: f32,
+    makeup_gain: OnePoleSmoother,
+    makeup_smooth_ms: f32,
+    sample_rate: f32,
     global_pitch: i8,
     env: ADSREnvelope,
+    scale: Scale,
+    scale_root: u8,
+    pitch_pan_spread: f32,
+    buffer_region: Option<(usize, usize)>,
+    spawn_probability: f32,
+    seed: Option<u64>,
+    spawn_rng: StdRng,
+    quality: Quality,
+    pitch_spray: PitchSpray,
+    reverse_probability: f64,
+    global_position: Option<usize>,
+    frozen: bool,
+    fading_in: bool,
+    fading_out: Option<Grain<S>>,
+    fade_len: usize,
+    fade_remaining: usize,
 }
 
-impl Default for GrainManager {
+impl<S: AudioSample> Default for GrainManager<S> {
     /// The default construction of GrainManager
     fn default() -> Self {
+        let mut makeup_gain = OnePoleSmoother::new(0.0, DEFAULT_SAMPLE_RATE);
+        makeup_gain.reset(3.0);
         Self {
             id_manager: IdManager::new(),
             grains: Vec::new(),
@@ -426,14 +766,32 @@ impl Default for GrainManager {
             sample_index: 0,
             grain_count: 0,
             mode: GrainMode::Sequence,
-            makeup_gain: 3.0,
-            global_pitch: 1,
+            makeup_gain,
+            makeup_smooth_ms: 0.0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            global_pitch: 0,
             env: ADSREnvelope::new(2.5, 1.0, 0.75, 2.0),
+            scale: Scale::Chromatic,
+            scale_root: 72,
+            pitch_pan_spread: 0.0,
+            buffer_region: None,
+            spawn_probability: 1.0,
+            seed: None,
+            spawn_rng: StdRng::from_entropy(),
+            quality: Quality::Low,
+            pitch_spray: PitchSpray::Stepped(12),
+            reverse_probability: 0.25,
+            global_position: None,
+            frozen: false,
+            fading_in: false,
+            fading_out: None,
+            fade_len: 0,
+            fade_remaining: 0,
         }
     }
 }
 
-impl GrainManager {
+impl<S: AudioSample> GrainManager<S> {
     /// Constructor that creates a new GrainManager with specified mode
     pub fn new(mode: GrainMode) -> Self {
         Self {
@@ -442,6 +800,18 @@ impl GrainManager {
         }
     }
 
+    /// Constructor that creates a new GrainManager with specified mode, which populates grains from a seeded random
+    /// number generator. Populating with the same seed, grain count, buffer and mode always gives the same grains,
+    /// so cloud layouts can be recalled.
+    pub fn new_seeded(mode: GrainMode, seed: u64) -> Self {
+        Self {
+            mode,
+            seed: Some(seed),
+            spawn_rng: StdRng::seed_from_u64(seed),
+            ..Default::default()
+        }
+    }
+
     /// Function to populate the grains buffer with a number of grains, all from the same audio buffer,
     ///     by a specified mode, including that modes individual metadata
     ///
@@ -449,69 +819,385 @@ impl GrainManager {
     pub fn populate_grains(
         &mut self,
         grain_count: usize,
-        audio_buffer: &'static Vec<i16>,
+        audio_buffer: &'static Vec<S>,
         mode: GrainMode,
     ) {
         self.env.setup();
+        self.id_manager.reset();
+        self.finish_fade();
         self.grains = (0..grain_count)
-            .map(|_| Grain::new(audio_buffer, self.id_manager.get_next_id(), grain_count, 0))
+            .map(|_| {
+                let mut grain =
+                    Grain::new(audio_buffer, self.id_manager.get_next_id(), grain_count, 0);
+                grain.set_window(mode.default_window());
+                grain
+            })
             .collect();
+        self.set_quality(self.quality);
+        // the new grains play at the original pitch
+        self.global_pitch = 0;
+        self.configure_grains(mode, audio_buffer.len(), 0);
+        self.grain_count = self.grains.len();
+        self.mode = mode;
+    }
+
+    /// Adds a single grain to the end of the sequence without rebuilding the others, such as to raise the density
+    /// of a cloud while it plays. The grain takes the same settings it would have if it had been populated.
+    ///
+    /// A cloud grain is placed by itself, while in sequence and cascade modes every grain is laid out again
+    /// to share the range, with playing grains finishing their current loop first. Has no effect before populating.
+    ///
+    /// In cloud and cascade modes the new grain fades in over `GRAIN_FADE_MS`, so the level of the mix doesn't step.
+    pub fn add_grain(&mut self) {
+        let Some(audio_buffer) = self.grains.first().map(|grain| grain.audio_buffer) else {
+            return;
+        };
+        self.finish_fade();
+        let grain_count = self.grains.len() + 1;
+        let mut grain = Grain::new(audio_buffer, self.id_manager.get_next_id(), grain_count, 0);
+        grain.set_window(self.mode.default_window());
+        grain.set_interpolation(self.quality.resample_method());
+        self.grains.push(grain);
+        self.relink_grains();
+
+        let first = match self.mode {
+            GrainMode::Cloud(_, _, _) => grain_count - 1,
+            GrainMode::Sequence | GrainMode::Cascade(_, _) => 0,
+        };
+        self.configure_grains(self.mode, audio_buffer.len(), first);
+        // the grain picks up the global pitch on top of its own
+        let grain = &mut self.grains[grain_count - 1];
+        let ratio = semitone_to_hz_ratio(self.global_pitch);
+        grain.set_pitch_freq(grain.get_pitch_freq() * ratio);
+
+        if self.mode != GrainMode::Sequence {
+            self.fading_in = true;
+            self.start_fade();
+        }
+    }
+
+    /// Removes the last grain in the sequence, stopping it immediately, such as to lower the density of a cloud.
+    ///
+    /// In sequence and cascade modes the remaining grains are laid out again to share the range.
+    /// The last grain is never removed, so the manager can keep playing.
+    ///
+    /// In cloud and cascade modes the removed grain keeps playing as it fades out over `GRAIN_FADE_MS`,
+    /// so the level of the mix doesn't step.
+    pub fn remove_grain(&mut self) {
+        if self.grains.len() <= 1 {
+            return;
+        }
+        self.finish_fade();
+        let removed = self.grains.pop();
+        if self.mode != GrainMode::Sequence {
+            self.fading_out = removed;
+            self.start_fade();
+        }
+        self.id_manager.release_id();
+        self.relink_grains();
+
+        // the sequence was partway through the removed grain, so it starts again from the first
+        if self.grain_index >= self.grains.len() {
+            self.grain_index = 0;
+            self.sample_index = 0;
+        }
+        if let GrainMode::Sequence | GrainMode::Cascade(_, _) = self.mode {
+            let buffer_len = self.grains[0].audio_buffer.len();
+            self.configure_grains(self.mode, buffer_len, 0);
+        }
+    }
+
+    /// Starts the fade of a grain which was just added or removed
+    fn start_fade(&mut self) {
+        self.fade_len = ((GRAIN_FADE_MS * 0.001 * self.sample_rate) as usize).max(1);
+        self.fade_remaining = self.fade_len;
+    }
+
+    /// Ends any fade of an added or removed grain immediately, such as before another grain is added or removed
+    fn finish_fade(&mut self) {
+        self.fading_in = false;
+        self.fading_out = None;
+        self.fade_remaining = 0;
+    }
+
+    /// Advances the fade of an added or removed grain by a sample.
+    /// Returns the weight of the last grain in the mix, which is below 1 while it fades in,
+    /// and the weight of the grain fading out, which is 0 once there is none.
+    fn next_fade_weights(&mut self) -> (f32, f32) {
+        if self.fade_remaining == 0 {
+            return (1.0, 0.0);
+        }
+        let fraction = self.fade_remaining as f32 / self.fade_len as f32;
+        let weights = (
+            match self.fading_in {
+                true => 1.0 - fraction,
+                false => 1.0,
+            },
+            match self.fading_out {
+                Some(_) => fraction,
+                None => 0.0,
+            },
+        );
+
+        self.fade_remaining -= 1;
+        if self.fade_remaining == 0 {
+            self.finish_fade();
+        }
+        weights
+    }
+
+    /// Updates the grain count and the sequence wrapping of every grain after grains are added or removed
+    fn relink_grains(&mut self) {
+        self.grain_count = self.grains.len();
+        for grain in self.grains.iter_mut() {
+            grain.set_index_mod(self.grain_count);
+        }
+    }
+
+    /// Switches the grain mode, reconfiguring the indices of the existing grains for the new mode rather than
+    /// repopulating them, so playback carries on and modes can be morphed live.
+    ///
+    /// Grains partway through a loop finish it with their current indices before picking up the new ones,
+    /// the same as any locked index change. Sequence mode plays one grain at a time from its start,
+    /// so switching into it restarts the grains. Switching to cloud mode randomizes the grains as populating does,
+    /// and switching away from it returns them to their original pitch, playing forwards.
+    pub fn set_mode(&mut self, mode: GrainMode) {
+        let Some(buffer_len) = self.grains.first().map(|grain| grain.audio_buffer.len()) else {
+            self.mode = mode;
+            return;
+        };
+        self.finish_fade();
+
+        if let GrainMode::Cloud(_, _, _) = self.mode {
+            for grain in self.grains.iter_mut() {
+                grain.set_pitch(0);
+                grain.set_reverse(false);
+                grain.silent = false;
+            }
+        }
+        if mode == GrainMode::Sequence && self.mode != GrainMode::Sequence {
+            for grain in self.grains.iter_mut() {
+                grain.seek(0.0);
+            }
+            self.grain_index = 0;
+            self.sample_index = 0;
+        }
+
+        self.configure_grains(mode, buffer_len, 0);
+        self.mode = mode;
+    }
+
+    /// Sets the indices of the grains from index `first` onwards for a mode,
+    /// confined to the buffer region of a buffer of length `buffer_len`.
+    ///
+    /// In cloud mode the random settings of the earlier grains are still drawn, so a seeded cloud places
+    /// a grain the same way whether it was populated or added later.
+    fn configure_grains(&mut self, mode: GrainMode, buffer_len: usize, first: usize) {
+        let (region_start, region_end) = self.region_bounds(buffer_len);
+        let grain_count = self.grains.len();
 
         match mode {
             GrainMode::Sequence => {
-                let grain_len = audio_buffer.len() / grain_count;
+                let grain_len = (region_end - region_start) / grain_count;
 
-                (0..grain_count).for_each(|index| {
+                (first..grain_count).for_each(|index| {
                     let grain = &mut self.grains[index];
-                    grain.set_lower_index(index * grain_len);
-                    grain.set_upper_index((index + 1) * grain_len);
-                    grain.update_smoother();
-                    grain.lock_playback();
+                    Self::reconfigure_grain(grain, |grain| {
+                        grain.set_bounds(
+                            region_start + index * grain_len,
+                            region_start + (index + 1) * grain_len,
+                        );
+                        grain.set_looping(false);
+                    });
                 });
             }
             GrainMode::Cloud(grain_len, variation, start_index) => {
-                let mut rng = thread_rng();
+                let mut rng = match self.seed {
+                    Some(seed) => StdRng::seed_from_u64(seed),
+                    None => StdRng::from_entropy(),
+                };
+                let intervals = self.scale.intervals();
+                // pitch class of the root relative to the assumed original pitch of C
+                let root_offset = (self.scale_root % 12) as i8;
+                // keep the start far enough from the end of the region to fit a whole grain
+                let start_index = start_index.clamp(
+                    region_start,
+                    region_end.saturating_sub(grain_len).max(region_start),
+                );
                 (0..grain_count).for_each(|index| {
                     let variation_depth: f32 = rng.gen();
-                    let grain = &mut self.grains[index];
-                    let lower = start_index.saturating_sub(
-                        (variation * 0.5 * variation_depth * (grain_len as f32)) as usize,
-                    );
-                    let mut upper = start_index.saturating_add(
-                        grain_len
-                            + (variation * 0.5 * variation_depth * (grain_len as f32)) as usize,
-                    );
-                    if upper > grain.audio_buffer.len() {
-                        upper = grain.audio_buffer.len();
+                    let lower = start_index
+                        .saturating_sub(
+                            (variation * 0.5 * variation_depth * (grain_len as f32)) as usize,
+                        )
+                        .max(region_start);
+                    let upper = start_index
+                        .saturating_add(
+                            grain_len
+                                + (variation * 0.5 * variation_depth * (grain_len as f32)) as usize,
+                        )
+                        .min(region_end);
+
+                    let spray = match self.pitch_spray {
+                        PitchSpray::Uniform(semitones) => rng.gen_range(-semitones..=semitones),
+                        PitchSpray::Stepped(semitones) => {
+                            *[-semitones, 0, semitones].choose(&mut rng).unwrap()
+                        }
+                    };
+                    let pitch = quantize_semitones(spray - root_offset, intervals) + root_offset;
+                    let reverse = rng.gen_bool(self.reverse_probability);
+                    if index < first {
+                        return;
                     }
-
-                    let octave: i8 = *[-1, 0, 1].choose(&mut rng).unwrap();
-                    grain.set_lower_index(lower);
-                    grain.set_upper_index(upper);
-                    grain.set_looping(true);
-                    grain.set_reverse(rng.gen_bool(0.25));
-                    grain.set_pitch(12 * octave);
-                    grain.update_smoother();
-                    grain.lock_playback();
+                    Self::reconfigure_grain(&mut self.grains[index], |grain| {
+                        grain.set_bounds(lower, upper);
+                        grain.set_looping(true);
+                        grain.set_reverse(reverse);
+                        grain.set_pitch(pitch);
+                    });
                 });
             }
             GrainMode::Cascade(lower, upper) => {
-                (0..grain_count).for_each(|index| {
+                let lower = lower.clamp(region_start, region_end);
+                let upper = upper.clamp(lower, region_end);
+                (first..grain_count).for_each(|index| {
                     let grain_len = (upper - lower) / grain_count;
                     let grain = &mut self.grains[index];
-                    grain.set_lower_index((index * grain_len) + lower);
-                    grain.set_upper_index(upper);
-                    grain.set_looping(true);
-                    grain.update_smoother();
-                    grain.lock_playback();
+                    Self::reconfigure_grain(grain, |grain| {
+                        grain.set_bounds((index * grain_len) + lower, upper);
+                        grain.set_looping(true);
+                    });
                 });
             }
         }
-        self.grain_count = self.grains.len();
+    }
+
+    /// Applies a change of settings to a grain, then locks its playback.
+    ///
+    /// A grain at the start of a loop takes the change immediately, otherwise its index changes wait until
+    /// it finishes the loop, avoiding a jump in the read position.
+    fn reconfigure_grain(grain: &mut Grain<S>, configure: impl FnOnce(&mut Grain<S>)) {
+        if grain.at_loop_start() {
+            grain.unlock_playback();
+        }
+        configure(grain);
+        grain.update_smoother();
+        grain.lock_playback();
+    }
+
+    /// Setter for the region of the audio buffer which grains are drawn from, such as to skip silence at the start.
+    /// Takes effect the next time the grains are populated, and is limited to the length of the buffer.
+    pub fn set_buffer_region(&mut self, start: usize, end: usize) {
+        self.buffer_region = Some((start.min(end), start.max(end)));
+    }
+
+    /// Removes the buffer region, so grains can be drawn from anywhere in the audio buffer
+    pub fn clear_buffer_region(&mut self) {
+        self.buffer_region = None;
+    }
+
+    /// The (start, end) of the buffer region limited to a buffer of length `buffer_len`,
+    /// or the whole buffer if no region is set
+    fn region_bounds(&self, buffer_len: usize) -> (usize, usize) {
+        match self.buffer_region {
+            Some((start, end)) => (start.min(buffer_len), end.min(buffer_len)),
+            None => (0, buffer_len),
+        }
+    }
+
+    /// Setter for the pitch spray of cloud grains in semitones, clamped between 0 and 24.
+    ///
+    /// Each grain is pitched by a random whole number of semitones within the spray either side,
+    /// before being quantized to the scale. Takes effect the next time grains are populated.
+    pub fn set_pitch_spray(&mut self, semitones: i8) {
+        self.pitch_spray = PitchSpray::Uniform(semitones.clamp(0, 24));
+    }
+
+    /// Setter for a stepped pitch spray of cloud grains in semitones, clamped between 0 and 24.
+    ///
+    /// Each grain is randomly pitched up by the step, down by it, or left at the original pitch, before being
+    /// quantized to the scale. The default is a step of 12, jumping grains by octaves.
+    /// Takes effect the next time grains are populated.
+    pub fn set_pitch_spray_steps(&mut self, semitones: i8) {
+        self.pitch_spray = PitchSpray::Stepped(semitones.clamp(0, 24));
+    }
+
+    /// Setter for the probability that each cloud grain plays in reverse, clamped between 0 and 1.
+    /// The default is 0.25. Takes effect the next time grains are populated.
+    pub fn set_reverse_probability(&mut self, probability: f64) {
+        self.reverse_probability = probability.clamp(0.0, 1.0);
+    }
+
+    /// Moves every grain to start at a position in the audio buffer, keeping their lengths.
+    ///
+    /// Playing grains finish their current loop before moving, so each grain's window crossfades it
+    /// to the new material. While frozen the position is stored and applied when the grains are unfrozen.
+    pub fn set_global_position(&mut self, pos: usize) {
+        self.global_position = Some(pos);
+        if !self.frozen {
+            for grain in self.grains.iter_mut() {
+                grain.set_pos(pos);
+            }
+        }
+    }
+
+    /// Freezes the grains on the material they are playing, holding back any changes from `set_global_position`.
+    /// Freezing also cancels any move a grain is still waiting to make at the end of its loop.
+    /// Unfreezing moves the grains to the last position set while frozen.
+    pub fn freeze(&mut self, on: bool) {
+        self.frozen = on;
+        match (on, self.global_position) {
+            (true, _) => {
+                for grain in self.grains.iter_mut() {
+                    grain.cancel_pending_bounds();
+                }
+            }
+            (false, Some(pos)) => self.set_global_position(pos),
+            (false, None) => (),
+        }
+    }
+
+    /// Setter for the probability that a cloud grain respawns each time it finishes a loop.
+    ///
+    /// A grain which doesn't respawn stays silent for its next loop, then is checked again,
+    /// so low probabilities give sparse, pointillistic clouds. A probability of 1 keeps every grain playing.
+    pub fn set_spawn_probability(&mut self, probability: f32) {
+        self.spawn_probability = probability.clamp(0.0, 1.0);
+    }
+
+    /// The index in the audio buffer each grain is reading, in grain order, such as to draw playback cursors
+    pub fn positions(&self) -> Vec<usize> {
+        self.grains
+            .iter()
+            .map(|grain| grain.buffer_position())
+            .collect()
+    }
+
+    /// The number of grains which are currently playing rather than silent
+    pub fn active_grain_count(&self) -> usize {
+        self.grains.iter().filter(|grain| !grain.silent).count()
+    }
+
+    /// Reads the next sample from a cloud grain, deciding whether the grain respawns each time it finishes a loop.
+    /// Silent grains still advance, and return 0.
+    fn next_cloud_grain_sample(
+        grain: &mut Grain<S>,
+        spawn_probability: f32,
+        spawn_rng: &mut StdRng,
+    ) -> S {
+        let value = grain.get_next_sample(true);
+        let silent = grain.silent;
+        if spawn_probability < 1.0 && grain.at_loop_start() {
+            grain.silent = !spawn_rng.gen_bool(spawn_probability as f64);
+        }
+        match silent {
+            true => S::default(),
+            false => value,
+        }
     }
 
     /// Get the grain as specified by the current grains `next_id` field, potentially the same grain
-    pub fn read_next_grain(&mut self) -> &mut Grain {
+    pub fn read_next_grain(&mut self) -> &mut Grain<S> {
         let grain = &mut self.grains[self.grain_index];
         self.grain_index = grain.next_id;
 
@@ -519,17 +1205,67 @@ impl GrainManager {
     }
 
     /// Setter for the global pitch shift in semitones, relative to the original pitch of the sample.
+    /// Replaces the previous global shift rather than adding to it, keeping each grain's own pitch offset.
     pub fn set_global_pitch(&mut self, pitch: i8) {
+        let ratio = semitone_to_hz_ratio(pitch) / semitone_to_hz_ratio(self.global_pitch);
         self.global_pitch = pitch;
         for grain in self.grains.iter_mut() {
             let grain_pitch = grain.resampler.get_pitch_freq();
-            grain.set_pitch_freq(grain_pitch as f32 * semitone_to_hz_ratio(self.global_pitch))
+            grain.set_pitch_freq(grain_pitch as f32 * ratio)
         }
     }
 
-    /// Setter for managers makeup gain
+    /// Getter for the global pitch shift in semitones
+    pub fn get_global_pitch(&self) -> i8 {
+        self.global_pitch
+    }
+
+    /// Sets the global pitch so that the sample, assumed to be a C5, plays at the pitch of a midi note
+    pub fn set_pitch_from_note(&mut self, note: u8) {
+        self.set_global_pitch(note_to_semitones(note));
+    }
+
+    /// Setter for the quality level, which decides the interpolation used by every grain when pitched
+    pub fn set_quality(&mut self, quality: Quality) {
+        self.quality = quality;
+        for grain in self.grains.iter_mut() {
+            grain.set_interpolation(quality.resample_method());
+        }
+    }
+
+    /// Setter for the scale which randomized grain pitches are snapped to.
+    ///
+    /// The root is given as a midi note, of which only the note name is used (72 and 60 are both C).
+    /// Takes effect the next time grains are populated. `Scale::Chromatic` disables quantization.
+    pub fn set_scale(&mut self, root: u8, scale: Scale) {
+        self.scale_root = root;
+        self.scale = scale;
+    }
+
+    /// Setter for managers makeup gain.
+    /// The applied gain moves towards this value over the time set by `set_makeup_gain_smooth_time`
     pub fn set_makeup_gain(&mut self, gain: f32) {
-        self.makeup_gain = gain;
+        self.makeup_gain.set_target(gain);
+    }
+
+    /// Setter for the time in milliseconds taken for makeup gain changes to take effect.
+    /// Smoothing avoids zipper noise when the gain is automated, a time of 0 makes changes instant.
+    pub fn set_makeup_gain_smooth_time(&mut self, ms: f32) {
+        self.makeup_smooth_ms = ms;
+        self.makeup_gain.set_smoothing_time(ms, self.sample_rate);
+    }
+
+    /// Setter for the sample rate in Hz, keeping the makeup gain smoothing and envelope times in milliseconds and seconds
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.makeup_gain
+            .set_smoothing_time(self.makeup_smooth_ms, sample_rate);
+        self.env.set_sample_rate(sample_rate);
+    }
+
+    /// Advances the smoothed makeup gain one sample towards the target makeup gain and returns it
+    fn next_makeup_gain(&mut self) -> f32 {
+        self.makeup_gain.next()
     }
 
     /// Get the next sample from the current grain or grains.
@@ -537,16 +1273,27 @@ impl GrainManager {
     ///
     /// In cascade mode, returns the next sample from all grains at once, mixed by average
     ///
-    /// In cloud mode, returns the next sample from all grains at once, mixed by average
+    /// In cloud mode, returns the next sample from all grains at once, mixed by average.
+    /// In both, a grain which was just added or removed is weighted by its fade in the average
     ///
     /// All samples are multiplied by makeup gain.
-    pub fn get_next_sample(&mut self) -> i16 {
+    pub fn get_next_sample(&mut self) -> S {
+        S::from_f32(self.get_next_sample_f32())
+    }
+
+    /// Get the next sample from the current grain or grains as an f32, the same as `get_next_sample`
+    /// but without converting back to the sample type of the buffer.
+    ///
+    /// Grains are mixed and scaled in f32, so the output isn't rounded or clipped, such as for further processing
+    /// before it is written out.
+    pub fn get_next_sample_f32(&mut self) -> f32 {
+        let makeup_gain = self.next_makeup_gain();
         let value = match self.mode {
             GrainMode::Sequence => {
                 if self.sample_index < (self.grains[self.grain_index].len() - 1) {
                     let value = self.grains[self.grain_index].get_next_sample(true);
                     self.sample_index += 1;
-                    value
+                    value.to_f32()
                 } else {
                     self.grains[self.grain_index].set_sample_index(0);
                     self.read_next_grain();
@@ -554,25 +1301,117 @@ impl GrainManager {
 
                     let value = self.grains[self.grain_index].get_next_sample(true);
                     self.sample_index += 1;
-                    (value as f32 * self.makeup_gain) as i16
+                    value.to_f32()
                 }
             }
-            GrainMode::Cloud(_, _, _) => {
-                let mut output: i16 = 0;
-                for grain in self.grains.iter_mut() {
-                    output += grain.get_next_sample(true) / self.grain_count as i16;
+            GrainMode::Cloud(_, _, _) | GrainMode::Cascade(_, _) => {
+                let is_cloud = matches!(self.mode, GrainMode::Cloud(_, _, _));
+                let (in_weight, out_weight) = self.next_fade_weights();
+                let last = self.grains.len() - 1;
+                // averaged by the total weight, so a fading grain moves the level smoothly
+                let divisor = last as f32 + in_weight + out_weight;
+                let mut output = 0.0;
+                for (index, grain) in self
+                    .grains
+                    .iter_mut()
+                    .chain(self.fading_out.iter_mut())
+                    .enumerate()
+                {
+                    let sample = match is_cloud {
+                        true => Self::next_cloud_grain_sample(
+                            grain,
+                            self.spawn_probability,
+                            &mut self.spawn_rng,
+                        ),
+                        false => grain.get_next_sample(true),
+                    };
+                    let weight = match index.cmp(&last) {
+                        Ordering::Less => 1.0,
+                        Ordering::Equal => in_weight,
+                        Ordering::Greater => out_weight,
+                    };
+                    output += sample.to_f32() * weight / divisor;
                 }
-                (output as f32 * self.makeup_gain) as i16
+                output
             }
-            GrainMode::Cascade(_, _) => {
-                let mut output: i16 = 0;
-                for grain in self.grains.iter_mut() {
-                    output += grain.get_next_sample(true) / self.grain_count as i16;
+        };
+        // makeup gain is applied to every sample, whichever grain or mode it came from
+        value * makeup_gain * self.env.get_next_sample()
+    }
+
+    /// Setter for the amount grains are auto-panned by their pitch.
+    ///
+    /// Each grain is moved from its own pan position by `amount` per octave above the original pitch,
+    /// so higher grains sit to the right and lower grains to the left. An amount of 0 disables it,
+    /// and negative amounts reverse the direction.
+    pub fn set_pitch_pan_spread(&mut self, amount: f32) {
+        self.pitch_pan_spread = amount;
+    }
+
+    /// Get the next (left, right) sample pair from the current grain or grains.
+    ///
+    /// Follows the same playback as `get_next_sample`, but places each grain in the stereo field
+    /// by its own pan position plus the pitch driven pan spread, using an equal power pan law.
+    ///
+    /// All samples are multiplied by makeup gain.
+    pub fn get_next_stereo_sample(&mut self) -> (S, S) {
+        let makeup_gain = self.next_makeup_gain();
+        let spread = self.pitch_pan_spread;
+        // pan offset by the number of octaves the grain is pitched from the original
+        let grain_gains =
+            |grain: &Grain<S>| equal_power_pan(grain.pan + spread * grain.get_pitch_freq().log2());
+
+        let (left, right) = match self.mode {
+            GrainMode::Sequence => {
+                if self.sample_index >= (self.grains[self.grain_index].len() - 1) {
+                    self.grains[self.grain_index].set_sample_index(0);
+                    self.read_next_grain();
+                    self.sample_index = 0;
                 }
-                (output as f32 * self.makeup_gain) as i16
+                let grain = &mut self.grains[self.grain_index];
+                let value = grain.get_next_sample(true).to_f32();
+                self.sample_index += 1;
+
+                let (left_gain, right_gain) = grain_gains(grain);
+                (value * left_gain, value * right_gain)
+            }
+            GrainMode::Cloud(_, _, _) | GrainMode::Cascade(_, _) => {
+                let is_cloud = matches!(self.mode, GrainMode::Cloud(_, _, _));
+                let (in_weight, out_weight) = self.next_fade_weights();
+                let last = self.grains.len() - 1;
+                let divisor = last as f32 + in_weight + out_weight;
+                let mut left = 0.0;
+                let mut right = 0.0;
+                for (index, grain) in self
+                    .grains
+                    .iter_mut()
+                    .chain(self.fading_out.iter_mut())
+                    .enumerate()
+                {
+                    let sample = match is_cloud {
+                        true => Self::next_cloud_grain_sample(
+                            grain,
+                            self.spawn_probability,
+                            &mut self.spawn_rng,
+                        ),
+                        false => grain.get_next_sample(true),
+                    };
+                    let weight = match index.cmp(&last) {
+                        Ordering::Less => 1.0,
+                        Ordering::Equal => in_weight,
+                        Ordering::Greater => out_weight,
+                    };
+                    let value = sample.to_f32() * weight / divisor;
+                    let (left_gain, right_gain) = grain_gains(grain);
+                    left += value * left_gain;
+                    right += value * right_gain;
+                }
+                (left, right)
             }
         };
-        (value as f32 * self.env.get_next_sample()) as i16
+
+        let gain = makeup_gain * self.env.get_next_sample();
+        (S::from_f32(left * gain), S::from_f32(right * gain))
     }
 
     /// Triggers the gate of the instances envelope with an on off boolean
@@ -601,17 +1440,613 @@ impl GrainManager {
     }
 }
 
+/// Struct which plays two grain managers at once and crossfades between them,
+/// such as morphing a sequence of grains into a cloud of them.
+///
+/// Both layers keep playing whatever the morph amount, so moving it fades between two running textures
+/// rather than restarting either one.
+/// ## Attributes:
+/// * `layer_a`: The grain manager heard at a morph of 0
+///
+/// * `layer_b`: The grain manager heard at a morph of 1
+///
+/// * `morph`: A `OnePoleSmoother` moving the applied morph amount towards its target, from 0 (only layer a) to 1 (only layer b)
+pub struct GrainMorph {
+    layer_a: GrainManager,
+    layer_b: GrainManager,
+    morph: OnePoleSmoother,
+}
+
+impl GrainMorph {
+    /// Constructor for the morph given the mode of each layer, starting fully on layer a
+    pub fn new(mode_a: GrainMode, mode_b: GrainMode) -> Self {
+        Self {
+            layer_a: GrainManager::new(mode_a),
+            layer_b: GrainManager::new(mode_b),
+            morph: OnePoleSmoother::new(MORPH_SMOOTH_MS, DEFAULT_SAMPLE_RATE),
+        }
+    }
+
+    /// Setter for the sample rate in Hz of both layers and the morph smoothing
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.layer_a.set_sample_rate(sample_rate);
+        self.layer_b.set_sample_rate(sample_rate);
+        self.morph.set_smoothing_time(MORPH_SMOOTH_MS, sample_rate);
+    }
+
+    /// Populates both layers with a number of grains from the same audio buffer, each in its own mode
+    pub fn populate_grains(&mut self, grain_count: usize, audio_buffer: &'static Vec<i16>) {
+        let (mode_a, mode_b) = (self.layer_a.mode, self.layer_b.mode);
+        self.layer_a
+            .populate_grains(grain_count, audio_buffer, mode_a);
+        self.layer_b
+            .populate_grains(grain_count, audio_buffer, mode_b);
+    }
+
+    /// Setter for the morph amount, from 0 (only layer a) to 1 (only layer b)
+    pub fn set_morph(&mut self, amount: f32) {
+        self.morph.set_target(amount.clamp(0.0, 1.0));
+    }
+
+    /// Mutable getter for the layer heard at a morph of 0, for changing its settings
+    pub fn layer_a_mut(&mut self) -> &mut GrainManager {
+        &mut self.layer_a
+    }
+
+    /// Mutable getter for the layer heard at a morph of 1, for changing its settings
+    pub fn layer_b_mut(&mut self) -> &mut GrainManager {
+        &mut self.layer_b
+    }
+
+    /// Triggers the gate of both layers envelopes with an on off boolean
+    pub fn gate_trigger(&mut self, on_off: bool) {
+        self.layer_a.gate_trigger(on_off);
+        self.layer_b.gate_trigger(on_off);
+    }
+
+    /// Advances the smoothed morph one sample towards the target, returning the gains of (layer a, layer b)
+    fn next_gains(&mut self) -> (f32, f32) {
+        let morph = self.morph.next();
+        // an equal power crossfade keeps the level steady between the two textures
+        equal_power_pan(2.0 * morph - 1.0)
+    }
+
+    /// Get the next sample of both layers, crossfaded by the morph amount
+    pub fn get_next_sample(&mut self) -> i16 {
+        let (gain_a, gain_b) = self.next_gains();
+        let a = self.layer_a.get_next_sample() as f32;
+        let b = self.layer_b.get_next_sample() as f32;
+        (a * gain_a + b * gain_b) as i16
+    }
+
+    /// Get the next (left, right) sample pair of both layers, crossfaded by the morph amount
+    pub fn get_next_stereo_sample(&mut self) -> (i16, i16) {
+        let (gain_a, gain_b) = self.next_gains();
+        let (left_a, right_a) = self.layer_a.get_next_stereo_sample();
+        let (left_b, right_b) = self.layer_b.get_next_stereo_sample();
+        (
+            (left_a as f32 * gain_a + left_b as f32 * gain_b) as i16,
+            (right_a as f32 * gain_a + right_b as f32 * gain_b) as i16,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::delay_line::StereoDelay;
-    use crate::grain::{Grain, GrainManager, GrainMode};
+    use crate::grain::{Grain, GrainManager, GrainMode, GrainMorph};
     use crate::lfo::{LFOMode, MMLFO};
     use crate::multi_channel::MultiDelayLine;
+    use crate::resample::semitone_to_hz_ratio;
     use crate::samples::{IntSamples, PhonicMode, Samples};
-    use crate::smoothers::NoSmoother;
+    use crate::smoothers::{GrainWindow, NoSmoother};
     use crate::{distribute_exponential, load_wav, write_wav};
     use ndarray::arr1;
     use once_cell::sync::Lazy;
+    use std::f32::consts::PI;
+
+    /// Renders a second of stereo output from a cascade of grains all pitched by `pitch` semitones,
+    /// returning the energy of the (left, right) channels.
+    fn pitched_stereo_energy(pitch: i8, spread: f32) -> (f32, f32) {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| {
+            (0..88200)
+                .map(|n| ((n as f32 * 0.05).sin() * 8000.0) as i16)
+                .collect()
+        });
+
+        let mut manager = GrainManager::new(GrainMode::Cascade(0, 0));
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Cascade(0, 44100));
+        manager.set_attack(0.0);
+        manager.set_sustain(1.0);
+        manager.set_makeup_gain(1.0);
+        manager.set_pitch_pan_spread(spread);
+        for grain in manager.grains.iter_mut() {
+            grain.set_pitch(pitch);
+        }
+        manager.gate_trigger(true);
+
+        let mut left_energy = 0.0;
+        let mut right_energy = 0.0;
+        for _ in 0..44100 {
+            let (left, right) = manager.get_next_stereo_sample();
+            left_energy += (left as f32).powi(2);
+            right_energy += (right as f32).powi(2);
+        }
+        (left_energy, right_energy)
+    }
+
+    #[test]
+    fn test_pitch_pan_spread() {
+        let (high_left, high_right) = pitched_stereo_energy(12, 0.8);
+        assert!(high_right > high_left * 4.0);
+
+        let (low_left, low_right) = pitched_stereo_energy(-12, 0.8);
+        assert!(low_left > low_right * 4.0);
+
+        let (center_left, center_right) = pitched_stereo_energy(12, 0.0);
+        assert!((center_left - center_right).abs() < center_left * 0.01);
+    }
+
+    /// Average number of active grains in a cloud over a second of playback with a given spawn probability,
+    /// with every grain shifted by a global pitch in semitones
+    fn average_active_grains(probability: f32, global_pitch: i8) -> f32 {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 20000]);
+
+        let mut manager = GrainManager::new_seeded(GrainMode::Cloud(200, 0.0, 5000), 7);
+        manager.populate_grains(32, &AUDIO_BUFFER, GrainMode::Cloud(200, 0.0, 5000));
+        manager.set_global_pitch(global_pitch);
+        manager.set_spawn_probability(probability);
+
+        let mut total = 0;
+        for _ in 0..44100 {
+            manager.get_next_sample();
+            total += manager.active_grain_count();
+        }
+        total as f32 / 44100.0
+    }
+
+    #[test]
+    fn test_spawn_probability() {
+        assert_eq!(average_active_grains(1.0, 0), 32.0);
+
+        // after the first loop, roughly a tenth of the grains respawn each time they are checked,
+        // including grains pitched so their read position never lands exactly on the start of a loop
+        for global_pitch in [0, 7] {
+            let sparse = average_active_grains(0.1, global_pitch);
+            assert!(
+                (1.0..6.0).contains(&sparse),
+                "average active grains {sparse} pitched by {global_pitch}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_seeded_cloud() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![0; 20000]);
+        let mode = || GrainMode::Cloud(1000, 2.0, 8000);
+
+        let mut first = GrainManager::new_seeded(mode(), 42);
+        let mut second = GrainManager::new_seeded(mode(), 42);
+        first.populate_grains(16, &AUDIO_BUFFER, mode());
+        second.populate_grains(16, &AUDIO_BUFFER, mode());
+
+        for (a, b) in first.grains.iter().zip(second.grains.iter()) {
+            assert_eq!(a.lower_index, b.lower_index);
+            assert_eq!(a.upper_index, b.upper_index);
+            assert_eq!(a.get_pitch_freq(), b.get_pitch_freq());
+            assert_eq!(a.reverse, b.reverse);
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| (0..2000).collect());
+
+        let mut grain = Grain::new(&AUDIO_BUFFER, 0, 1, 0);
+        grain.set_lower_index(200);
+        grain.set_upper_index(1200);
+        assert_eq!(grain.progress(), 0.0);
+
+        grain.set_progress(0.5);
+        assert!((grain.progress() - 0.5).abs() < 0.01);
+        // halfway through the grain's region of the buffer
+        assert!((grain.get_next_sample(false) - 700).abs() <= 1);
+
+        // when locked, seeking waits for the current loop to finish
+        grain.lock_playback();
+        grain.set_progress(0.25);
+        assert!((grain.progress() - 0.5).abs() < 0.01);
+        let mut previous = grain.progress();
+        loop {
+            grain.get_next_sample(false);
+            if grain.progress() < previous {
+                break;
+            }
+            previous = grain.progress();
+        }
+        assert!((grain.progress() - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_buffer_positions() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| (0..20003).map(|n| n as i16).collect());
+        let mode = GrainMode::Cascade(4000, 8000);
+        let mut manager = GrainManager::new(mode);
+        manager.populate_grains(4, &AUDIO_BUFFER, mode);
+        assert_eq!(manager.positions(), vec![4000, 5000, 6000, 7000]);
+
+        for _ in 0..500 {
+            manager.get_next_sample();
+        }
+        assert_eq!(manager.positions(), vec![4500, 5500, 6500, 7500]);
+
+        // a reversed grain reads backwards from its upper index
+        let grain = &mut manager.grains[0];
+        grain.unlock_playback();
+        grain.set_progress(0.0);
+        grain.set_reverse(true);
+        grain.set_progress(0.25);
+        assert!((grain.buffer_position() as i64 - 7000).abs() <= 1);
+        assert!((grain.progress() - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_float_output() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![100; 20003]);
+        let mode = GrainMode::Cascade(0, 20000);
+        let mut float_manager = GrainManager::new(mode);
+        float_manager.populate_grains(64, &AUDIO_BUFFER, mode);
+        let mut int_manager = GrainManager::new(mode);
+        int_manager.populate_grains(64, &AUDIO_BUFFER, mode);
+        float_manager.gate_trigger(true);
+        int_manager.gate_trigger(true);
+
+        // the float output keeps the detail of a dense mix which rounds away as i16
+        let float_output: Vec<f32> = (0..10000)
+            .map(|_| float_manager.get_next_sample_f32())
+            .collect();
+        let int_output: Vec<i16> = (0..10000).map(|_| int_manager.get_next_sample()).collect();
+        assert!(float_output.iter().any(|sample| sample.fract() != 0.0));
+        for (float_sample, int_sample) in float_output.iter().zip(int_output.iter()) {
+            assert_eq!(*float_sample as i16, *int_sample);
+        }
+    }
+
+    #[test]
+    fn test_freeze_position() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 20000]);
+        let mode = GrainMode::Cloud(1000, 0.0, 2000);
+        let mut manager = GrainManager::new(mode);
+        manager.populate_grains(4, &AUDIO_BUFFER, mode);
+        let lower_indices = |manager: &GrainManager| -> Vec<usize> {
+            manager
+                .grains
+                .iter()
+                .map(|grain| grain.lower_index)
+                .collect()
+        };
+
+        // grains move once they finish the loop they are playing
+        manager.set_global_position(8000);
+        for _ in 0..4000 {
+            manager.get_next_sample();
+        }
+        assert_eq!(lower_indices(&manager), vec![8000; 4]);
+
+        // frozen grains stay where they are until unfrozen
+        manager.freeze(true);
+        manager.set_global_position(12000);
+        for _ in 0..4000 {
+            manager.get_next_sample();
+        }
+        assert_eq!(lower_indices(&manager), vec![8000; 4]);
+
+        manager.freeze(false);
+        for _ in 0..4000 {
+            manager.get_next_sample();
+        }
+        assert_eq!(lower_indices(&manager), vec![12000; 4]);
+
+        // a move still waiting for the grains to finish their loop is cancelled by freezing
+        manager.get_next_sample();
+        manager.set_global_position(16000);
+        manager.freeze(true);
+        for _ in 0..4000 {
+            manager.get_next_sample();
+        }
+        assert_eq!(lower_indices(&manager), vec![12000; 4]);
+
+        manager.freeze(false);
+        for _ in 0..4000 {
+            manager.get_next_sample();
+        }
+        assert_eq!(lower_indices(&manager), vec![16000; 4]);
+    }
+
+    #[test]
+    fn test_cloud_randomization() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 20000]);
+        let mode = GrainMode::Cloud(1000, 0.0, 5000);
+
+        let mut manager = GrainManager::new_seeded(mode, 3);
+        let semitones = |manager: &GrainManager| -> Vec<i8> {
+            manager
+                .grains
+                .iter()
+                .map(|grain| (12.0 * grain.get_pitch_freq().log2()).round() as i8)
+                .collect()
+        };
+
+        // grains are spread across every semitone within a fifth either side, and all reversed
+        manager.set_pitch_spray(7);
+        manager.set_reverse_probability(1.0);
+        manager.populate_grains(32, &AUDIO_BUFFER, mode);
+        let mut sprayed = semitones(&manager);
+        assert!(sprayed.iter().all(|semitones| (-7..=7).contains(semitones)));
+        assert!(manager.grains.iter().all(|grain| grain.reverse));
+        sprayed.sort();
+        sprayed.dedup();
+        assert!(sprayed.len() > 3, "only pitched to {sprayed:?}");
+
+        // stepped grains are a fifth up, a fifth down or unpitched
+        manager.set_pitch_spray_steps(7);
+        manager.populate_grains(32, &AUDIO_BUFFER, mode);
+        assert!(semitones(&manager)
+            .iter()
+            .all(|semitones| [-7, 0, 7].contains(semitones)));
+
+        manager.set_pitch_spray(0);
+        manager.set_reverse_probability(0.0);
+        manager.populate_grains(32, &AUDIO_BUFFER, mode);
+        for grain in manager.grains.iter() {
+            assert_eq!(grain.get_pitch_freq(), 1.0);
+            assert!(!grain.reverse);
+        }
+    }
+
+    #[test]
+    fn test_add_and_remove_grains() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 20000]);
+
+        let mut manager = GrainManager::new(GrainMode::Sequence);
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Sequence);
+        manager.add_grain();
+        assert_eq!(manager.grain_count, 5);
+
+        // the sequence plays through the new grain and wraps back to the first
+        let next_ids: Vec<usize> = manager.grains.iter().map(|grain| grain.next_id).collect();
+        assert_eq!(next_ids, vec![1, 2, 3, 4, 0]);
+        assert_eq!(manager.grains[4].grain_id, 4);
+
+        manager.remove_grain();
+        manager.remove_grain();
+        let next_ids: Vec<usize> = manager.grains.iter().map(|grain| grain.next_id).collect();
+        assert_eq!(next_ids, vec![1, 2, 0]);
+        for _ in 0..20000 {
+            manager.get_next_sample();
+        }
+
+        // the last grain is kept
+        for _ in 0..4 {
+            manager.remove_grain();
+        }
+        assert_eq!(manager.grains.len(), 1);
+        assert_eq!(manager.grains[0].next_id, 0);
+
+        // an added cloud grain is placed as it would be if it had been populated, leaving the others alone
+        let mode = GrainMode::Cloud(1000, 2.0, 5000);
+        let mut populated = GrainManager::new_seeded(mode, 7);
+        populated.populate_grains(6, &AUDIO_BUFFER, mode);
+        let mut added = GrainManager::new_seeded(mode, 7);
+        added.populate_grains(5, &AUDIO_BUFFER, mode);
+        let before: Vec<(usize, usize)> = added
+            .grains
+            .iter()
+            .map(|grain| (grain.lower_index, grain.upper_index))
+            .collect();
+        added.add_grain();
+        for (index, grain) in added.grains.iter().enumerate() {
+            assert_eq!(grain.lower_index, populated.grains[index].lower_index);
+            assert_eq!(grain.upper_index, populated.grains[index].upper_index);
+            assert_eq!(
+                grain.get_pitch_freq(),
+                populated.grains[index].get_pitch_freq()
+            );
+            assert_eq!(grain.next_id, index);
+        }
+        assert_eq!(before.len(), 5);
+        assert!(before
+            .iter()
+            .zip(added.grains.iter())
+            .all(|(bounds, grain)| *bounds == (grain.lower_index, grain.upper_index)));
+    }
+
+    #[test]
+    fn test_mode_default_window() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 8003]);
+
+        // grains in a cloud use a Gaussian window, which matches a fresh one of the same length
+        let mut manager = GrainManager::new(GrainMode::Cloud(0, 0.0, 0));
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Cloud(1000, 0.0, 2000));
+        let mut gaussian = GrainWindow::Gaussian.smoother();
+        gaussian.set_length(manager.grains[0].len());
+        let quarter = manager.grains[0].len() / 4;
+        assert_eq!(
+            manager.grains[0].smoother.get_index(quarter),
+            gaussian.get_index(quarter)
+        );
+
+        let mut manager = GrainManager::new(GrainMode::Sequence);
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Sequence);
+        assert!(manager.grains[0].smoother.get_index(0) < 1e-6);
+
+        manager.grains[0].set_window(GrainWindow::Rectangular);
+        assert_eq!(manager.grains[0].smoother.get_index(0), 1.0);
+    }
+
+    #[test]
+    fn test_float_grain() {
+        // a quiet sine which would round to silence as i16 samples
+        static AUDIO_BUFFER: Lazy<Vec<f32>> =
+            Lazy::new(|| (0..4003).map(|n| 0.5 * (n as f32 * 0.01).sin()).collect());
+
+        let mut grain = Grain::new(&AUDIO_BUFFER, 0, 1, 0);
+        grain.update_smoother();
+        for n in 0..4000 {
+            assert!((grain.get_next_sample(false) - AUDIO_BUFFER[n]).abs() < 1e-6);
+        }
+
+        // pitched float grains are mixed by the manager without converting to i16
+        let mut manager = GrainManager::new(GrainMode::Cascade(0, 4002));
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Cascade(0, 4002));
+        manager.set_global_pitch(7);
+        manager.gate_trigger(true);
+        let output: Vec<f32> = (0..4000).map(|_| manager.get_next_sample()).collect();
+        assert!(output.iter().any(|sample| sample.fract() != 0.0));
+    }
+
+    /// A buffer of a sine at a frequency in Hz, for pitching up
+    fn sine_buffer(frequency: f32) -> Vec<i16> {
+        (0..20000)
+            .map(|n| ((2.0 * PI * frequency * n as f32 / 44100.0).sin() * 10000.0) as i16)
+            .collect()
+    }
+
+    /// RMS of a sine grain pitched up an octave
+    fn pitched_up_rms(audio_buffer: &'static Vec<i16>, antialias: bool) -> f32 {
+        let mut grain = Grain::new(audio_buffer, 0, 1, 12);
+        grain.set_lower_index(0);
+        grain.set_upper_index(10000);
+        grain.set_antialias(antialias);
+
+        let output: Vec<f32> = (0..4000)
+            .map(|_| grain.get_next_sample(false) as f32)
+            .collect();
+        (output[100..].iter().map(|x| x * x).sum::<f32>() / (output.len() - 100) as f32).sqrt()
+    }
+
+    #[test]
+    fn test_antialias_pitch_up() {
+        // a 15kHz sine pitched up an octave aliases down to 14.1kHz without anti-aliasing
+        static HIGH_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| sine_buffer(15000.0));
+        let aliased = pitched_up_rms(&HIGH_BUFFER, false);
+        let filtered = pitched_up_rms(&HIGH_BUFFER, true);
+        assert!(
+            filtered < aliased * 0.5,
+            "{filtered} with, {aliased} without"
+        );
+
+        // a 1kHz sine is well inside the passband, so keeps its level
+        static LOW_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| sine_buffer(1000.0));
+        let unfiltered = pitched_up_rms(&LOW_BUFFER, false);
+        let filtered = pitched_up_rms(&LOW_BUFFER, true);
+        assert!(
+            (filtered / unfiltered - 1.0).abs() < 0.05,
+            "{filtered} with, {unfiltered} without"
+        );
+    }
+
+    #[test]
+    fn test_buffer_region() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![0; 20000]);
+
+        let mut manager = GrainManager::new(GrainMode::Cloud(1000, 2.0, 500));
+        manager.set_buffer_region(4000, 12000);
+        // a start position before the region is moved inside it
+        manager.populate_grains(32, &AUDIO_BUFFER, GrainMode::Cloud(1000, 2.0, 500));
+        for grain in manager.grains.iter() {
+            assert!(grain.lower_index >= 4000 && grain.upper_index <= 12000);
+        }
+
+        manager.populate_grains(8, &AUDIO_BUFFER, GrainMode::Sequence);
+        assert_eq!(manager.grains[0].lower_index, 4000);
+        assert_eq!(manager.grains[7].upper_index, 12000);
+    }
+
+    #[test]
+    fn test_pitch_from_note() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 8003]);
+
+        let mut manager = GrainManager::new(GrainMode::Sequence);
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Sequence);
+        manager.grains[1].set_pitch(7);
+
+        // notes snap to their distance from C5, rather than stacking up
+        for (note, semitones) in [(72, 0), (76, 4), (60, -12), (84, 12), (71, -1)] {
+            manager.set_pitch_from_note(note);
+            assert_eq!(manager.get_global_pitch(), semitones);
+            assert!(
+                (manager.grains[0].get_pitch_freq() - semitone_to_hz_ratio(semitones)).abs() < 1e-4
+            );
+            assert!(
+                (manager.grains[1].get_pitch_freq() - semitone_to_hz_ratio(semitones + 7)).abs()
+                    < 1e-4
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_mode_during_playback() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 8003]);
+
+        let mut manager = GrainManager::new(GrainMode::Sequence);
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Sequence);
+        for _ in 0..3000 {
+            manager.get_next_sample();
+        }
+
+        // grains which haven't started playing move straight away, the playing grain waits for its loop to end
+        manager.set_mode(GrainMode::Cascade(4000, 8000));
+        assert_eq!(manager.grains[3].lower_index, 7000);
+        for _ in 0..8000 {
+            manager.get_next_sample();
+        }
+        for (index, grain) in manager.grains.iter().enumerate() {
+            assert_eq!(grain.lower_index, 4000 + index * 1000);
+            assert_eq!(grain.upper_index, 8000);
+        }
+
+        manager.set_mode(GrainMode::Cloud(1000, 0.5, 2000));
+        for _ in 0..8000 {
+            manager.get_next_sample();
+        }
+        for grain in manager.grains.iter() {
+            assert!(grain.lower_index >= 1750 && grain.upper_index <= 3250);
+        }
+
+        // sequence mode restarts the grains, so the new indices apply immediately
+        manager.set_mode(GrainMode::Sequence);
+        for (index, grain) in manager.grains.iter().enumerate() {
+            assert_eq!(grain.lower_index, index * 2000);
+            assert_eq!(grain.upper_index, (index + 1) * 2000);
+            assert_eq!(grain.get_pitch_freq(), 1.0);
+        }
+        for _ in 0..10000 {
+            manager.get_next_sample();
+        }
+    }
+
+    #[test]
+    fn test_reverse_deferred_mid_grain() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| (0..2000).collect());
+
+        let mut grain = Grain::new(&AUDIO_BUFFER, 0, 1, 0);
+        grain.set_lower_index(0);
+        grain.set_upper_index(1000);
+
+        let mut last = 0;
+        for _ in 0..200 {
+            last = grain.get_next_sample(false);
+        }
+
+        // reversing now would jump from the start of the ramp to the end
+        grain.set_reverse(true);
+        for _ in 0..700 {
+            let sample = grain.get_next_sample(false);
+            assert!((sample - last).abs() <= 2, "jumped from {last} to {sample}");
+            last = sample;
+        }
+    }
 
     #[test]
     fn test_init() {
@@ -640,21 +2075,13 @@ mod tests {
         grain.set_smoothing(NoSmoother::new())
     }
 
-    fn get_left(v: Vec<i16>) -> Vec<i16> {
-        (0..(v.len() / 2)).map(|index| v[index * 2]).collect()
-    }
-
-    fn get_right(v: Vec<i16>) -> Vec<i16> {
-        (0..(v.len() / 2)).map(|index| v[(index * 2) + 1]).collect()
-    }
-
     #[test]
     #[ignore]
     fn generate_grain_with_manager() {
         static LEFT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_left(load_wav("tests/handpan.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/handpan.wav").unwrap()).left());
         static RIGHT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_right(load_wav("tests/handpan.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/handpan.wav").unwrap()).right());
 
         let grain_len: usize = LEFT_AUDIO_BUFFER.len() / 2056;
         let initial_grain_pos = 1;
@@ -779,9 +2206,9 @@ mod tests {
     #[test]
     fn test_octaves() {
         static LEFT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_left(load_wav("tests/handpan.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/handpan.wav").unwrap()).left());
         static RIGHT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_right(load_wav("tests/handpan.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/handpan.wav").unwrap()).right());
 
         let mut manager_left = GrainManager::new(GrainMode::Cloud(0, 0.0, 0));
         manager_left.populate_grains(
@@ -848,9 +2275,9 @@ mod tests {
     #[test]
     fn test_adsr() {
         static LEFT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_left(load_wav("tests/handpan.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/handpan.wav").unwrap()).left());
         static RIGHT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_right(load_wav("tests/handpan.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/handpan.wav").unwrap()).right());
 
         let mut manager_left = GrainManager::new(GrainMode::Cloud(0, 0.0, 0));
         manager_left.populate_grains(
@@ -900,9 +2327,9 @@ mod tests {
     #[test]
     fn test_chord() {
         static LEFT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_left(load_wav("tests/kalimba.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/kalimba.wav").unwrap()).left());
         static RIGHT_AUDIO_BUFFER: Lazy<Vec<i16>> =
-            Lazy::new(|| get_right(load_wav("tests/kalimba.wav").unwrap()));
+            Lazy::new(|| IntSamples::new(load_wav("tests/kalimba.wav").unwrap()).right());
 
         let mut root = GrainManager::new(GrainMode::Cloud(0, 0.0, 0));
         root.populate_grains(
@@ -964,4 +2391,166 @@ mod tests {
             PhonicMode::Stereo,
         );
     }
+
+    #[test]
+    fn test_set_pos_keeps_length() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 4000]);
+
+        let mut grain = Grain::new(&AUDIO_BUFFER, 0, 1, 0);
+        grain.set_bounds(0, 1000);
+        grain.set_pos(100);
+        assert_eq!((grain.lower_index, grain.upper_index), (100, 1100));
+
+        // past the end the grain is placed against the end of the buffer, still 1000 samples long
+        grain.set_pos(3500);
+        assert_eq!((grain.lower_index, grain.upper_index), (3000, 4000));
+        assert_eq!(grain.len(), 1000);
+
+        // a later locked change to another setting doesn't move the grain back to an older position
+        grain.lock_playback();
+        grain.set_reverse(true);
+        grain.finish_loop();
+        assert_eq!((grain.lower_index, grain.upper_index), (3000, 4000));
+    }
+
+    /// The largest change between consecutive samples of a seeded cloud over a flat buffer,
+    /// over the samples after a change is made to the manager
+    fn largest_cloud_step(change: impl Fn(&mut GrainManager)) -> f32 {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 20000]);
+
+        let mode = GrainMode::Cloud(2000, 1.0, 0);
+        let mut manager = GrainManager::new_seeded(mode, 7);
+        manager.populate_grains(4, &AUDIO_BUFFER, mode);
+        manager.gate_trigger(true);
+        for _ in 0..(44100 * 4) {
+            manager.get_next_sample_f32();
+        }
+
+        change(&mut manager);
+        let output: Vec<f32> = (0..2000).map(|_| manager.get_next_sample_f32()).collect();
+        output
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_add_and_remove_grains_keep_level() {
+        let steady = largest_cloud_step(|_| ());
+        let added = largest_cloud_step(|manager| manager.add_grain());
+        let removed = largest_cloud_step(|manager| manager.remove_grain());
+        // the grain fades in or out rather than the average jumping to its new divisor
+        assert!(added < 2.0 * steady, "step of {added} against {steady}");
+        assert!(removed < 2.0 * steady, "step of {removed} against {steady}");
+    }
+
+    #[test]
+    fn test_sequence_makeup_gain_every_sample() {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 4000]);
+
+        let mut manager = GrainManager::new(GrainMode::Sequence);
+        manager.populate_grains(4, &AUDIO_BUFFER, GrainMode::Sequence);
+        for grain in manager.grains.iter_mut() {
+            grain.set_smooth_factor(0.0);
+        }
+        manager.set_makeup_gain(2.0);
+        manager.gate_trigger(true);
+
+        // wait for the envelope to reach its sustain level of 0.75
+        for _ in 0..(44100 * 4) {
+            manager.get_next_sample();
+        }
+        // covers samples in the middle of grains as well as the transitions between them
+        for n in 0..3000 {
+            let sample = manager.get_next_sample();
+            assert!((sample - 1500).abs() <= 2, "sample {n} was {sample}");
+        }
+    }
+
+    #[test]
+    fn test_makeup_gain_smoothing() {
+        let mut manager: GrainManager = GrainManager::new(GrainMode::Sequence);
+        manager.set_makeup_gain(1.0);
+        manager.next_makeup_gain();
+
+        // instant changes by default
+        manager.set_makeup_gain(4.0);
+        assert_eq!(manager.next_makeup_gain(), 4.0);
+
+        manager.set_makeup_gain_smooth_time(10.0);
+        let mut previous = 4.0;
+        for toggle in 0..8 {
+            let target = match toggle % 2 {
+                0 => 0.0,
+                _ => 4.0,
+            };
+            manager.set_makeup_gain(target);
+            for _ in 0..32 {
+                let gain = manager.next_makeup_gain();
+                // each step moves a fraction of the way, never jumping to the target
+                assert!((gain - previous).abs() < 0.5);
+                assert!(gain > 0.0 && gain < 4.0);
+                previous = gain;
+            }
+        }
+    }
+
+    #[test]
+    fn test_makeup_gain_sample_rate() {
+        // after the same number of samples, smoothing at double the sample rate has covered half the time
+        let step_after = |sample_rate: f32| {
+            let mut manager: GrainManager = GrainManager::new(GrainMode::Sequence);
+            manager.set_sample_rate(sample_rate);
+            manager.set_makeup_gain_smooth_time(10.0);
+            manager.set_makeup_gain(0.0);
+            (0..441).map(|_| manager.next_makeup_gain()).last().unwrap()
+        };
+        assert!((step_after(44100.0) - 3.0 * (-1.0_f32).exp()).abs() < 0.01);
+        assert!((step_after(88200.0) - 3.0 * (-0.5_f32).exp()).abs() < 0.01);
+    }
+
+    /// Builds a manager of four unwindowed grains over a constant buffer of 1000 with its gate held,
+    /// run until the envelope reaches its sustain level of 0.75, so every sample is 750 times the makeup gain
+    fn settled_flat_manager(mode: GrainMode, makeup_gain: f32) -> GrainManager {
+        static AUDIO_BUFFER: Lazy<Vec<i16>> = Lazy::new(|| vec![1000; 4000]);
+
+        let mut manager = GrainManager::new(mode);
+        manager.populate_grains(4, &AUDIO_BUFFER, mode);
+        for grain in manager.grains.iter_mut() {
+            grain.set_smooth_factor(0.0);
+        }
+        manager.set_makeup_gain(makeup_gain);
+        manager.gate_trigger(true);
+        for _ in 0..(44100 * 4) {
+            manager.get_next_sample();
+        }
+        manager
+    }
+
+    /// Whether a sample from a `settled_flat_manager` is at a level, allowing for rounding in the gain stages
+    fn is_at_level(sample: i16, level: i16) -> bool {
+        (sample - level).abs() <= 2
+    }
+
+    #[test]
+    fn test_morph_between_modes() {
+        let mut morph = GrainMorph::new(GrainMode::Sequence, GrainMode::Cascade(0, 4000));
+        // the layers play the same buffer, so they are told apart by level
+        morph.layer_a = settled_flat_manager(GrainMode::Sequence, 2.0);
+        morph.layer_b = settled_flat_manager(GrainMode::Cascade(0, 4000), 1.0);
+        assert!(is_at_level(morph.get_next_sample(), 1500));
+
+        morph.set_morph(1.0);
+        let mut previous = 1500;
+        for n in 0..44100 {
+            let sample = morph.get_next_sample();
+            // the level glides from one layer to the other without jumping
+            assert!(
+                (sample - previous).abs() <= 5,
+                "sample {n} jumped from {previous} to {sample}"
+            );
+            previous = sample;
+        }
+        assert!(is_at_level(previous, 750), "ended at {previous}");
+    }
 }
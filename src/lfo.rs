@@ -1,18 +1,20 @@
 //! Multi-Mode Low Frequency Oscillator (MMLFO) module with the following features:
+use crate::interpolators::{hermite_interpolate, lerp};
 use crate::modulation::Modulator;
 /// * WaveForms
 ///      - square
 ///      - triangle
 ///      - sine
 ///      - S&H circuit
+///      - smooth random
 /// * frequency (Hz)
 /// * sync (time div enum)
 /// * get current sample / step current index
-use crate::timing::{TimeDiv, Timing};
+use crate::timing::{TimeDiv, Timing, TransportSync};
 use rand::{thread_rng, Rng};
 use std::f32::consts::PI;
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, Clone, PartialEq, Debug)]
 /// An enum of available LFO modes, excluding the Sample and Hold mode,
 /// which is implemented separately
 #[allow(missing_docs)]
@@ -57,10 +59,27 @@ impl LFOMode {
 ///
 /// * `function`: The closure object used to populate the discrete function vector with samples
 ///
-/// * `current_index`: The index used to iterate over the discrete function buffer to get the next sample
+/// * `phase`: The position in samples within one period of the waveform, used to read the discrete function buffer.
+/// Fractional when interpolating, as the period is rarely a whole number of samples
+///
+/// * `period_samples`: The length of one period of the waveform in samples, kept so the phase can be rescaled when it changes
 ///
 /// * `discrete_func`: The rendered buffer of sampled waveform,
 /// will have the length needed for 1 period of the waveform at the correct frequency
+///
+/// * `interpolate`: A boolean deciding whether to linearly interpolate between samples of the discrete function,
+/// or to read it at integer indices
+///
+/// * `wavetable`: A boolean which is true when the discrete function is a single cycle table given by `from_wavetable`,
+/// which is stretched across the period rather than being regenerated
+///
+/// * `duty_cycle`: The fraction of each period the square waveform spends high, between 0 and 1
+///
+/// * `fade_in_s`: The time in seconds the output takes to fade in from 0 to full depth after a reset, 0 for no fade
+///
+/// * `fade_elapsed`: The number of samples since the last reset, stopping once the fade in is complete
+///
+/// * `bipolar`: A boolean deciding whether `get_next_value` outputs from -1 to 1 centered on zero, rather than 0 to 1
 pub struct MMLFO {
     mode: LFOMode,
     sync: bool,
@@ -68,8 +87,15 @@ pub struct MMLFO {
     freq_hz: f32,
     sample_rate: f32,
     function: fn(f32) -> f32,
-    current_index: usize,
+    phase: f32,
+    period_samples: f32,
     discrete_func: Vec<f32>,
+    interpolate: bool,
+    wavetable: bool,
+    duty_cycle: f32,
+    fade_in_s: f32,
+    fade_elapsed: f32,
+    bipolar: bool,
 }
 
 impl Default for MMLFO {
@@ -81,8 +107,15 @@ impl Default for MMLFO {
             freq_hz: 500.0,
             sample_rate: 44100.0,
             function: LFOMode::Sine.get_function(),
-            current_index: 0,
+            phase: 0.0,
+            period_samples: 0.0,
             discrete_func: Vec::new(),
+            interpolate: true,
+            wavetable: false,
+            duty_cycle: 0.5,
+            fade_in_s: 0.0,
+            fade_elapsed: 0.0,
+            bipolar: false,
         };
         instance.update_state();
         instance
@@ -91,15 +124,26 @@ impl Default for MMLFO {
 
 impl Modulator for MMLFO {
     fn get_value(&self) -> f32 {
-        self.discrete_func[self.current_index] - 0.5
+        (self.read() - 0.5) * self.fade()
     }
 
     fn advance(&mut self) {
+        let fade_samples = self.fade_in_s * self.sample_rate;
+        if self.fade_elapsed < fade_samples {
+            self.fade_elapsed += 1.0;
+        }
+
         let period = self.sample_rate / (self.freq_hz);
-        self.current_index = (self.current_index + 1) % (period as usize);
+        self.phase = match self.interpolate {
+            true => (self.phase + 1.0) % period,
+            false => ((self.phase as usize + 1) % (period as usize)) as f32,
+        };
     }
 
-    fn reset(&mut self) {}
+    /// Restarts the fade in, leaving the phase where it is
+    fn reset(&mut self) {
+        self.fade_elapsed = 0.0;
+    }
 }
 
 impl MMLFO {
@@ -121,6 +165,24 @@ impl MMLFO {
         instance
     }
 
+    /// A constructor for an LFO playing a single cycle of a custom shape, such as a drawn LFO shape.
+    /// The table is read across each period, so the frequency sets how quickly it is played back
+    /// # Parameters
+    /// * `table`: The values of one cycle of the shape, which should be between 0 and 1 like the built in waveforms
+    ///
+    /// * `sample_rate`: The sample rate the LFO will be played back at in Hz
+    pub fn from_wavetable(table: Vec<f32>, sample_rate: f32) -> Self {
+        assert!(!table.is_empty(), "The wavetable needs at least one value");
+        let mut instance = Self {
+            sample_rate,
+            discrete_func: table,
+            wavetable: true,
+            ..Default::default()
+        };
+        instance.update_state();
+        instance
+    }
+
     /// Updates fields of the struct that need recomputing after a set operation.
     ///
     /// Function is updated from the mode enum variant
@@ -136,20 +198,114 @@ impl MMLFO {
         };
 
         let period_samples = self.sample_rate / (self.freq_hz);
+        if !self.wavetable {
+            self.render_waveform(period_samples);
+        }
+
+        // the phase is scaled with the period, keeping the same position in the waveform,
+        // so any offset from `set_phase` is kept when the frequency changes
+        if self.period_samples > 0.0 {
+            self.phase = (self.phase / self.period_samples * period_samples) % period_samples;
+            if !self.interpolate {
+                self.phase = self.phase.floor();
+            }
+        }
+        self.period_samples = period_samples;
+    }
+
+    /// Regenerates the discrete function from the waveform mode, with one sample for each sample of the period
+    fn render_waveform(&mut self, period_samples: f32) {
         self.discrete_func = vec![1.0; period_samples as usize];
 
         for x in 0..(period_samples as usize) {
-            self.discrete_func[x] = (self.function)(x as f32 / period_samples)
+            let position = x as f32 / period_samples;
+            self.discrete_func[x] = match self.mode {
+                // the square is generated here, as its closure can't take the duty cycle
+                LFOMode::Square => match position {
+                    p if p < self.duty_cycle => 1.0,
+                    p if p == self.duty_cycle => 0.5,
+                    _ => 0.0,
+                },
+                _ => (self.function)(position),
+            }
+        }
+    }
+
+    /// Reads the wavetable at the current phase, mapping the period across the length of the table
+    fn read_wavetable(&self) -> f32 {
+        let length = self.discrete_func.len();
+        let position = self.phase / self.period_samples * length as f32;
+        let index = (position as usize).min(length - 1);
+        match self.interpolate {
+            // the table is a single cycle, so the last value leads back to the first
+            true => lerp(
+                self.discrete_func[index],
+                self.discrete_func[(index + 1) % length],
+                position - index as f32,
+            ),
+            false => self.discrete_func[index],
+        }
+    }
+
+    /// Reads the discrete buffer at the current phase, interpolating between adjacent samples if enabled
+    fn read(&self) -> f32 {
+        if self.wavetable {
+            return self.read_wavetable();
+        }
+        let last = self.discrete_func.len() - 1;
+        let index = (self.phase as usize).min(last);
+        match self.interpolate {
+            true if index == last => {
+                // the waveform is periodic, so the last sample leads back to the first,
+                // over the fractional remainder of the period rather than a whole sample
+                let period = self.sample_rate / (self.freq_hz);
+                let t = (self.phase - last as f32) / (period - last as f32);
+                lerp(self.discrete_func[last], self.discrete_func[0], t)
+            }
+            true => lerp(
+                self.discrete_func[index],
+                self.discrete_func[index + 1],
+                self.phase.fract(),
+            ),
+            false => self.discrete_func[index],
+        }
+    }
+
+    /// Returns the multiplier for the output during the fade in, which rises from 0 to 1 and then stays at 1
+    fn fade(&self) -> f32 {
+        let fade_samples = self.fade_in_s * self.sample_rate;
+        match fade_samples > 0.0 {
+            true => (self.fade_elapsed / fade_samples).min(1.0),
+            false => 1.0,
         }
     }
 
-    /// Returns the next value from the discrete buffer and cycles the index to 0 if necessary
+    /// Returns the next value from the discrete buffer and cycles the index to 0 if necessary.
+    /// The value is between 0 and 1, or between -1 and 1 when bipolar, scaled down while fading in
     pub fn get_next_value(&mut self) -> f32 {
-        let value = self.discrete_func[self.current_index];
+        let value = match self.bipolar {
+            true => 2.0 * self.read() - 1.0,
+            false => self.read(),
+        } * self.fade();
         self.advance();
         value
     }
 
+    /// Setter for bipolar output, mapping the output of `get_next_value` from 0 to 1 onto -1 to 1,
+    /// for modulating parameters such as pitch or pan either side of their center
+    pub fn set_bipolar(&mut self, bipolar: bool) {
+        self.bipolar = bipolar;
+    }
+
+    /// Setter for linear interpolation of the discrete buffer readout.
+    /// When off, the buffer is read at integer indices, which quantizes the phase of short period LFOs.
+    pub fn set_interpolation(&mut self, interpolate: bool) {
+        self.interpolate = interpolate;
+        if !interpolate {
+            self.phase = self.phase.floor();
+        }
+    }
+
     /// Setter for sample rate in Hz
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
         self.sample_rate = sample_rate;
@@ -186,14 +342,121 @@ impl MMLFO {
         self.update_state();
     }
 
-    /// Setter for the waveform mode. Accepts an `LFOMode` as a parameter
+    /// Moves the LFO to the phase a synced LFO should have at the host's transport position,
+    /// so it lines up with the bar rather than wherever it was when playback started. Has no effect without sync.
+    pub fn align(&mut self, transport: &TransportSync) {
+        if self.sync {
+            let period = self.sample_rate / (self.freq_hz);
+            self.phase = transport.timing_phase(&self.sync_timing) * period;
+            if !self.interpolate {
+                self.phase = self.phase.floor();
+            }
+        }
+    }
+
+    /// Setter for the position in the waveform as a fraction of the period, wrapped into the range 0 to 1,
+    /// so that LFOs can be offset from each other, such as 0.25 for 90° or 0.5 for 180°
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = phase.rem_euclid(1.0) * self.period_samples;
+        // rounding can put the wrapped phase at the very end of the period
+        if self.phase >= self.period_samples {
+            self.phase = 0.0;
+        }
+        if !self.interpolate {
+            self.phase = self.phase.floor();
+        }
+    }
+
+    /// Setter for the duty cycle of the square waveform, the fraction of each period spent high, clamped between 0 and 1
+    pub fn set_duty_cycle(&mut self, duty: f32) {
+        self.duty_cycle = duty.clamp(0.0, 1.0);
+        self.update_state();
+    }
+
+    /// Setter for the time in seconds the output takes to fade in after a reset, like a delayed vibrato.
+    /// 0 turns the fade in off
+    pub fn set_fade_in(&mut self, seconds: f32) {
+        self.fade_in_s = seconds.max(0.0);
+    }
+
+    /// Setter for the waveform mode. Accepts an `LFOMode` as a parameter, replacing any wavetable
     pub fn set_mode(&mut self, mode: LFOMode) {
         self.mode = mode;
+        self.wavetable = false;
         self.update_state();
     }
 }
 
-/// Sample and Hold struct accessing a 2 second noise buffer, which is generated at construction
+/// A pair of MMLFOs sharing a waveform and frequency, with the right LFO offset in phase from the left
+/// for stereo modulation, such as a quadrature chorus with a spread of 90°
+/// ## Attributes
+/// * `left`: The LFO for the left channel
+///
+/// * `right`: The LFO for the right channel, which runs ahead of the left by the spread
+pub struct StereoLFO {
+    left: MMLFO,
+    right: MMLFO,
+}
+
+impl StereoLFO {
+    /// The constructor for the stereo LFO with sync and mode as the parameters, starting with no spread
+    pub fn new(sync: bool, mode: LFOMode) -> Self {
+        Self {
+            left: MMLFO::new(sync, mode.clone()),
+            right: MMLFO::new(sync, mode),
+        }
+    }
+
+    /// Setter for the phase difference between the left and right LFOs in degrees,
+    /// where 0 gives mono modulation and 180 moves the channels in opposite directions
+    pub fn set_spread(&mut self, degrees: f32) {
+        let left_phase = self.left.phase / self.left.period_samples;
+        self.right.set_phase(left_phase + degrees / 360.0);
+    }
+
+    /// Setter for the waveform mode of both LFOs
+    pub fn set_mode(&mut self, mode: LFOMode) {
+        self.left.set_mode(mode.clone());
+        self.right.set_mode(mode);
+    }
+
+    /// Setter for the frequency of both LFOs in Hz
+    pub fn set_frequency_hz(&mut self, freq_hz: f32) {
+        self.left.set_frequency_hz(freq_hz);
+        self.right.set_frequency_hz(freq_hz);
+    }
+
+    /// Setter for the sample rate of both LFOs in Hz
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.left.set_sample_rate(sample_rate);
+        self.right.set_sample_rate(sample_rate);
+    }
+
+    /// Setter for the timing both LFOs sync to
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.left.set_timing(timing.clone());
+        self.right.set_timing(timing);
+    }
+
+    /// Setter for the BPM both LFOs sync to
+    pub fn set_bpm(&mut self, bpm: i16) {
+        self.left.set_bpm(bpm);
+        self.right.set_bpm(bpm);
+    }
+
+    /// Setter for bipolar output of both LFOs
+    pub fn set_bipolar(&mut self, bipolar: bool) {
+        self.left.set_bipolar(bipolar);
+        self.right.set_bipolar(bipolar);
+    }
+
+    /// Returns the next (left, right) pair of values from the LFOs
+    pub fn get_next_values(&mut self) -> (f32, f32) {
+        (self.left.get_next_value(), self.right.get_next_value())
+    }
+}
+
+/// Sample and Hold struct accessing a noise buffer, 2 seconds long by default, which is generated at construction
 /// ## Attributes
 /// * `noise_buffer`: A vector of noise samples, used to generate the stepped random output
 ///
@@ -210,6 +473,8 @@ impl MMLFO {
 /// * `slew`: Bool used to decide whether to perform slew rate limiting or not
 ///
 /// * `slew_time_s`: The time in seconds that it should take for a transition between samples.
+///
+/// * `sample_rate`: The sample rate the S&H will be played back at in Hz
 pub struct SampleAndHold {
     noise_buffer: Vec<f32>,
     current_value: f32,
@@ -219,13 +484,14 @@ pub struct SampleAndHold {
     interpolate: f32,
     slew: bool,
     slew_time_s: f32,
+    sample_rate: f32,
 }
 
 impl Default for SampleAndHold {
     fn default() -> Self {
         let mut rng = thread_rng();
         Self {
-            noise_buffer: (0..88200).map(|_| rng.gen()).collect(),
+            noise_buffer: (0..(2 * 44100)).map(|_| rng.gen()).collect(),
             current_value: 0.0,
             current_index: 0,
             frequency_hz: 1.0,
@@ -233,6 +499,7 @@ impl Default for SampleAndHold {
             interpolate: 0.0,
             slew: false,
             slew_time_s: 0.25,
+            sample_rate: 44100.0,
         }
     }
 }
@@ -247,6 +514,8 @@ impl SampleAndHold {
     /// * slew: false
     ///
     /// * slew time: 0.25s
+    ///
+    /// * sample rate: 44100Hz, with `buffer_length` seconds of noise
     pub fn new(slew: bool, slew_time: f32, buffer_length: usize) -> Self {
         let mut rng = thread_rng();
         Self {
@@ -265,9 +534,21 @@ impl SampleAndHold {
         self.interpolate = 0.0;
     }
 
-    /// Increase the index and take it mod 2 seconds in samples, which loops the index to 0 after a full duration
+    /// Increase the index and take it mod the length of the noise buffer, which loops the index to 0 after a full duration
     fn advance(&mut self) {
-        self.current_index = (self.current_index + 1) % 88200;
+        self.current_index = (self.current_index + 1) % self.noise_buffer.len();
+    }
+
+    /// Setter for sample rate in Hz, regenerating the noise buffer so it lasts the same duration
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let buffer_seconds = self.noise_buffer.len() as f32 / self.sample_rate;
+        self.sample_rate = sample_rate;
+
+        let mut rng = thread_rng();
+        self.noise_buffer = (0..(buffer_seconds * sample_rate) as usize)
+            .map(|_| rng.gen())
+            .collect();
+        self.current_index %= self.noise_buffer.len();
     }
 
     /// Setter for frequency in Hz
@@ -279,7 +560,7 @@ impl SampleAndHold {
     pub fn get_next_value(&mut self) -> f32 {
         self.advance();
 
-        let period_samples = ((1.0 / self.frequency_hz) * 44100.0) as usize;
+        let period_samples = ((1.0 / self.frequency_hz) * self.sample_rate) as usize;
         if self.current_index % period_samples == 0 {
             self.sample();
         }
@@ -290,7 +571,7 @@ impl SampleAndHold {
                 self.last_value = self.current_value;
                 self.current_value
             } else {
-                self.interpolate += 1.0 / (44100.0 * self.slew_time_s);
+                self.interpolate += 1.0 / (self.sample_rate * self.slew_time_s);
                 ((1.0 - self.interpolate) * self.last_value)
                     + (self.interpolate * self.current_value)
             }
@@ -310,14 +591,78 @@ impl SampleAndHold {
     }
 }
 
+/// Smooth random LFO struct, which draws a random point at its frequency and moves between them
+/// along a Hermite spline, giving a continuous wandering curve rather than the steps of `SampleAndHold`
+/// ## Attributes
+/// * `points`: The last 4 random points, the output moves between the middle 2 while the outer 2 decide its gradients
+///
+/// * `position`: The interpolant between the middle 2 points, from 0 to 1
+///
+/// * `frequency_hz`: The rate new random points are drawn in Hz
+///
+/// * `sample_rate`: The sample rate the LFO will be played back at in Hz
+///
+/// * `smoothness`: The tension of the spline, from 0 (easing in and out of each point) to 1 (flowing through them)
+pub struct SmoothRandom {
+    points: [f32; 4],
+    position: f32,
+    frequency_hz: f32,
+    sample_rate: f32,
+    smoothness: f32,
+}
+
+impl SmoothRandom {
+    /// The constructor for the smooth random LFO, with a frequency of 1Hz and full smoothness
+    /// # Parameters
+    /// * `sample_rate`: The sample rate the LFO will be played back at in Hz
+    pub fn new(sample_rate: f32) -> Self {
+        let mut rng = thread_rng();
+        Self {
+            points: [rng.gen(), rng.gen(), rng.gen(), rng.gen()],
+            position: 0.0,
+            frequency_hz: 1.0,
+            sample_rate,
+            smoothness: 1.0,
+        }
+    }
+
+    /// Setter for the rate new random points are drawn in Hz
+    pub fn set_freq(&mut self, freq: f32) {
+        self.frequency_hz = freq;
+    }
+
+    /// Setter for the tension of the spline, clamped between 0 and 1
+    pub fn set_smoothness(&mut self, smoothness: f32) {
+        self.smoothness = smoothness.clamp(0.0, 1.0);
+    }
+
+    /// Getter for the next sample value between 0 and 1, drawing a new random point each period
+    pub fn get_next_value(&mut self) -> f32 {
+        let [p0, p1, p2, p3] = self.points;
+        // the spline can overshoot the points slightly, so it is kept in the range of the other LFOs
+        let value =
+            hermite_interpolate(p0, p1, p2, p3, self.smoothness, self.position).clamp(0.0, 1.0);
+
+        self.position += self.frequency_hz / self.sample_rate;
+        while self.position >= 1.0 {
+            self.position -= 1.0;
+            self.points.rotate_left(1);
+            self.points[3] = thread_rng().gen();
+        }
+        value
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::delay_line::StereoDelay;
     use crate::filter::LowpassFilter;
-    use crate::lfo::{LFOMode, SampleAndHold, MMLFO};
+    use crate::lfo::{LFOMode, SampleAndHold, SmoothRandom, StereoLFO, MMLFO};
+    use crate::modulation::Modulator;
     use crate::samples::{IntSamples, PhonicMode, Samples};
-    use crate::timing::TimeDiv;
+    use crate::timing::{TimeDiv, TransportSync};
     use crate::{load_wav, write_wav};
+    use std::f32::consts::PI;
     use test_case::test_case;
 
     #[test]
@@ -346,6 +691,30 @@ mod tests {
         assert_eq!(lfo.freq_hz, 800.0);
     }
 
+    /// Returns the largest difference between a 4300Hz sine LFO and the ideal waveform over a second
+    fn high_frequency_error(interpolate: bool) -> f32 {
+        let mut lfo = MMLFO::new(false, LFOMode::Sine);
+        lfo.set_frequency_hz(4300.0);
+        lfo.set_interpolation(interpolate);
+
+        (0..44100)
+            .map(|n| {
+                let ideal = 0.5 * (2.0 * PI * 4300.0 * n as f32 / 44100.0).sin() + 0.5;
+                (lfo.get_next_value() - ideal).abs()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_interpolated_readout() {
+        // the period is around 10.26 samples, so integer readout drifts out of phase
+        let stepped_error = high_frequency_error(false);
+        let interpolated_error = high_frequency_error(true);
+
+        assert!(interpolated_error < 0.1);
+        assert!(interpolated_error < stepped_error);
+    }
+
     #[test_case(LFOMode::Sine ; "sin lfo")]
     #[test_case(LFOMode::Triangle ; "tri lfo")]
     #[test_case(LFOMode::Square ; "sqr lfo")]
@@ -470,4 +839,199 @@ mod tests {
 
         write_wav("tests/debug/lfo_snh_slew.wav", out, PhonicMode::Mono);
     }
+
+    #[test_case(0.5 ; "even")]
+    #[test_case(0.25 ; "short pulse")]
+    #[test_case(0.8 ; "long pulse")]
+    fn test_duty_cycle(duty: f32) {
+        let mut lfo = MMLFO::new(false, LFOMode::Square);
+        lfo.set_frequency_hz(10.0);
+        lfo.set_interpolation(false);
+        lfo.set_duty_cycle(duty);
+
+        // one period is 4410 samples
+        let high = (0..4410).filter(|_| lfo.get_next_value() > 0.5).count();
+        assert!(
+            (high as f32 - duty * 4410.0).abs() <= 1.0,
+            "{high} samples high"
+        );
+    }
+
+    /// Returns the number of times a 10Hz S&H changes value over a second at the sample rate
+    fn count_snh_steps(sample_rate: f32) -> usize {
+        let mut snh = SampleAndHold::default();
+        snh.set_sample_rate(sample_rate);
+        snh.set_freq(10.0);
+        let values: Vec<f32> = (0..sample_rate as usize)
+            .map(|_| snh.get_next_value())
+            .collect();
+        values.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+
+    #[test]
+    fn test_snh_sample_rate() {
+        // a new value is sampled 10 times a second whatever the sample rate
+        assert_eq!(count_snh_steps(44100.0), 10);
+        assert_eq!(count_snh_steps(96000.0), 10);
+
+        let mut snh = SampleAndHold::default();
+        snh.set_sample_rate(96000.0);
+        assert_eq!(snh.noise_buffer.len(), 2 * 96000);
+    }
+
+    #[test]
+    fn test_wavetable() {
+        let mut lfo = MMLFO::from_wavetable(vec![0.0, 0.5, 1.0, 0.5], 44100.0);
+        lfo.set_frequency_hz(10.0);
+
+        // the 4 values are spread across the 4410 sample period, interpolating between them
+        let values: Vec<f32> = (0..8820).map(|_| lfo.get_next_value()).collect();
+        assert_eq!(values[0], 0.0);
+        assert!((values[2205] - 1.0).abs() < 1e-3);
+        assert!((values[3307] - 0.5).abs() < 1e-3);
+        assert!((values[4134] - 0.125).abs() < 1e-3);
+        assert!((values[4410 + 2205] - 1.0).abs() < 1e-3);
+
+        // without interpolation each value is held for a quarter of the period
+        lfo.set_interpolation(false);
+        lfo.set_phase(0.0);
+        let held: Vec<f32> = (0..4410).map(|_| lfo.get_next_value()).collect();
+        assert!(held[..1102].iter().all(|value| *value == 0.0));
+        assert!(held[2206..3307].iter().all(|value| *value == 1.0));
+
+        // setting a mode goes back to the built in waveforms
+        lfo.set_mode(LFOMode::Square);
+        lfo.set_phase(0.0);
+        assert_eq!(lfo.get_next_value(), 1.0);
+    }
+
+    #[test]
+    fn test_stereo_lfo_spread() {
+        let mut lfo = StereoLFO::new(false, LFOMode::Sine);
+        lfo.set_frequency_hz(2.0);
+        lfo.set_spread(90.0);
+
+        // in quadrature the right channel is a cosine, so the pair traces a circle
+        for n in 0..22050 {
+            let (left, right) = lfo.get_next_values();
+            let radius = (left - 0.5).powi(2) + (right - 0.5).powi(2);
+            assert!((radius - 0.25).abs() < 1e-3, "sample {n}");
+        }
+
+        // the spread is kept when the frequency changes
+        lfo.set_spread(180.0);
+        lfo.set_frequency_hz(5.0);
+        for _ in 0..22050 {
+            let (left, right) = lfo.get_next_values();
+            assert!((left + right - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_smooth_random() {
+        let mut lfo = SmoothRandom::new(44100.0);
+        lfo.set_freq(5.0);
+        let values: Vec<f32> = (0..44100).map(|_| lfo.get_next_value()).collect();
+
+        // the curve wanders around the range without jumping between samples like a sample and hold
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(min >= 0.0 && max <= 1.0);
+        assert!(max - min > 0.1, "range {min} to {max}");
+        let largest_step = values
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(largest_step < 0.002, "largest step {largest_step}");
+    }
+
+    #[test]
+    fn test_fade_in() {
+        let mut reference = MMLFO::new(false, LFOMode::Sine);
+        reference.set_frequency_hz(10.0);
+        let mut lfo = MMLFO::new(false, LFOMode::Sine);
+        lfo.set_frequency_hz(10.0);
+        lfo.set_fade_in(0.5);
+
+        // the output is scaled up linearly over the first 22050 samples, then stays at full depth
+        for n in 0..44100 {
+            let fade = (n as f32 / 22050.0).min(1.0);
+            let expected = fade * reference.get_next_value();
+            assert!((lfo.get_next_value() - expected).abs() < 1e-5, "sample {n}");
+        }
+
+        // resetting starts the fade in again
+        lfo.reset();
+        assert_eq!(lfo.get_next_value(), 0.0);
+    }
+
+    #[test]
+    fn test_bipolar_output() {
+        let mut lfo = MMLFO::new(false, LFOMode::Triangle);
+        lfo.set_frequency_hz(10.0);
+        let mut bipolar = MMLFO::new(false, LFOMode::Triangle);
+        bipolar.set_frequency_hz(10.0);
+        bipolar.set_bipolar(true);
+
+        let values: Vec<f32> = (0..4410).map(|_| bipolar.get_next_value()).collect();
+        for (n, value) in values.iter().enumerate() {
+            assert!(
+                (value - (2.0 * lfo.get_next_value() - 1.0)).abs() < 1e-6,
+                "sample {n}"
+            );
+        }
+
+        // the output spans -1 to 1 and is centered on zero
+        let min = values.iter().cloned().fold(f32::MAX, f32::min);
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!(min < -0.99 && max > 0.99, "range {min} to {max}");
+        assert!(mean.abs() < 0.01, "mean {mean}");
+    }
+
+    #[test]
+    fn test_phase_offset() {
+        let mut lfo = MMLFO::new(false, LFOMode::Sine);
+        lfo.set_frequency_hz(1.0);
+
+        // a quarter of the way through a sine starts at its peak
+        lfo.set_phase(0.25);
+        assert!((lfo.get_next_value() - 1.0).abs() < 1e-3);
+
+        // phases outside of 0 to 1 wrap around, to the trough of the sine
+        lfo.set_phase(-1.25);
+        assert!(lfo.get_next_value() < 1e-3);
+
+        // two LFOs half a period apart stay opposite after changing frequency
+        let mut opposite = MMLFO::new(false, LFOMode::Sine);
+        opposite.set_frequency_hz(1.0);
+        opposite.set_phase(0.5);
+        lfo.set_phase(0.0);
+        lfo.set_frequency_hz(3.0);
+        opposite.set_frequency_hz(3.0);
+        for _ in 0..44100 {
+            let sum = lfo.get_next_value() + opposite.get_next_value();
+            assert!((sum - 1.0).abs() < 1e-3, "sum {sum}");
+        }
+    }
+
+    #[test]
+    fn test_align_to_transport() {
+        // a quarter note LFO at 120 BPM has a period of 22050 samples
+        let mut lfo = MMLFO::new(true, LFOMode::Sine);
+        for _ in 0..1000 {
+            lfo.get_next_value();
+        }
+
+        let mut transport = TransportSync::new(44100.0);
+        transport.set_position(8.25, 120.0, true);
+        lfo.align(&transport);
+        assert!((lfo.phase - 0.25 * 22050.0).abs() < 1.0);
+
+        // an unsynced LFO keeps its own phase
+        let mut free = MMLFO::new(false, LFOMode::Sine);
+        free.get_next_value();
+        free.align(&transport);
+        assert_eq!(free.phase, 1.0);
+    }
 }
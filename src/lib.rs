@@ -2,32 +2,48 @@
 //! GranularPlugin is the main plugin, using the NIH-plug framework to build to the VST3 and CLAP formats.
 //! stat() is used for integration tests.
 //! load_wav() and its float counterpart load samples from a .wav file.
-//! write_wav() and its float counterpart write samples to a .wav file.
+//! write_wav() and its float counterpart write samples to a .wav file, with _rate variants for other sample rates.
 #![warn(missing_docs)]
 
+pub mod analysis;
+pub mod auto_pan;
 pub mod delay_buffer;
 pub mod delay_line;
 pub mod diffusion;
 pub mod envelope;
 pub mod filter;
+pub mod flanger;
 pub mod grain;
 pub mod interpolators;
 pub mod lfo;
+pub mod looper;
+pub mod meters;
 pub mod midi;
 pub mod modulation;
 pub mod multi_channel;
+pub mod oscillator;
+pub mod oversampling;
+pub mod processor;
+pub mod quality;
 pub mod resample;
 pub mod reverb;
 pub mod samples;
 pub mod saturation;
 pub mod smoothers;
+pub mod spectral;
+pub mod stutter;
 pub mod timing;
 
-use samples::PhonicMode;
+#[cfg(test)]
+mod test_util;
+
+use samples::{IntSamples, PhonicMode};
 use std::num::NonZeroU32;
 
 use crate::delay_line::StereoDelay;
-use crate::timing::{NoteModifier, TimeDiv, Timing};
+use crate::quality::Quality;
+use crate::saturation::{ClipMode, Saturator};
+use crate::timing::{NoteModifier, TimeDiv, Timing, TransportSync};
 use hound::SampleFormat::Int;
 use hound::{Error, SampleFormat, WavReader, WavSpec, WavWriter};
 use nih_plug::prelude::*;
@@ -37,9 +53,13 @@ use std::sync::Arc;
 /// # Attributes
 /// * `params`: An Arc containing an instance of `GranularPluginParams`
 /// * `delay`: An instance of `StereoDelay` storing the plugins delay processor
+/// * `output_clipper`: A soft clipping `Saturator` which limits the output when the output clip parameter is on
+/// * `transport`: A `TransportSync` following the host's position, which tempo synced delay lines follow
 struct GranularPlugin {
     params: Arc<GranularPluginParams>,
     delay: StereoDelay,
+    output_clipper: Saturator,
+    transport: TransportSync,
 }
 
 /// The parameters for the main plugin, returned in an Arc type.
@@ -92,13 +112,26 @@ struct GranularPluginParams {
 
     #[id = "Saturate-Mix"]
     pub saturate_mix: FloatParam,
+
+    #[id = "Output-Clip"]
+    pub output_clip: BoolParam,
+
+    #[id = "Quality"]
+    pub quality: EnumParam<Quality>,
 }
 
 impl Default for GranularPlugin {
     fn default() -> Self {
         Self {
             params: Arc::new(GranularPluginParams::default()),
-            delay: StereoDelay::new(44100.0, 0.2, 0.3, 0.4, 0.5),
+            delay: Self::build_delay(44100.0),
+            output_clipper: {
+                // soft clips towards full scale, so hot output is limited gracefully
+                let mut clipper = Saturator::new(1.0, 1.0);
+                clipper.set_clip_mode(ClipMode::Soft);
+                clipper
+            },
+            transport: TransportSync::new(44100.0),
         }
     }
 }
@@ -179,11 +212,68 @@ impl Default for GranularPluginParams {
             .with_smoother(SmoothingStyle::Logarithmic(50.0)),
             saturate_factor: IntParam::new("Dirt", 2, IntRange::Linear { min: 1, max: 32 }),
             saturate_mix: FloatParam::new("Crunch", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            output_clip: BoolParam::new("Output Clip", false),
+            quality: EnumParam::new("Quality", Quality::default()),
         }
     }
 }
 
+/// The largest number of samples processed before smoothed parameters are read again
+const PARAMETER_BLOCK_SIZE: usize = 64;
+
 impl GranularPlugin {
+    /// Builds the delay processor at a sample rate, with its starting times, feedback and mix
+    fn build_delay(sample_rate: f32) -> StereoDelay {
+        StereoDelay::new(sample_rate, 0.2, 0.3, 0.4, 0.5)
+    }
+
+    /// Processes a buffer in place through the delay and output stage.
+    /// nih-plug already splits the buffer at automation events, and it is split further into blocks of at most
+    /// `PARAMETER_BLOCK_SIZE` samples so that the smoothers are stepped regularly, keeping parameter changes
+    /// from zippering
+    fn process_buffer(&mut self, buffer: &mut Buffer) {
+        for (_, mut block) in buffer.iter_blocks(PARAMETER_BLOCK_SIZE) {
+            let block_len = block.samples();
+            let steps = block_len as u32;
+            // Mix and Feedback:
+            self.delay
+                .set_mix(self.params.mix.smoothed.next_step(steps));
+            self.delay
+                .set_feedback(self.params.feedback.smoothed.next_step(steps));
+            // Saturate and Filter:
+            self.delay
+                .set_filter_cutoff(self.params.cutoff.smoothed.next_step(steps));
+            self.delay
+                .set_saturation_factor(self.params.saturate_factor.value() as f32);
+            self.delay
+                .set_saturation_mix(self.params.saturate_mix.smoothed.next_step(steps));
+            self.delay.set_quality(self.params.quality.value());
+
+            for mut channel_samples in block.iter_samples() {
+                let input_l = *channel_samples.get_mut(0).unwrap();
+                let input_r = *channel_samples.get_mut(1).unwrap();
+                let (processed_l, processed_r) = self.delay.process(
+                    input_l,
+                    input_r,
+                    self.params.filter.value(),
+                    self.params.saturate.value(),
+                );
+                // gain is read every sample as it is applied directly to the output
+                let gain = self.params.gain.smoothed.next();
+                let (output_l, output_r) = match self.params.output_clip.value() {
+                    true => (
+                        self.output_clipper.process(processed_l * gain),
+                        self.output_clipper.process(processed_r * gain),
+                    ),
+                    false => (processed_l * gain, processed_r * gain),
+                };
+                *channel_samples.get_mut(0).unwrap() = output_l;
+                *channel_samples.get_mut(1).unwrap() = output_r;
+            }
+            self.transport.advance(block_len);
+        }
+    }
+
     fn update_time(&mut self) {
         match self.params.sync_time.value() {
             true => {
@@ -197,8 +287,8 @@ impl GranularPlugin {
                     self.params.bpm.value() as i16,
                     self.params.right_note_type.value(),
                 );
-                self.delay.set_time_left(new_timing_left.to_seconds());
-                self.delay.set_time_right(new_timing_right.to_seconds());
+                self.delay.set_timing_left(new_timing_left);
+                self.delay.set_timing_right(new_timing_right);
             }
             false => {
                 self.delay
@@ -246,49 +336,33 @@ impl Plugin for GranularPlugin {
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
+        buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
+        // the oversampling filters, ducking follower and delay times all depend on the host's sample rate
+        self.delay = Self::build_delay(buffer_config.sample_rate);
+        self.transport.set_sample_rate(buffer_config.sample_rate);
         true
     }
 
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.delay.clear();
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        // Mix and Feedback:
-        self.delay.set_mix(self.params.mix.value());
-        self.delay.set_feedback(self.params.feedback.value());
-        // Saturate and Filter:
-        self.delay.set_filter_cutoff(self.params.cutoff.value());
-        self.delay
-            .set_saturation_factor(self.params.saturate_factor.value() as f32);
-        self.delay
-            .set_saturation_mix(self.params.saturate_mix.value());
-
-        for mut channel_samples in buffer.iter_samples() {
-            let left = *channel_samples.get_mut(0).unwrap();
-            let right = *channel_samples.get_mut(1).unwrap();
-
-            let (processed_l, processed_r) = self.delay.process(
-                left,
-                right,
-                self.params.filter.value(),
-                self.params.saturate.value(),
-            );
-            *channel_samples.get_mut(0).unwrap() = processed_l * self.params.gain.value();
-            *channel_samples.get_mut(1).unwrap() = processed_r * self.params.gain.value();
-        }
+        self.transport.update(context.transport());
+        self.delay.align(&self.transport);
+        self.process_buffer(buffer);
         ProcessStatus::Normal
     }
 }
@@ -315,35 +389,59 @@ pub fn stat() -> i16 {
     200
 }
 
-/// loads a wav file from string path and returns a result type possibly containing a vector of integer samples
+/// Reads every sample of an integer wav file as i16.
+/// 8, 24 and 32 bit files are read at full resolution and shifted to 16 bits, rather than failing to read as i16
+fn read_int_samples<R: std::io::Read>(reader: &mut WavReader<R>) -> Result<Vec<i16>, Error> {
+    let bits = reader.spec().bits_per_sample;
+    match bits {
+        16 => reader.samples::<i16>().collect(),
+        _ => reader
+            .samples::<i32>()
+            .map(|sample| {
+                sample.map(|s| match bits < 16 {
+                    true => (s << (16 - bits)) as i16,
+                    false => (s >> (bits - 16)) as i16,
+                })
+            })
+            .collect(),
+    }
+}
+
+/// loads a wav file from string path and returns a result type possibly containing a vector of integer samples.
+/// Files of any integer bit depth are loaded, scaled to 16 bits
 /// # Returns
-/// * A result type containing either a vector of i16 samples or a hound error
+/// * A result type containing either a vector of i16 samples or a hound error,
+///     which is `Error::IoError` if the file could not be opened
 /// # Parameters
 /// * `path`: A string containing the relative path to the file to be loaded (must include .wav file extension)
 pub fn load_wav(path: &str) -> Result<Vec<i16>, Error> {
-    let mut reader = WavReader::open(path)
-        .expect("Test audio should be in tests directory and have the path specified");
-    let mut samples: Vec<i16> = vec![];
+    let mut reader = WavReader::open(path)?;
+    read_int_samples(&mut reader)
+}
 
-    // turbofish used to get samples as i16 type
-    for sample in reader.samples::<i16>() {
-        match sample {
-            Ok(s) => samples.push(s),
-            Err(e) => return Err(e),
-        };
-    }
+/// loads a wav file from string path, returning its integer samples along with the sample rate of the file,
+/// so callers can resample or configure their processors rather than assuming 44100Hz.
+/// Files of any integer bit depth are loaded, scaled to 16 bits
+/// # Returns
+/// * A result type containing either a tuple of a vector of i16 samples and the sample rate in Hz, or a hound error
+/// # Parameters
+/// * `path`: A string containing the relative path to the file to be loaded (must include .wav file extension)
+pub fn load_wav_with_spec(path: &str) -> Result<(Vec<i16>, u32), Error> {
+    let mut reader = WavReader::open(path)?;
+    let sample_rate = reader.spec().sample_rate;
+    let samples = read_int_samples(&mut reader)?;
 
-    Ok(samples)
+    Ok((samples, sample_rate))
 }
 
 /// loads a wav file from string path and returns a result type possibly containing a vector of float samples
 /// # Returns
-/// * A result type containing either a vector of f32 samples or a hound error
+/// * A result type containing either a vector of f32 samples or a hound error,
+///     which is `Error::IoError` if the file could not be opened
 /// # Parameters
 /// * `path`: A string containing the relative path to the file to be loaded (must include .wav file extension)
 pub fn load_wav_float(path: &str) -> Result<Vec<f32>, Error> {
-    let mut reader = WavReader::open(path)
-        .expect("Test audio should be in tests directory and have the path specified");
+    let mut reader = WavReader::open(path)?;
     let mut samples: Vec<f32> = vec![];
 
     // turbofish used to get samples as i16 type
@@ -357,24 +455,107 @@ pub fn load_wav_float(path: &str) -> Result<Vec<f32>, Error> {
     Ok(samples)
 }
 
+/// loads a wav file from string path and scales the samples so that the loudest one reaches a target peak
+/// # Returns
+/// * A result type containing either a vector of i16 samples or a hound error
+/// # Parameters
+/// * `path`: A string containing the relative path to the file to be loaded (must include .wav file extension)
+/// * `target_peak`: The peak level to normalize to, as a fraction of full scale (1.0 is i16::MAX)
+pub fn load_wav_normalized(path: &str, target_peak: f32) -> Result<Vec<i16>, Error> {
+    let mut samples = IntSamples::new(load_wav(path)?);
+    samples.normalize(target_peak);
+    Ok(samples.samples())
+}
+
+/// Converts an interleaved sample vector from one channel layout to another.
+/// Mono to stereo duplicates each sample into both channels, stereo to mono averages each pair of samples.
+/// # Parameters
+/// * `samples`: A vector of samples in the `input` layout, stereo samples must be interleaved
+/// * `input`: The channel layout of `samples`
+/// * `output`: The channel layout to convert to
+/// * `average`: A function returning the average of 2 samples, used for downmixing
+fn format_channels<T: Copy>(
+    samples: Vec<T>,
+    input: PhonicMode,
+    output: PhonicMode,
+    average: fn(T, T) -> T,
+) -> Vec<T> {
+    if let PhonicMode::Stereo = input {
+        assert_eq!(
+            samples.len() % 2,
+            0,
+            "stereo samples must be interleaved pairs, but an odd number of samples was given"
+        );
+    }
+
+    match (input, output) {
+        (PhonicMode::Mono, PhonicMode::Stereo) => samples
+            .iter()
+            .flat_map(|sample| [*sample, *sample])
+            .collect(),
+        (PhonicMode::Stereo, PhonicMode::Mono) => samples
+            .chunks_exact(2)
+            .map(|frame| average(frame[0], frame[1]))
+            .collect(),
+        _ => samples,
+    }
+}
+
 /// writes to a wav file at string path from integer samples
 /// # Parameters
 /// * `path`: A string containing the relative path to the file to be written to (must include .wav file extension)
 /// * `samples`: A vector of i16 samples which will be written to the file
-/// * `mode`: An enum variant determining whether sample vector is stereo or mono (interleaved or not)
+/// * `mode`: An enum variant determining whether sample vector is stereo or mono (interleaved or not).
+///     Stereo samples must contain an even number of samples.
 pub fn write_wav(path: &str, samples: Vec<i16>, mode: PhonicMode) {
-    let channels: u16 = match mode {
+    write_wav_as(path, samples, mode, mode)
+}
+
+/// writes to a wav file at string path from integer samples, labelled with a sample rate other than 44100Hz
+/// # Parameters
+/// * `path`: A string containing the relative path to the file to be written to (must include .wav file extension)
+/// * `samples`: A vector of i16 samples which will be written to the file
+/// * `mode`: An enum variant determining whether sample vector is stereo or mono (interleaved or not).
+///     Stereo samples must contain an even number of samples.
+/// * `sample_rate`: The sample rate in Hz the samples were rendered at
+pub fn write_wav_rate(path: &str, samples: Vec<i16>, mode: PhonicMode, sample_rate: u32) {
+    write_wav_as_rate(path, samples, mode, mode, sample_rate)
+}
+
+/// writes to a wav file at string path from integer samples, converting them to a different channel layout
+/// # Parameters
+/// * `path`: A string containing the relative path to the file to be written to (must include .wav file extension)
+/// * `samples`: A vector of i16 samples which will be written to the file
+/// * `input`: The channel layout of `samples`, stereo samples must be interleaved
+/// * `output`: The channel layout of the written file. Mono samples are duplicated to stereo, stereo is averaged to mono
+pub fn write_wav_as(path: &str, samples: Vec<i16>, input: PhonicMode, output: PhonicMode) {
+    write_wav_as_rate(path, samples, input, output, 44100)
+}
+
+/// writes integer samples to a wav file at a sample rate, converting them to a different channel layout
+fn write_wav_as_rate(
+    path: &str,
+    samples: Vec<i16>,
+    input: PhonicMode,
+    output: PhonicMode,
+    sample_rate: u32,
+) {
+    let channels: u16 = match output {
         PhonicMode::Mono => 1,
         PhonicMode::Stereo => 2,
     };
 
     let spec = WavSpec {
         channels,
-        sample_rate: 44100,
+        sample_rate,
         bits_per_sample: 16,
         sample_format: SampleFormat::Int,
     };
 
+    let samples = format_channels(samples, input, output, |a, b| {
+        ((a as i32 + b as i32) / 2) as i16
+    });
+
     let mut writer = WavWriter::create(path, spec).expect("could not create writer");
 
     for sample in samples {
@@ -389,20 +570,55 @@ pub fn write_wav(path: &str, samples: Vec<i16>, mode: PhonicMode) {
 /// # Parameters
 /// * `path`: A string containing the relative path to the file to be written to (must include .wav file extension)
 /// * `samples`: A vector of f32 samples which will be written to the file
-/// * `mode`: An enum variant determining whether sample vector is stereo or mono (interleaved or not)
+/// * `mode`: An enum variant determining whether sample vector is stereo or mono (interleaved or not).
+///     Stereo samples must contain an even number of samples.
 pub fn write_wav_float(path: &str, samples: Vec<f32>, mode: PhonicMode) {
-    let channels: u16 = match mode {
+    write_wav_float_as(path, samples, mode, mode)
+}
+
+/// writes to a wav file at string path from float samples, labelled with a sample rate other than 44100Hz
+/// # Parameters
+/// * `path`: A string containing the relative path to the file to be written to (must include .wav file extension)
+/// * `samples`: A vector of f32 samples which will be written to the file
+/// * `mode`: An enum variant determining whether sample vector is stereo or mono (interleaved or not).
+///     Stereo samples must contain an even number of samples.
+/// * `sample_rate`: The sample rate in Hz the samples were rendered at
+pub fn write_wav_float_rate(path: &str, samples: Vec<f32>, mode: PhonicMode, sample_rate: u32) {
+    write_wav_float_as_rate(path, samples, mode, mode, sample_rate)
+}
+
+/// writes to a wav file at string path from float samples, converting them to a different channel layout
+/// # Parameters
+/// * `path`: A string containing the relative path to the file to be written to (must include .wav file extension)
+/// * `samples`: A vector of f32 samples which will be written to the file
+/// * `input`: The channel layout of `samples`, stereo samples must be interleaved
+/// * `output`: The channel layout of the written file. Mono samples are duplicated to stereo, stereo is averaged to mono
+pub fn write_wav_float_as(path: &str, samples: Vec<f32>, input: PhonicMode, output: PhonicMode) {
+    write_wav_float_as_rate(path, samples, input, output, 44100)
+}
+
+/// writes float samples to a wav file at a sample rate, converting them to a different channel layout
+fn write_wav_float_as_rate(
+    path: &str,
+    samples: Vec<f32>,
+    input: PhonicMode,
+    output: PhonicMode,
+    sample_rate: u32,
+) {
+    let channels: u16 = match output {
         PhonicMode::Mono => 1,
         PhonicMode::Stereo => 2,
     };
 
     let spec = WavSpec {
         channels,
-        sample_rate: 44100,
+        sample_rate,
         bits_per_sample: 32,
         sample_format: SampleFormat::Float,
     };
 
+    let samples = format_channels(samples, input, output, |a, b| (a + b) * 0.5);
+
     let mut writer = WavWriter::create(path, spec).expect("could not create writer");
 
     for sample in samples {
@@ -439,10 +655,46 @@ mod tests {
     use crate::multi_channel::MultiDelayLine;
     use crate::samples::{IntSamples, PhonicMode, Samples};
     use crate::timing::{NoteModifier, TimeDiv, Timing};
-    use crate::{load_wav, write_wav};
+    use crate::{
+        load_wav, load_wav_float, load_wav_normalized, load_wav_with_spec, write_wav, write_wav_as,
+        write_wav_float_rate, write_wav_rate, GranularPlugin, PARAMETER_BLOCK_SIZE,
+    };
+    use hound::{Error, SampleFormat, WavReader, WavSpec, WavWriter};
     use ndarray::Array1;
+    use nih_plug::prelude::Buffer;
     use test_case::test_case;
 
+    #[test]
+    fn test_mix_changes_mid_buffer() {
+        let mut plugin = GranularPlugin::default();
+        // the host wrapper resets the smoothers when the plugin is activated, which is done by hand here
+        plugin.params.gain.smoothed.reset(1.0);
+        plugin.params.mix.smoothed.reset(0.0);
+        plugin.params.mix.smoothed.set_target(44100.0, 1.0);
+
+        let mut channels = vec![vec![1.0_f32; 256]; 2];
+        {
+            let mut buffer = Buffer::default();
+            unsafe {
+                buffer.set_slices(256, |output_slices| {
+                    let (left, right) = channels.split_at_mut(1);
+                    *output_slices = vec![left[0].as_mut_slice(), right[0].as_mut_slice()];
+                })
+            };
+            plugin.process_buffer(&mut buffer);
+        }
+
+        // the echoes haven't arrived yet, so the output is the dry level, which falls as the mix is smoothed up.
+        // The mix is held within each parameter block, and moves between them inside the one host buffer
+        let left = &channels[0];
+        let block = PARAMETER_BLOCK_SIZE;
+        assert_eq!(left[0], left[block - 1]);
+        assert!(left[block] < left[block - 1]);
+        assert!(left[3 * block] < left[2 * block]);
+        assert!(left[0] < 1.0);
+        assert_eq!(channels[0], channels[1]);
+    }
+
     // Reverb Algorithm
     #[test]
     #[ignore]
@@ -533,6 +785,140 @@ mod tests {
         load_wav("tests/amen_br.wav").expect("wav file loaded incorrectly");
     }
 
+    #[test]
+    fn test_write_mono_as_stereo() {
+        let mono: Vec<i16> = (0..1000).map(|n| (n * 20) as i16).collect();
+        write_wav_as(
+            "tests/debug/mono_as_stereo.wav",
+            mono.clone(),
+            PhonicMode::Mono,
+            PhonicMode::Stereo,
+        );
+
+        let reader = WavReader::open("tests/debug/mono_as_stereo.wav").unwrap();
+        assert_eq!(reader.spec().channels, 2);
+
+        let written = IntSamples::new(load_wav("tests/debug/mono_as_stereo.wav").unwrap());
+        let frames = written.get_frames();
+        assert_eq!(frames.len(), mono.len());
+        for ((left, right), sample) in frames.iter().zip(mono.iter()) {
+            assert_eq!(left, sample);
+            assert_eq!(right, sample);
+        }
+    }
+
+    #[test]
+    fn test_load_with_spec() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create("tests/debug/48k.wav", spec).unwrap();
+        for n in 0..480 {
+            writer.write_sample((n * 10) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let (samples, sample_rate) = load_wav_with_spec("tests/debug/48k.wav").unwrap();
+        assert_eq!(sample_rate, 48000);
+        assert_eq!(samples.len(), 480);
+        assert_eq!(samples[479], 4790);
+    }
+
+    #[test]
+    fn test_write_sample_rate() {
+        write_wav_rate(
+            "tests/debug/rate_96k.wav",
+            vec![0; 96],
+            PhonicMode::Mono,
+            96000,
+        );
+        let reader = WavReader::open("tests/debug/rate_96k.wav").unwrap();
+        assert_eq!(reader.spec().sample_rate, 96000);
+
+        write_wav_float_rate(
+            "tests/debug/rate_48k_float.wav",
+            vec![0.0; 96],
+            PhonicMode::Stereo,
+            48000,
+        );
+        let reader = WavReader::open("tests/debug/rate_48k_float.wav").unwrap();
+        assert_eq!(reader.spec().sample_rate, 48000);
+        assert_eq!(reader.spec().channels, 2);
+
+        // the original functions still label files as 44100Hz
+        write_wav("tests/debug/rate_44k.wav", vec![0; 96], PhonicMode::Mono);
+        let reader = WavReader::open("tests/debug/rate_44k.wav").unwrap();
+        assert_eq!(reader.spec().sample_rate, 44100);
+    }
+
+    /// Writes a mono integer wav file of a bit depth, with the given samples
+    fn write_int_wav(path: &str, bits_per_sample: u16, samples: &[i32]) {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for sample in samples {
+            match bits_per_sample {
+                8 => writer.write_sample(*sample as i8).unwrap(),
+                _ => writer.write_sample(*sample).unwrap(),
+            }
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_load_other_bit_depths() {
+        // full scale, half scale and silence are kept at the same level in 16 bits
+        write_int_wav("tests/debug/24_bit.wav", 24, &[8388607, -4194304, 0]);
+        assert_eq!(
+            load_wav("tests/debug/24_bit.wav").unwrap(),
+            vec![32767, -16384, 0]
+        );
+
+        write_int_wav("tests/debug/8_bit.wav", 8, &[127, -64, 0]);
+        assert_eq!(
+            load_wav("tests/debug/8_bit.wav").unwrap(),
+            vec![32512, -16384, 0]
+        );
+    }
+
+    #[test]
+    fn test_load_normalized() {
+        // the sine fixture peaks at less than a third of full scale
+        let samples = load_wav_normalized("tests/sine.wav", 0.95).unwrap();
+        let peak = samples
+            .iter()
+            .map(|sample| (*sample as i32).abs())
+            .max()
+            .unwrap();
+        let target = 0.95 * i16::MAX as f32;
+        assert!((peak as f32 - target).abs() < target * 0.001);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_write_odd_stereo_panics() {
+        write_wav("tests/debug/odd_stereo.wav", vec![0; 3], PhonicMode::Stereo);
+    }
+
+    #[test]
+    fn test_missing_file_is_error() {
+        assert!(matches!(
+            load_wav("doesnt/exist.wav"),
+            Err(Error::IoError(_))
+        ));
+        assert!(matches!(
+            load_wav_float("doesnt/exist.wav"),
+            Err(Error::IoError(_))
+        ));
+    }
+
     #[test]
     #[should_panic]
     #[ignore]
@@ -0,0 +1,190 @@
+#![allow(dead_code)]
+#![warn(missing_docs)]
+//! A module containing a looper, for recording a phrase of live audio and layering over it.
+//! Looper records a loop of any length up to a maximum, then plays it back, optionally overdubbing the input
+//! on top with a feedback level which fades older layers each time around.
+
+/// An enum of the states a `Looper` can be in
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum LooperState {
+    /// Nothing is recorded or played
+    #[default]
+    Stopped,
+    /// The input is being recorded, the loop length grows until playback starts
+    Recording,
+    /// The loop is played back
+    Playing,
+    /// The loop is played back while the input is summed into it
+    Overdubbing,
+}
+
+/// A struct which records a loop from its input and plays it back, with overdubbing.
+/// ## Attributes:
+/// * `buffer`: The recorded loop, preallocated to the maximum length so that recording doesn't allocate
+///
+/// * `max_len`: The longest loop which can be recorded, in samples
+///
+/// * `state`: Whether the looper is stopped, recording, playing or overdubbing
+///
+/// * `position`: The read position within the loop
+///
+/// * `feedback`: The level existing loop content is kept at each time it is overdubbed, from 0 to 1
+pub struct Looper {
+    buffer: Vec<f32>,
+    max_len: usize,
+    state: LooperState,
+    position: usize,
+    feedback: f32,
+}
+
+impl Looper {
+    /// Constructor for the looper
+    /// # Parameters
+    /// * `max_seconds`: The longest loop that can be recorded, in seconds
+    /// * `sample_rate`: The sample rate of the input in Hz
+    pub fn new(max_seconds: f32, sample_rate: f32) -> Self {
+        let max_len = ((max_seconds * sample_rate) as usize).max(1);
+        Self {
+            buffer: Vec::with_capacity(max_len),
+            max_len,
+            state: LooperState::Stopped,
+            position: 0,
+            feedback: 1.0,
+        }
+    }
+
+    /// Starts recording a new loop, replacing any existing one
+    pub fn record(&mut self) {
+        self.buffer.clear();
+        self.position = 0;
+        self.state = LooperState::Recording;
+    }
+
+    /// Starts playing the loop. When recording, this ends the recording and sets the loop length.
+    /// Does nothing if no loop has been recorded.
+    pub fn play(&mut self) {
+        if self.state == LooperState::Recording {
+            self.position = 0;
+        }
+        if !self.buffer.is_empty() {
+            self.state = LooperState::Playing;
+        }
+    }
+
+    /// Starts overdubbing, summing the input into the loop as it plays.
+    /// When recording, this ends the recording first. Does nothing if no loop has been recorded.
+    pub fn overdub(&mut self) {
+        self.play();
+        if self.state == LooperState::Playing {
+            self.state = LooperState::Overdubbing;
+        }
+    }
+
+    /// Stops recording or playback, keeping the loop so it can be played again from the start
+    pub fn stop(&mut self) {
+        self.position = 0;
+        self.state = LooperState::Stopped;
+    }
+
+    /// Setter for the feedback applied to existing loop content as it is overdubbed.
+    /// Values below 1 fade older layers a little each time around the loop.
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, 1.0);
+    }
+
+    /// Getter for the current state of the looper
+    pub fn state(&self) -> LooperState {
+        self.state
+    }
+
+    /// The length of the recorded loop in samples
+    pub fn loop_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Takes an f32 input, recording or overdubbing it depending on the state, and returns the loop's output.
+    /// Only the loop is output, the input isn't passed through.
+    pub fn process(&mut self, xn: f32) -> f32 {
+        match self.state {
+            LooperState::Stopped => 0.0,
+            LooperState::Recording => {
+                self.buffer.push(xn);
+                // a full buffer ends the recording, looping what was captured
+                if self.buffer.len() == self.max_len {
+                    self.play();
+                }
+                0.0
+            }
+            LooperState::Playing => {
+                let value = self.buffer[self.position];
+                self.position = (self.position + 1) % self.buffer.len();
+                value
+            }
+            LooperState::Overdubbing => {
+                let value = self.buffer[self.position];
+                self.buffer[self.position] = value * self.feedback + xn;
+                self.position = (self.position + 1) % self.buffer.len();
+                value
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::looper::{Looper, LooperState};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_overdub_layers() {
+        let first_layer = |n: usize| (2.0 * PI * n as f32 / 100.0).sin();
+        let second_layer = |n: usize| 0.5 * (2.0 * PI * n as f32 / 25.0).sin();
+
+        let mut looper = Looper::new(1.0, 44100.0);
+        looper.record();
+        for n in 0..1000 {
+            assert_eq!(looper.process(first_layer(n)), 0.0);
+        }
+
+        // the first pass overdubbing plays only the recorded layer
+        looper.overdub();
+        assert_eq!(looper.loop_len(), 1000);
+        for n in 0..1000 {
+            assert_eq!(looper.process(second_layer(n)), first_layer(n));
+        }
+
+        // afterwards both layers are heard together
+        looper.play();
+        for n in 0..1000 {
+            let expected = first_layer(n) + second_layer(n);
+            assert!((looper.process(1.0) - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_overdub_feedback_fades() {
+        let mut looper = Looper::new(1.0, 44100.0);
+        looper.set_feedback(0.5);
+        looper.record();
+        for _ in 0..100 {
+            looper.process(1.0);
+        }
+
+        // overdubbing silence halves the original layer each time around
+        looper.overdub();
+        for _ in 0..300 {
+            looper.process(0.0);
+        }
+        looper.play();
+        assert_eq!(looper.process(0.0), 0.125);
+
+        // a full buffer stops recording and starts playback
+        let mut looper = Looper::new(0.001, 44100.0);
+        looper.record();
+        for _ in 0..44 {
+            looper.process(1.0);
+        }
+        assert_eq!(looper.state(), LooperState::Playing);
+        assert_eq!(looper.process(0.0), 1.0);
+    }
+}
@@ -0,0 +1,245 @@
+//! Module containing real-time safe metering, for sending values from the audio thread to the UI.
+//! Meters is a lock-free single producer, single consumer ring buffer which never allocates or blocks when written to.
+//! StereoMeter measures the peak of each channel and the correlation between them, for checking mono compatibility.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// A lock-free ring buffer of recent peak values and a count of events, shared between the audio and UI threads.
+///
+/// The audio thread is the only writer, calling `push_peak` and `count_event`, neither of which allocate or lock.
+/// The UI thread is the only reader, calling `drain` and `event_count`.
+/// Share a single instance between the threads by wrapping it in an `Arc`.
+/// # Attributes
+/// * `peaks`: The ring buffer of peak values, stored as the bits of an f32 so they can be atomic
+/// * `write_index`: The total number of peaks written, only changed by the audio thread
+/// * `read_index`: The total number of peaks read, only changed by the UI thread
+/// * `dropped`: The number of peaks discarded because the buffer was full
+/// * `events`: The number of events counted by the audio thread
+pub struct Meters {
+    peaks: Vec<AtomicU32>,
+    write_index: AtomicUsize,
+    read_index: AtomicUsize,
+    dropped: AtomicUsize,
+    events: AtomicUsize,
+}
+
+impl Meters {
+    /// Constructor for the meters, allocating space for `capacity` peak values which haven't been drained
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            peaks: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+            write_index: AtomicUsize::new(0),
+            read_index: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            events: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pushes a peak value from the audio thread. Never blocks, if the reader has fallen behind and the buffer is full
+    /// the value is dropped and false is returned.
+    pub fn push_peak(&self, peak: f32) -> bool {
+        let write = self.write_index.load(Ordering::Relaxed);
+        let read = self.read_index.load(Ordering::Acquire);
+        if write - read >= self.peaks.len() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        self.peaks[write % self.peaks.len()].store(peak.to_bits(), Ordering::Relaxed);
+        // publish the value only once it has been stored
+        self.write_index.store(write + 1, Ordering::Release);
+        true
+    }
+
+    /// Increments the event count from the audio thread, such as for each note or grain triggered
+    pub fn count_event(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reads every peak pushed since the last drain on the UI thread, oldest first
+    pub fn drain(&self) -> Vec<f32> {
+        let read = self.read_index.load(Ordering::Relaxed);
+        let write = self.write_index.load(Ordering::Acquire);
+
+        let values = (read..write)
+            .map(|index| {
+                f32::from_bits(self.peaks[index % self.peaks.len()].load(Ordering::Relaxed))
+            })
+            .collect();
+        // free the slots for the writer only once they have been read
+        self.read_index.store(write, Ordering::Release);
+        values
+    }
+
+    /// Getter for the number of events counted so far
+    pub fn event_count(&self) -> usize {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Getter for the number of peaks dropped because the buffer was full
+    pub fn dropped_count(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// A struct measuring the peak level of each channel of a stereo signal, and the Pearson correlation
+/// coefficient between the channels, over consecutive windows of samples.
+///
+/// A correlation near 1 means the channels are alike and the signal is safe to sum to mono,
+/// near 0 means they are unrelated, and near -1 means they cancel out when summed.
+/// The getters report the most recently completed window.
+/// # Attributes
+/// * `window_len`: The number of samples in each measurement window
+/// * `count`: The number of samples accumulated in the current window
+/// * `peaks`: The largest absolute (left, right) samples in the current window
+/// * `sums`: The sums of the left, right, left squared, right squared and left times right samples in the current window
+/// * `peak_left`: The left peak of the last completed window
+/// * `peak_right`: The right peak of the last completed window
+/// * `correlation`: The correlation of the last completed window
+pub struct StereoMeter {
+    window_len: usize,
+    count: usize,
+    peaks: (f32, f32),
+    sums: [f64; 5],
+    peak_left: f32,
+    peak_right: f32,
+    correlation: f32,
+}
+
+impl StereoMeter {
+    /// Constructor for the meter given the length of its measurement window in samples
+    pub fn new(window_len: usize) -> Self {
+        Self {
+            window_len: window_len.max(1),
+            count: 0,
+            peaks: (0.0, 0.0),
+            sums: [0.0; 5],
+            peak_left: 0.0,
+            peak_right: 0.0,
+            correlation: 0.0,
+        }
+    }
+
+    /// Accumulates a pair of samples, publishing the measurements each time a window completes
+    pub fn process(&mut self, left: f32, right: f32) {
+        self.peaks = (self.peaks.0.max(left.abs()), self.peaks.1.max(right.abs()));
+        let (l, r) = (left as f64, right as f64);
+        for (sum, value) in self.sums.iter_mut().zip([l, r, l * l, r * r, l * r]) {
+            *sum += value;
+        }
+        self.count += 1;
+
+        if self.count == self.window_len {
+            (self.peak_left, self.peak_right) = self.peaks;
+            self.correlation = self.window_correlation();
+            self.count = 0;
+            self.peaks = (0.0, 0.0);
+            self.sums = [0.0; 5];
+        }
+    }
+
+    /// The Pearson correlation coefficient of the current window, 0 if either channel is constant
+    fn window_correlation(&self) -> f32 {
+        let n = self.count as f64;
+        let [sum_l, sum_r, sum_ll, sum_rr, sum_lr] = self.sums;
+        let covariance = n * sum_lr - sum_l * sum_r;
+        let variance = (n * sum_ll - sum_l * sum_l) * (n * sum_rr - sum_r * sum_r);
+        match variance > f64::EPSILON {
+            true => (covariance / variance.sqrt()).clamp(-1.0, 1.0) as f32,
+            false => 0.0,
+        }
+    }
+
+    /// Getter for the peak absolute level of the left channel
+    pub fn peak_left(&self) -> f32 {
+        self.peak_left
+    }
+
+    /// Getter for the peak absolute level of the right channel
+    pub fn peak_right(&self) -> f32 {
+        self.peak_right
+    }
+
+    /// Getter for the correlation between the channels, from -1 to 1
+    pub fn correlation(&self) -> f32 {
+        self.correlation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::meters::{Meters, StereoMeter};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_push_and_drain() {
+        let meters = Meters::new(4);
+        for peak in [0.1, 0.2, 0.3, 0.4] {
+            assert!(meters.push_peak(peak));
+        }
+        // the buffer is full, so the writer drops the value instead of waiting
+        assert!(!meters.push_peak(0.5));
+        assert_eq!(meters.dropped_count(), 1);
+
+        assert_eq!(meters.drain(), vec![0.1, 0.2, 0.3, 0.4]);
+        assert!(meters.drain().is_empty());
+        assert!(meters.push_peak(0.6));
+        assert_eq!(meters.drain(), vec![0.6]);
+    }
+
+    #[test]
+    fn test_concurrent_reader() {
+        let meters = Arc::new(Meters::new(64));
+        let writer_meters = meters.clone();
+
+        let writer = thread::spawn(move || {
+            for n in 0..10000 {
+                writer_meters.push_peak(n as f32);
+                writer_meters.count_event();
+            }
+        });
+
+        let mut received = Vec::new();
+        while !writer.is_finished() {
+            received.extend(meters.drain());
+        }
+        writer.join().unwrap();
+        received.extend(meters.drain());
+
+        assert_eq!(meters.event_count(), 10000);
+        assert_eq!(received.len() + meters.dropped_count(), 10000);
+        // values arrive in the order they were pushed, even when some are dropped
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_stereo_correlation() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let noise: Vec<f32> = (0..4096).map(|_| rng.gen_range(-0.5..0.5)).collect();
+        let measure = |pair: fn(f32) -> (f32, f32)| {
+            let mut meter = StereoMeter::new(4096);
+            for sample in noise.iter() {
+                let (left, right) = pair(*sample);
+                meter.process(left, right);
+            }
+            meter
+        };
+
+        let identical = measure(|x| (x, x));
+        assert!((identical.correlation() - 1.0).abs() < 1e-3);
+
+        let anti_phase = measure(|x| (x, -0.5 * x));
+        assert!((anti_phase.correlation() + 1.0).abs() < 1e-3);
+        let peak = noise.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+        assert_eq!(anti_phase.peak_left(), peak);
+        assert_eq!(anti_phase.peak_right(), 0.5 * peak);
+
+        let silent_right = measure(|x| (x, 0.0));
+        assert_eq!(silent_right.correlation(), 0.0);
+    }
+}
@@ -2,16 +2,18 @@
 //! Contains a struct for mock midi messages, called NoteMessage.
 //! This only allows for Midi Note messages, with no note off message, and predetermined timing.
 //! This struct interfaces with the interpolator method of repitching.
+//! Note velocities are shaped into gains by a VelocityCurve, to scale the envelope.
 
 use crate::resample::semitone_to_hz_ratio;
 
-/// Note message which contains an optional midi note number and duration in seconds
+/// Note message which contains an optional midi note number, a velocity from 0 to 127 and duration in seconds
 ///
 /// The note being `None` can be interpreted as the note being gate off, which is used for the gate behaviour of various objects
 ///
 /// The time is used by the midi manager to determine when to load the next note, in a sort of sequence behaviour
 pub struct NoteMessage {
     note: Option<u8>,
+    velocity: u8,
     time_s: f32,
 }
 
@@ -95,10 +97,20 @@ impl NoteMessage {
         (note + (21 + octave) as i8) as u8
     }
 
-    /// The constructor for a midi note given a valid note name and the duration in seconds
+    /// The constructor for a midi note given a valid note name and the duration in seconds, at full velocity
     pub fn new(name: &str, time: f32) -> Self {
         Self {
             note: Some(Self::midi_note_from_name(name)),
+            velocity: 127,
+            time_s: time,
+        }
+    }
+
+    /// The constructor for a midi note given its midi note number, velocity (clamped to 127) and duration in seconds
+    pub fn from_midi(note: u8, velocity: u8, time: f32) -> Self {
+        Self {
+            note: Some(note),
+            velocity: velocity.min(127),
             time_s: time,
         }
     }
@@ -113,42 +125,96 @@ impl NoteMessage {
         self.note.unwrap_or(0)
     }
 
+    /// Get the velocity of the note, from 0 to 127
+    pub fn get_velocity(&self) -> u8 {
+        self.velocity
+    }
+
     /// Reusable constant instance with no note, to save time in removing Note to a gateless value
     const NONE: Self = Self {
         note: None,
+        velocity: 0,
         time_s: 0.0,
     };
 }
 
+/// An enum of the curves which map a note's velocity to the gain it plays at
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum VelocityCurve {
+    /// The gain is proportional to the velocity
+    #[default]
+    Linear,
+    /// The gain rises slowly at low velocities and quickly at high ones, so soft playing stays quiet
+    Exponential,
+    /// The gain rises quickly at low velocities and levels off, so soft playing is still clearly heard
+    Logarithmic,
+}
+
+impl VelocityCurve {
+    /// Maps a velocity between 0 and 1 to a gain between 0 and 1, both curves pass through 0 and 1
+    pub fn apply(&self, velocity: f32) -> f32 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential => (10.0_f32.powf(velocity) - 1.0) / 9.0,
+            VelocityCurve::Logarithmic => (1.0 + 9.0 * velocity).log10(),
+        }
+    }
+}
+
 /// Struct which manages midi notes and can output a frequency ratio for repitching.
+/// # Attributes
+/// * `current_event`: The note message currently being played
+/// * `samples_remaining`: The number of ticks left before the current note ends.
+/// Counted in whole samples so that the note length doesn't drift with float error.
+/// * `sample_rate`: The rate at which `tick` is called, in Hz
+/// * `velocity_curve`: The curve mapping the velocity of the current note to its gain
 pub struct MidiManager {
     current_event: NoteMessage,
-    current_timer: f32,
+    samples_remaining: usize,
+    sample_rate: f32,
+    velocity_curve: VelocityCurve,
 }
 
 impl MidiManager {
     /// Constructor with a default value of no midi message
-    pub fn new() -> Self {
+    /// # Parameters
+    /// * `sample_rate`: The rate at which `tick` will be called, in Hz
+    pub fn new(sample_rate: f32) -> Self {
         Self {
             current_event: NoteMessage::NONE,
-            current_timer: 0.0,
+            samples_remaining: 0,
+            sample_rate,
+            velocity_curve: VelocityCurve::Linear,
         }
     }
 
+    /// Setter for the sample rate. Rescales the time remaining on the current note.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let time_remaining = self.time_remaining();
+        self.sample_rate = sample_rate;
+        self.samples_remaining = (time_remaining * sample_rate).round() as usize;
+    }
+
     /// Set the current note event given an instance of NoteMessage
     pub fn set_note_event(&mut self, event: NoteMessage) {
-        self.current_timer = event.get_time();
+        self.samples_remaining = (event.get_time() * self.sample_rate).round() as usize;
         self.current_event = event;
     }
 
-    /// Decrease the timer, used for gate signals, uses 44100Hz sample rate.
+    /// Decrease the timer by one sample, used for gate signals.
     pub fn tick(&mut self) {
-        self.current_timer -= 1.0 / 44100.0;
-        if self.current_timer < 0.0 {
+        self.samples_remaining = self.samples_remaining.saturating_sub(1);
+        if self.samples_remaining == 0 {
             self.current_event = NoteMessage::NONE
         }
     }
 
+    /// Returns the time left on the current note in seconds
+    pub fn time_remaining(&self) -> f32 {
+        self.samples_remaining as f32 / self.sample_rate
+    }
+
     /// Returns a boolean based on whether the note is a valid note or 0, which indicates an empty event
     pub fn get_gate(&self) -> bool {
         !matches!(self.current_event.get_note(), 0)
@@ -162,18 +228,97 @@ impl MidiManager {
         semitone_to_hz_ratio(semitones)
     }
 
+    /// Setter for the curve which maps note velocities to gains
+    pub fn set_velocity_curve(&mut self, curve: VelocityCurve) {
+        self.velocity_curve = curve;
+    }
+
+    /// Get the gain of the current note from its velocity, shaped by the velocity curve.
+    /// The envelope is multiplied by this, and it is 0 when no note is playing.
+    pub fn get_velocity_gain(&self) -> f32 {
+        self.velocity_curve
+            .apply(self.current_event.get_velocity() as f32 / 127.0)
+    }
+
     /// Get the number of semitones from middle C
     pub fn get_semitones(&self) -> i8 {
-        let note = self.current_event.get_note() as i8;
-        // 72 is the midi number of C5 - middle C
-        -(72 - note)
+        note_to_semitones(self.current_event.get_note())
+    }
+}
+
+/// The midi number of C5 - middle C, the note a sample is assumed to be pitched at
+pub const ROOT_NOTE: u8 = 72;
+
+/// Returns the distance in semitones of a midi note from the root note C5, negative for lower notes
+pub fn note_to_semitones(note: u8) -> i8 {
+    note as i8 - ROOT_NOTE as i8
+}
+
+/// An enum of musical scales which pitches can be quantized to.
+///
+/// Each scale is stored as a set of semitone offsets from its root, within a single octave.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum Scale {
+    #[default]
+    /// Every semitone is allowed, so no quantization takes place
+    Chromatic,
+    /// The major (Ionian) scale
+    Major,
+    /// The natural minor (Aeolian) scale
+    Minor,
+    /// The harmonic minor scale, a natural minor with a raised 7th
+    HarmonicMinor,
+    /// The 5 note major pentatonic scale
+    Pentatonic,
+    /// The 5 note minor pentatonic scale
+    MinorPentatonic,
+}
+
+impl Scale {
+    /// Returns the semitone offsets of each scale degree from the root, between 0 and 11
+    pub fn intervals(&self) -> &'static [i8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+}
+
+/// Snaps a semitone offset (relative to the root of the scale) to the nearest degree of the scale.
+///
+/// The scale is given as semitone offsets within one octave, as returned by `Scale::intervals`.
+/// If two degrees are equally close, the lower one is chosen.
+///
+/// Example with a major scale:
+///
+/// ` quantize_semitones(1, &[0, 2, 4, 5, 7, 9, 11]) -> 0 `
+/// ` quantize_semitones(-2, &[0, 2, 4, 5, 7, 9, 11]) -> -3 `
+pub fn quantize_semitones(semitones: i8, scale: &[i8]) -> i8 {
+    if scale.is_empty() {
+        return semitones;
     }
+    // search outwards from the input, checking below before above so ties round down
+    for distance in 0..12_i8 {
+        for candidate in [
+            semitones.saturating_sub(distance),
+            semitones.saturating_add(distance),
+        ] {
+            if scale.contains(&candidate.rem_euclid(12)) {
+                return candidate;
+            }
+        }
+    }
+    semitones
 }
 
 #[cfg(test)]
 mod tests {
     use crate::grain::{GrainManager, GrainMode};
-    use crate::midi::{MidiManager, NoteMessage};
+    use crate::midi::{quantize_semitones, MidiManager, NoteMessage, Scale, VelocityCurve};
     use crate::resample::LinearResampler;
     use crate::samples::PhonicMode;
     use crate::{load_wav, write_wav};
@@ -199,11 +344,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_quantize_major() {
+        let major = Scale::Major.intervals();
+        for semitones in -24..=24 {
+            let quantized = quantize_semitones(semitones, major);
+            assert!(major.contains(&quantized.rem_euclid(12)));
+            assert!((quantized - semitones).abs() <= 1);
+        }
+        assert_eq!(quantize_semitones(1, major), 0);
+        assert_eq!(quantize_semitones(6, major), 5);
+        assert_eq!(quantize_semitones(-12, major), -12);
+        assert_eq!(quantize_semitones(-2, major), -3);
+    }
+
+    #[test]
+    fn test_note_length_at_48k() {
+        let mut midi_manager = MidiManager::new(48000.0);
+        midi_manager.set_note_event(NoteMessage {
+            note: Some(72),
+            velocity: 127,
+            time_s: 1.0,
+        });
+        assert_eq!(midi_manager.time_remaining(), 1.0);
+
+        for _ in 0..47999 {
+            midi_manager.tick();
+        }
+        assert!(midi_manager.get_gate());
+
+        midi_manager.tick();
+        assert!(!midi_manager.get_gate());
+        assert_eq!(midi_manager.time_remaining(), 0.0);
+    }
+
     #[test]
     fn test_pitch() {
         let input = load_wav("tests/kalimba.wav").unwrap();
         let mut resampler = LinearResampler::new(&input, 1.0);
-        let mut midi_manager = MidiManager::new();
+        let mut midi_manager = MidiManager::new(44100.0);
 
         let mut out: Vec<i16> = Vec::new();
 
@@ -246,7 +425,7 @@ mod tests {
 
         let mut out: Vec<i16> = Vec::new();
 
-        let mut midi_manager = MidiManager::new();
+        let mut midi_manager = MidiManager::new(44100.0);
 
         let mut events = VecDeque::from([
             NoteMessage::new("C5", 2.0),
@@ -274,4 +453,37 @@ mod tests {
             PhonicMode::Stereo,
         );
     }
+
+    #[test]
+    fn test_velocity_curves() {
+        let mut manager = MidiManager::new(44100.0);
+        manager.set_note_event(NoteMessage::from_midi(72, 64, 1.0));
+
+        let linear = manager.get_velocity_gain();
+        manager.set_velocity_curve(VelocityCurve::Exponential);
+        let exponential = manager.get_velocity_gain();
+        manager.set_velocity_curve(VelocityCurve::Logarithmic);
+        let logarithmic = manager.get_velocity_gain();
+
+        // a mid velocity is around half gain when linear, quieter when exponential and louder when logarithmic
+        assert!((linear - 0.504).abs() < 0.001);
+        assert!((exponential - 0.243).abs() < 0.001);
+        assert!((logarithmic - 0.743).abs() < 0.001);
+
+        // every curve agrees at the extremes
+        for curve in [
+            VelocityCurve::Linear,
+            VelocityCurve::Exponential,
+            VelocityCurve::Logarithmic,
+        ] {
+            assert!(curve.apply(0.0).abs() < 1e-6);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6);
+        }
+
+        // once the note ends there is no gain
+        for _ in 0..44100 {
+            manager.tick();
+        }
+        assert_eq!(manager.get_velocity_gain(), 0.0);
+    }
 }
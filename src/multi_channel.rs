@@ -5,11 +5,13 @@
 //! Hadamard mixer is a struct that stores a matrix and can perform mixing by multiplying the input vector by the matrix
 //! Multi delay line has a vector of delay times and buffers.
 //! Will process the input through the delays independently and then mix them using the Hadamard matrix
+//! Downmix matrix is a struct that stores a gain per channel for each side and folds an N channel array down to stereo
 
 use crate::delay_buffer::DelayBuffer;
+use crate::filter::LowpassFilter;
 use ndarray::linalg::{general_mat_vec_mul, kron};
-use ndarray::{arr1, arr2, Array, Array1, Ix1, Ix2};
-use std::f32::consts::FRAC_1_SQRT_2;
+use ndarray::{arr1, arr2, Array, Array1, ArrayView1, Ix1, Ix2};
+use std::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
 
 /// A function generating a Hadamard matrix from given dimension
 /// # Parameters
@@ -52,21 +54,51 @@ impl HadamardMixer {
 
         mixed
     }
+
+    /// The gain a channel is mixed back into itself with, the scaled diagonal entry of the matrix
+    pub fn self_gain(&self, channel: usize) -> f32 {
+        self.scalar * self.matrix[[channel, channel]]
+    }
+}
+
+/// An enum of the ways the delayed channels of a multi delay line can be routed back into its inputs.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum FeedbackTopology {
+    #[default]
+    /// Every channel feeds back into every other channel through the Hadamard mixing matrix
+    Matrix,
+    /// Each channel feeds back into the next one, with the last channel feeding the first, forming a ring
+    Series,
+    /// Each channel feeds back only into itself, with no cross-feedback
+    Independent,
 }
 
 /// A struct storing functionality relating to delay lines in multiples of 2.
 /// Stores a vector of buffers and a vector of times which correspond to delay lines of those times.
-/// Stores feedback and mix levels, which are uniform for each delay line.
+/// Stores a feedback gain for each delay line, uniform unless derived from a decay time, and a mix level.
 /// Stores a HadamardMixer which is used to mix the input channels in each feedback loop.
+/// Stores a FeedbackTopology which decides how the delayed channels are routed back into the buffers.
+/// Optionally tracks the RMS level of the summed input and output, scaling the output to match the input level.
+/// Optionally lowpass filters the feedback passed between channels, leaving each channel's feedback into itself untouched.
 pub struct MultiDelayLine {
     delay_buffers: Vec<DelayBuffer>,
     mixer: HadamardMixer,
-    feedback: f32,
+    feedback_gains: Vec<f32>,
     times_samples: Vec<usize>,
     num_channels: u8,
     mix_ratio: f32,
+    topology: FeedbackTopology,
+    output_normalize: bool,
+    input_mean_square: f32,
+    output_mean_square: f32,
+    crossfeed_filters: Option<Vec<LowpassFilter>>,
 }
 
+/// The one-pole coefficient of the RMS trackers used for output normalization, a time constant of 100ms at 44100Hz
+const RMS_TRACKING_COEFF: f32 = 0.999_773_3;
+/// The largest gain output normalization can apply, so that near silent tails aren't amplified into noise
+const MAX_NORMALIZE_GAIN: f32 = 4.0;
+
 impl MultiDelayLine {
     /// Constructor for the multi delay line, which takes a vector of times, number of channels and feedback and mix levels as well as max delay samples, and returns an instance of the class.
     pub fn new(
@@ -80,20 +112,88 @@ impl MultiDelayLine {
             // creates a vector of buffers initialized to capacity 'max_delay_samples'
             delay_buffers: vec![DelayBuffer::new(max_delay_samples); num_channels as usize],
             mixer: HadamardMixer::new(num_channels),
-            feedback,
+            feedback_gains: vec![feedback; num_channels as usize],
             times_samples: times_s
                 .iter()
                 .map(|time| (time * 44100.0) as usize)
                 .collect(),
             num_channels,
             mix_ratio: mix,
+            topology: FeedbackTopology::Matrix,
+            output_normalize: false,
+            input_mean_square: 0.0,
+            output_mean_square: 0.0,
+            crossfeed_filters: None,
         }
     }
 
+    /// Silences every channel's delay buffer, along with the crossfeed filters and loudness tracking
+    pub fn clear(&mut self) {
+        self.delay_buffers
+            .iter_mut()
+            .for_each(|buffer| buffer.clear());
+        if let Some(filters) = self.crossfeed_filters.as_mut() {
+            filters.iter_mut().for_each(|filter| filter.reset());
+        }
+        self.input_mean_square = 0.0;
+        self.output_mean_square = 0.0;
+    }
+
+    /// Setter for output normalization. When on, the output is scaled so the RMS level of the summed output
+    /// follows the RMS level of the summed input, keeping a consistent loudness regardless of channel count.
+    pub fn set_output_normalize(&mut self, on: bool) {
+        self.output_normalize = on;
+        self.input_mean_square = 0.0;
+        self.output_mean_square = 0.0;
+    }
+
+    /// Setter for a single feedback gain shared by every channel
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback_gains.fill(feedback);
+    }
+
+    /// Sets the feedback gain of each channel so that every channel decays by 60dB in the same time,
+    /// regardless of its delay length. Shorter delays recirculate more often, so are given lower gains.
+    /// # Parameters
+    /// * `seconds`: The time taken for the feedback to decay by 60dB (RT60)
+    pub fn set_rt60(&mut self, seconds: f32) {
+        let decay_samples = seconds.max(f32::EPSILON) * 44100.0;
+        for (gain, time) in self
+            .feedback_gains
+            .iter_mut()
+            .zip(self.times_samples.iter())
+        {
+            // the loop is one sample longer than the delay, as the buffer is read before it is written
+            let loop_samples = (*time + 1) as f32;
+            *gain = 10.0_f32.powf(-3.0 * loop_samples / decay_samples);
+        }
+    }
+
+    /// Setter for crossfeed damping, a lowpass filter on the feedback passed from one channel into the others.
+    /// Softening the coupling between channels reduces the metallic ringing of dense feedback networks.
+    /// Has no effect with the `Independent` topology, as no feedback passes between channels.
+    /// # Parameters
+    /// * `cutoff`: The cutoff frequency of the damping filters in Hz, a cutoff of 0 turns damping off
+    pub fn set_crossfeed_damping(&mut self, cutoff: f32) {
+        self.crossfeed_filters = match cutoff {
+            cutoff if cutoff > 0.0 => Some(
+                (0..self.num_channels)
+                    .map(|_| LowpassFilter::new(cutoff, 44100.0, 2))
+                    .collect(),
+            ),
+            _ => None,
+        };
+    }
+
+    /// Setter for the feedback routing topology
+    pub fn set_topology(&mut self, topology: FeedbackTopology) {
+        self.topology = topology;
+    }
+
     /// Processes a vector of samples with delay and feedback Hadamard mixing.
     /// # Parameters
     /// * `xn`: The input array, must be the same length as num_channels and contain floats.
-    /// * `do_mixing`: whether to mix the output with a hadamard mixer or not
+    /// * `do_mixing`: whether to mix the output with a hadamard mixer or not, only used by the `Matrix` topology
     pub fn process_with_feedback(&mut self, xn: Array1<f32>, do_mixing: bool) -> Array<f32, Ix1> {
         let mut delayed_vec: Vec<f32> = Vec::new();
 
@@ -106,12 +206,35 @@ impl MultiDelayLine {
         // optional hadamard mixing step
         let scaled_delayed_vec: Vec<f32> = delayed_vec
             .iter()
-            .map(|sample| sample * self.feedback)
+            .zip(self.feedback_gains.iter())
+            .map(|(sample, gain)| sample * gain)
             .collect();
-        let mixed = match do_mixing {
-            true => self.mixer.mix(arr1(&scaled_delayed_vec)),
-            false => Array1::from_vec(scaled_delayed_vec),
+        let mut mixed = match (self.topology, do_mixing) {
+            (FeedbackTopology::Matrix, true) => self.mixer.mix(arr1(&scaled_delayed_vec)),
+            (FeedbackTopology::Series, _) => {
+                // rotate right so each channel receives the output of the channel before it
+                let mut chained = scaled_delayed_vec;
+                chained.rotate_right(1);
+                Array1::from_vec(chained)
+            }
+            _ => Array1::from_vec(scaled_delayed_vec),
         };
+        if let Some(filters) = self.crossfeed_filters.as_mut() {
+            for (index, filter) in filters.iter_mut().enumerate() {
+                // the part of the feedback each channel receives from itself, which isn't damped
+                let self_feedback = match (self.topology, do_mixing) {
+                    (FeedbackTopology::Matrix, true) => {
+                        self.mixer.self_gain(index)
+                            * delayed_vec[index]
+                            * self.feedback_gains[index]
+                    }
+                    (FeedbackTopology::Series, _) => 0.0,
+                    _ => continue,
+                };
+                mixed[index] = self_feedback + filter.process(mixed[index] - self_feedback);
+            }
+        }
+
         for (index, buffer) in self.delay_buffers.iter_mut().enumerate() {
             let feedback_signal: f32 = mixed[index];
             buffer.write(xn[index] + feedback_signal);
@@ -128,14 +251,119 @@ impl MultiDelayLine {
             yn[index] = yn_i
         }
 
+        if self.output_normalize {
+            let input_square = xn.iter().sum::<f32>().powi(2);
+            let output_square = yn.iter().sum::<f32>().powi(2);
+            self.input_mean_square =
+                input_square + RMS_TRACKING_COEFF * (self.input_mean_square - input_square);
+            self.output_mean_square =
+                output_square + RMS_TRACKING_COEFF * (self.output_mean_square - output_square);
+
+            if self.output_mean_square > 0.0 {
+                let gain = (self.input_mean_square / self.output_mean_square)
+                    .sqrt()
+                    .min(MAX_NORMALIZE_GAIN);
+                yn.iter_mut().for_each(|sample| *sample *= gain);
+            }
+        }
+
         yn
     }
 }
 
+/// Returns the (left, right) gains for a pan position using an equal power pan law.
+/// # Parameters
+/// * `pan`: The pan position, from -1 (hard left) through 0 (center) to 1 (hard right). Values outside are clamped.
+pub fn equal_power_pan(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * 0.5 * FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
+
+/// A struct which maps an N channel array of samples down to a stereo pair, using a gain per channel for each side.
+/// ## Attributes:
+/// * `left_gains`: The gain applied to each input channel before it is summed into the left output
+///
+/// * `right_gains`: The gain applied to each input channel before it is summed into the right output
+pub struct DownmixMatrix {
+    left_gains: Array1<f32>,
+    right_gains: Array1<f32>,
+}
+
+impl DownmixMatrix {
+    /// Constructor for a downmix matrix given the gains of every channel for the left and right outputs.
+    /// Both vectors must be the same length, which is the number of input channels.
+    pub fn new(left_gains: Vec<f32>, right_gains: Vec<f32>) -> Self {
+        assert_eq!(left_gains.len(), right_gains.len());
+        Self {
+            left_gains: Array1::from_vec(left_gains),
+            right_gains: Array1::from_vec(right_gains),
+        }
+    }
+
+    /// Preset which sends even channels to the left and odd channels to the right, unscaled.
+    ///
+    /// The left and right outputs added together are equal to the mono sum of all channels.
+    pub fn alternate(num_channels: u8) -> Self {
+        let left_gains = (0..num_channels)
+            .map(|channel| match channel % 2 {
+                0 => 1.0,
+                _ => 0.0,
+            })
+            .collect();
+        let right_gains = (0..num_channels)
+            .map(|channel| match channel % 2 {
+                0 => 0.0,
+                _ => 1.0,
+            })
+            .collect();
+        Self::new(left_gains, right_gains)
+    }
+
+    /// Preset which pans the channels evenly from hard left (first channel) to hard right (last channel)
+    /// using an equal power pan law, so the energy of uncorrelated channels is preserved in the stereo output.
+    pub fn energy_preserving_spread(num_channels: u8) -> Self {
+        let gains: Vec<(f32, f32)> = (0..num_channels)
+            .map(|channel| match num_channels {
+                1 => equal_power_pan(0.0),
+                n => equal_power_pan(2.0 * channel as f32 / (n - 1) as f32 - 1.0),
+            })
+            .collect();
+        Self::new(
+            gains.iter().map(|(left, _)| *left).collect(),
+            gains.iter().map(|(_, right)| *right).collect(),
+        )
+    }
+
+    /// The number of input channels the matrix expects
+    pub fn num_channels(&self) -> usize {
+        self.left_gains.len()
+    }
+
+    /// Folds a 1D array of samples (one per channel) down to a (left, right) pair of samples.
+    /// The input must be the same length as the number of channels in the matrix.
+    pub fn downmix(&self, input: ArrayView1<f32>) -> (f32, f32) {
+        assert_eq!(input.len(), self.num_channels());
+        let left = input
+            .iter()
+            .zip(self.left_gains.iter())
+            .map(|(sample, gain)| sample * gain)
+            .sum();
+        let right = input
+            .iter()
+            .zip(self.right_gains.iter())
+            .map(|(sample, gain)| sample * gain)
+            .sum();
+        (left, right)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::multi_channel::hadamard;
-    use ndarray::arr2;
+    use crate::distribute_exponential;
+    use crate::multi_channel::{hadamard, DownmixMatrix, FeedbackTopology, MultiDelayLine};
+    use ndarray::{arr1, arr2};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_hadamard_construction() {
@@ -168,4 +396,193 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_downmix_alternate() {
+        let matrix = DownmixMatrix::alternate(4);
+        let input = arr1(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(matrix.downmix(input.view()), (4.0, 6.0));
+    }
+
+    #[test]
+    fn test_downmix_spread() {
+        let matrix = DownmixMatrix::energy_preserving_spread(4);
+
+        // the first channel is hard left and the last is hard right
+        let (left, right) = matrix.downmix(arr1(&[1.0, 0.0, 0.0, 0.0]).view());
+        assert!((left - 1.0).abs() < 0.0001 && right.abs() < 0.0001);
+        let (left, right) = matrix.downmix(arr1(&[0.0, 0.0, 0.0, 1.0]).view());
+        assert!(left.abs() < 0.0001 && (right - 1.0).abs() < 0.0001);
+
+        // each channel keeps its energy when spread across both sides
+        for channel in 0..4 {
+            let mut input = arr1(&[0.0; 4]);
+            input[channel] = 1.0;
+            let (left, right) = matrix.downmix(input.view());
+            assert!((left.powi(2) + right.powi(2) - 1.0).abs() < 0.0001);
+        }
+    }
+
+    /// Renders a second of noise through a delay line with the given channel count,
+    /// returning the RMS of the summed output divided by the channel count
+    fn summed_output_rms(num_channels: u8, normalize: bool) -> f32 {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut delay = MultiDelayLine::new(
+            distribute_exponential(num_channels as i8, 0.01),
+            0.85,
+            1.0,
+            num_channels,
+            44100,
+        );
+        delay.set_output_normalize(normalize);
+
+        let mut squared_sum = 0.0;
+        for n in 0..88200 {
+            let xn: f32 = rng.gen_range(-1.0..1.0);
+            let output = delay.process_with_feedback(arr1(&vec![xn; num_channels as usize]), true);
+            // only the second half is measured, once the trackers have settled
+            if n >= 44100 {
+                squared_sum += (output.sum() / num_channels as f32).powi(2);
+            }
+        }
+        (squared_sum / 44100.0).sqrt()
+    }
+
+    #[test]
+    fn test_output_normalize() {
+        let unnormalized_ratio = summed_output_rms(8, false) / summed_output_rms(4, false);
+        let normalized_ratio = summed_output_rms(8, true) / summed_output_rms(4, true);
+
+        assert!((normalized_ratio - 1.0).abs() < 0.1);
+        assert!((normalized_ratio - 1.0).abs() < (unnormalized_ratio - 1.0).abs());
+    }
+
+    /// Renders an impulse into the first channel of a 4 channel delay line,
+    /// returning the first sample index each channel outputs a non zero value, if any
+    fn first_echoes(topology: FeedbackTopology) -> Vec<Option<usize>> {
+        let mut delay = MultiDelayLine::new(vec![0.01, 0.02, 0.03, 0.04], 0.5, 1.0, 4, 44100);
+        delay.set_topology(topology);
+
+        let mut first_echoes = vec![None; 4];
+        for n in 0..8820 {
+            let impulse = match n {
+                0 => 1.0,
+                _ => 0.0,
+            };
+            let output = delay.process_with_feedback(arr1(&[impulse, 0.0, 0.0, 0.0]), true);
+            for (channel, sample) in output.iter().enumerate() {
+                if first_echoes[channel].is_none() && sample.abs() > 1e-6 {
+                    first_echoes[channel] = Some(n);
+                }
+            }
+        }
+        first_echoes
+    }
+
+    #[test]
+    fn test_feedback_topologies() {
+        // each delay time in samples, plus one as the buffers are read before being written to
+        let hops = [442, 883, 1324, 1765];
+        let matrix = first_echoes(FeedbackTopology::Matrix);
+        let series = first_echoes(FeedbackTopology::Series);
+        let independent = first_echoes(FeedbackTopology::Independent);
+
+        // the matrix spreads the first echo into every channel at once
+        assert_eq!(
+            matrix,
+            vec![
+                Some(hops[0]),
+                Some(hops[0] + hops[1]),
+                Some(hops[0] + hops[2]),
+                Some(hops[0] + hops[3])
+            ]
+        );
+        // the series ring passes the echo along one channel at a time
+        assert_eq!(
+            series,
+            vec![
+                Some(hops[0]),
+                Some(hops[0] + hops[1]),
+                Some(hops[0] + hops[1] + hops[2]),
+                Some(hops[0] + hops[1] + hops[2] + hops[3])
+            ]
+        );
+        // independent channels never leak into each other
+        assert_eq!(independent, vec![Some(hops[0]), None, None, None]);
+    }
+
+    #[test]
+    fn test_rt60_decay_matching() {
+        let mut delay =
+            MultiDelayLine::new(vec![0.0113, 0.0171, 0.0239, 0.0307], 0.9, 1.0, 4, 44100);
+        delay.set_topology(FeedbackTopology::Independent);
+        delay.set_rt60(0.5);
+
+        // after the first echo, every echo should lie on the same 60dB per half second decay line
+        let expected_rate = -60.0 / 0.5;
+        let mut first_echoes = [None; 4];
+        for n in 0..22050 {
+            let impulse = match n {
+                0 => 1.0,
+                _ => 0.0,
+            };
+            let output = delay.process_with_feedback(arr1(&[impulse; 4]), true);
+            for (channel, sample) in output.iter().enumerate() {
+                if n == 0 || sample.abs() < 1e-9 {
+                    continue;
+                }
+                match first_echoes[channel] {
+                    None => first_echoes[channel] = Some(n),
+                    Some(first) => {
+                        let elapsed = (n - first) as f32 / 44100.0;
+                        let rate = 20.0 * sample.abs().log10() / elapsed;
+                        assert!(
+                            (rate - expected_rate).abs() < 1.0,
+                            "channel {channel} decayed at {rate}dB/s at sample {n}"
+                        );
+                    }
+                }
+            }
+        }
+        assert!(first_echoes.iter().all(|echo| echo.is_some()));
+    }
+
+    /// Renders two seconds of the impulse response of an 8 channel feedback network,
+    /// returning the mean absolute first difference of the last second, a measure of its high frequency content
+    fn tail_brightness(topology: FeedbackTopology, damping: f32) -> f32 {
+        let times = vec![
+            0.0113, 0.0171, 0.0239, 0.0307, 0.0373, 0.0419, 0.0467, 0.0521,
+        ];
+        let mut delay = MultiDelayLine::new(times, 0.85, 1.0, 8, 44100);
+        delay.set_topology(topology);
+        delay.set_crossfeed_damping(damping);
+
+        let output: Vec<f32> = (0..88200)
+            .map(|n| {
+                let impulse = match n {
+                    0 => 1.0,
+                    _ => 0.0,
+                };
+                delay.process_with_feedback(arr1(&[impulse; 8]), true).sum()
+            })
+            .collect();
+
+        let tail = &output[44100..];
+        let level: f32 = tail.iter().map(|x| x.abs()).sum();
+        let difference: f32 = tail.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+        difference / level
+    }
+
+    #[test]
+    fn test_crossfeed_damping() {
+        let undamped = tail_brightness(FeedbackTopology::Matrix, 0.0);
+        let damped = tail_brightness(FeedbackTopology::Matrix, 2000.0);
+        assert!(damped < undamped, "{damped} damped, {undamped} undamped");
+
+        // independent channels have no crossfeed to damp
+        assert_eq!(
+            tail_brightness(FeedbackTopology::Independent, 0.0),
+            tail_brightness(FeedbackTopology::Independent, 2000.0)
+        );
+    }
 }
@@ -0,0 +1,121 @@
+//! Module containing audio rate oscillators, used as test tones and sources for drone synthesis.
+//! AdditiveOsc sums a bank of harmonically related sine partials which stay phase coherent.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use std::f32::consts::PI;
+
+/// An additive oscillator summing sine partials at integer multiples of a fundamental frequency.
+///
+/// Every partial is derived from a single fundamental phase, so the partials start at zero phase together
+/// and stay coherent however long the oscillator runs.
+/// # Attributes
+/// * `fundamental`: The frequency of the first partial in Hz
+///
+/// * `partials`: The amplitude of each harmonic, where index 0 is the fundamental and index N is harmonic N + 1
+///
+/// * `sample_rate`: The sample rate the oscillator is played back at in Hz
+///
+/// * `phase`: The phase of the fundamental, in cycles from 0 to 1
+pub struct AdditiveOsc {
+    fundamental: f32,
+    partials: Vec<f32>,
+    sample_rate: f32,
+    phase: f32,
+}
+
+impl AdditiveOsc {
+    /// Constructor for the additive oscillator
+    /// # Parameters
+    /// * `fundamental`: The frequency of the first partial in Hz
+    /// * `partials`: The amplitude of each harmonic, starting with the fundamental.
+    ///     The output is the plain sum of the partials, so amplitudes should sum to at most 1 to avoid clipping.
+    /// * `sample_rate`: The sample rate the oscillator is played back at in Hz
+    pub fn new(fundamental: f32, partials: Vec<f32>, sample_rate: f32) -> Self {
+        Self {
+            fundamental,
+            partials,
+            sample_rate,
+            phase: 0.0,
+        }
+    }
+
+    /// Setter for the fundamental frequency in Hz, all partials follow it without a phase jump
+    pub fn set_fundamental(&mut self, fundamental: f32) {
+        self.fundamental = fundamental;
+    }
+
+    /// Setter for the amplitude of a single partial, where 0 is the fundamental.
+    /// Does nothing if the partial doesn't exist.
+    pub fn set_partial(&mut self, index: usize, amplitude: f32) {
+        if let Some(partial) = self.partials.get_mut(index) {
+            *partial = amplitude;
+        }
+    }
+
+    /// Resets the phase of every partial to 0
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+}
+
+impl Iterator for AdditiveOsc {
+    type Item = f32;
+
+    /// Returns the next sample of the summed partials. Never returns `None`.
+    /// Partials above the Nyquist frequency are skipped to avoid aliasing.
+    fn next(&mut self) -> Option<Self::Item> {
+        let nyquist = self.sample_rate * 0.5;
+        let sample = self
+            .partials
+            .iter()
+            .enumerate()
+            .map(|(index, amplitude)| (index as f32 + 1.0, amplitude))
+            .take_while(|(harmonic, _)| harmonic * self.fundamental < nyquist)
+            .map(|(harmonic, amplitude)| amplitude * (2.0 * PI * harmonic * self.phase).sin())
+            .sum();
+
+        self.phase = (self.phase + self.fundamental / self.sample_rate).fract();
+
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::oscillator::AdditiveOsc;
+    use rustfft::num_complex::Complex;
+    use rustfft::FftPlanner;
+
+    #[test]
+    fn test_additive_spectrum() {
+        const LENGTH: usize = 4096;
+        // a fundamental landing exactly on an FFT bin avoids spectral leakage
+        const BIN: usize = 40;
+        let fundamental = 44100.0 * BIN as f32 / LENGTH as f32;
+        let partials = vec![0.5, 0.25, 0.0, 0.125];
+
+        let osc = AdditiveOsc::new(fundamental, partials.clone(), 44100.0);
+        let mut spectrum: Vec<Complex<f32>> =
+            osc.take(LENGTH).map(|x| Complex::new(x, 0.0)).collect();
+
+        let mut planner = FftPlanner::new();
+        planner.plan_fft_forward(LENGTH).process(&mut spectrum);
+
+        for (index, amplitude) in partials.iter().enumerate() {
+            // a sine of amplitude A has a magnitude of A * N / 2 in its bin
+            let magnitude = spectrum[BIN * (index + 1)].norm() / (LENGTH as f32 * 0.5);
+            assert!(
+                (magnitude - amplitude).abs() < 0.01,
+                "harmonic {} had amplitude {magnitude}, expected {amplitude}",
+                index + 1
+            );
+        }
+    }
+
+    #[test]
+    fn test_starts_at_zero_phase() {
+        let mut osc = AdditiveOsc::new(100.0, vec![1.0, 0.5, 0.25], 44100.0);
+        assert_eq!(osc.next(), Some(0.0));
+    }
+}
@@ -0,0 +1,190 @@
+//! Module containing an oversampling wrapper for nonlinear processors such as saturators.
+//! Oversampled runs a processor at 2 or 4 times the sample rate, so the harmonics it generates above the
+//! original Nyquist frequency are filtered out rather than aliasing back down into the audible band.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use crate::filter::AntiAliasFilter;
+use crate::processor::Processor;
+
+/// The fraction of the original sample rate that the anti-aliasing filters cut off at, just below Nyquist
+const ANTI_ALIAS_CUTOFF: f32 = 0.45;
+
+/// An enum of the factors a signal can be oversampled by
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum OversampleFactor {
+    /// Processes at twice the sample rate
+    #[default]
+    X2,
+    /// Processes at four times the sample rate
+    X4,
+}
+
+impl OversampleFactor {
+    /// The number of samples processed for each input sample
+    pub fn ratio(&self) -> usize {
+        match self {
+            OversampleFactor::X2 => 2,
+            OversampleFactor::X4 => 4,
+        }
+    }
+}
+
+/// A struct which wraps a processor, running it at a multiple of the sample rate.
+///
+/// Each input is upsampled by inserting zeros between samples and lowpass filtering away the images,
+/// processed, then lowpass filtered again to remove anything above the original Nyquist frequency before
+/// every sample but one is dropped.
+/// ## Attributes:
+/// * `processor`: The wrapped processor, which runs at the oversampled rate
+///
+/// * `factor`: The factor the sample rate is multiplied by
+///
+/// * `sample_rate`: The original sample rate in Hz
+///
+/// * `upsample_filter`: The filter removing the images created by inserting zeros
+///
+/// * `downsample_filter`: The filter removing content which would alias when decimating
+pub struct Oversampled<P: Processor> {
+    processor: P,
+    factor: OversampleFactor,
+    sample_rate: f32,
+    upsample_filter: AntiAliasFilter,
+    downsample_filter: AntiAliasFilter,
+}
+
+impl<P: Processor> Oversampled<P> {
+    /// Constructor wrapping a processor
+    /// # Parameters
+    /// * `processor`: The processor to run at the oversampled rate
+    /// * `factor`: The factor to oversample by
+    /// * `sample_rate`: The sample rate of the input in Hz
+    pub fn new(processor: P, factor: OversampleFactor, sample_rate: f32) -> Self {
+        let oversampled_rate = sample_rate * factor.ratio() as f32;
+        Self {
+            processor,
+            factor,
+            sample_rate,
+            upsample_filter: AntiAliasFilter::new(
+                ANTI_ALIAS_CUTOFF * sample_rate,
+                oversampled_rate,
+            ),
+            downsample_filter: AntiAliasFilter::new(
+                ANTI_ALIAS_CUTOFF * sample_rate,
+                oversampled_rate,
+            ),
+        }
+    }
+
+    /// Clears the history of the filters without reallocating, so a signal starts from silence
+    pub fn reset(&mut self) {
+        self.upsample_filter.reset();
+        self.downsample_filter.reset();
+    }
+
+    /// Setter for the oversampling factor, which resets the filters
+    pub fn set_factor(&mut self, factor: OversampleFactor) {
+        let oversampled_rate = self.sample_rate * factor.ratio() as f32;
+        self.factor = factor;
+        self.upsample_filter =
+            AntiAliasFilter::new(ANTI_ALIAS_CUTOFF * self.sample_rate, oversampled_rate);
+        self.downsample_filter =
+            AntiAliasFilter::new(ANTI_ALIAS_CUTOFF * self.sample_rate, oversampled_rate);
+    }
+
+    /// Getter for the wrapped processor
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    /// Mutable getter for the wrapped processor, for changing its settings
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.processor
+    }
+
+    /// The delay the filters add to the signal, in samples at the original sample rate, rounded to the nearest sample
+    pub fn latency_samples(&self) -> usize {
+        let oversampled_delay =
+            self.upsample_filter.dc_group_delay() + self.downsample_filter.dc_group_delay();
+        (oversampled_delay / self.factor.ratio() as f32).round() as usize
+    }
+
+    /// Takes an f32 input, and returns it processed at the oversampled rate
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let ratio = self.factor.ratio();
+        let mut output = 0.0;
+        for step in 0..ratio {
+            // zero stuffing spreads the input's energy over the inserted samples, so it's scaled back up
+            let stuffed = match step {
+                0 => xn * ratio as f32,
+                _ => 0.0,
+            };
+            let upsampled = self.upsample_filter.process(stuffed);
+            let processed = self.processor.process(upsampled);
+            let filtered = self.downsample_filter.process(processed);
+            if step == 0 {
+                output = filtered;
+            }
+        }
+        output
+    }
+}
+
+impl<P: Processor> Processor for Oversampled<P> {
+    fn process(&mut self, xn: f32) -> f32 {
+        Oversampled::process(self, xn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::oversampling::{OversampleFactor, Oversampled};
+    use crate::processor::Processor;
+    use crate::saturation::Saturator;
+    use crate::test_util::{alias_ratio, BLOCK_LEN, SINE_BIN};
+    use std::f32::consts::PI;
+
+    /// Saturates the test sine through a processor, returning a block of the output once it has settled
+    fn process_sine(processor: &mut impl Processor) -> Vec<f32> {
+        let frequency = SINE_BIN as f32 * 44100.0 / BLOCK_LEN as f32;
+        (0..BLOCK_LEN + 2000)
+            .map(|n| processor.process((2.0 * PI * frequency * n as f32 / 44100.0).sin()))
+            .skip(2000)
+            .collect()
+    }
+
+    #[test]
+    fn test_oversampling_reduces_aliasing() {
+        let flat = alias_ratio(&process_sine(&mut Saturator::new(0.3, 1.0)));
+        let oversampled = alias_ratio(&process_sine(&mut Oversampled::new(
+            Saturator::new(0.3, 1.0),
+            OversampleFactor::X4,
+            44100.0,
+        )));
+
+        // at least 15dB less alias energy
+        assert!(
+            oversampled * 30.0 < flat,
+            "alias ratios {flat} and {oversampled}"
+        );
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut oversampled =
+            Oversampled::new(Saturator::new(1.0, 0.0), OversampleFactor::X2, 44100.0);
+        let latency = oversampled.latency_samples();
+        assert!(latency > 0);
+
+        // a slow ramp comes out delayed by the latency
+        let output: Vec<f32> = (0..2000)
+            .map(|n| oversampled.process(n as f32 * 0.001))
+            .collect();
+        let expected = (1500 - latency) as f32 * 0.001;
+        assert!(
+            (output[1500] - expected).abs() < 0.001,
+            "{} != {expected}",
+            output[1500]
+        );
+    }
+}
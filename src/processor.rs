@@ -0,0 +1,69 @@
+//! Module containing the Processor trait, shared by effects which take one sample and return one processed sample.
+//! Implementing it lets an effect be wrapped generically, such as by `Oversampled`.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use crate::filter::{
+    AllpassFilter, AntiAliasFilter, BiquadFilter, HighpassFilter, LowpassFilter, ShelfFilter,
+};
+use crate::saturation::{EmphasizedSaturator, MultibandSaturator, Saturator};
+
+/// A trait for mono effects which process a stream one sample at a time
+pub trait Processor {
+    /// Takes an f32 input and returns the processed output
+    fn process(&mut self, xn: f32) -> f32;
+}
+
+impl Processor for Saturator {
+    fn process(&mut self, xn: f32) -> f32 {
+        Saturator::process(self, xn)
+    }
+}
+
+impl Processor for EmphasizedSaturator {
+    fn process(&mut self, xn: f32) -> f32 {
+        EmphasizedSaturator::process(self, xn)
+    }
+}
+
+impl Processor for MultibandSaturator {
+    fn process(&mut self, xn: f32) -> f32 {
+        MultibandSaturator::process(self, xn)
+    }
+}
+
+impl Processor for LowpassFilter {
+    fn process(&mut self, xn: f32) -> f32 {
+        LowpassFilter::process(self, xn)
+    }
+}
+
+impl Processor for HighpassFilter {
+    fn process(&mut self, xn: f32) -> f32 {
+        HighpassFilter::process(self, xn)
+    }
+}
+
+impl Processor for ShelfFilter {
+    fn process(&mut self, xn: f32) -> f32 {
+        ShelfFilter::process(self, xn)
+    }
+}
+
+impl Processor for BiquadFilter {
+    fn process(&mut self, xn: f32) -> f32 {
+        BiquadFilter::process(self, xn)
+    }
+}
+
+impl Processor for AntiAliasFilter {
+    fn process(&mut self, xn: f32) -> f32 {
+        AntiAliasFilter::process(self, xn)
+    }
+}
+
+impl Processor for AllpassFilter {
+    fn process(&mut self, xn: f32) -> f32 {
+        AllpassFilter::process(self, xn)
+    }
+}
@@ -0,0 +1,86 @@
+//! Module containing the Quality setting, a single control trading CPU use for audio quality across the crate.
+//! Each level selects the interpolation method used when resampling and the oversampling of nonlinear processors.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use crate::oversampling::OversampleFactor;
+use crate::resample::ResampleMethod;
+use nih_plug::prelude::Enum;
+
+/// An enum of quality levels, from the cheapest to process to the cleanest sounding
+#[derive(Default, Clone, Copy, Enum, PartialEq, Debug)]
+pub enum Quality {
+    /// Linear interpolation and no oversampling
+    Low,
+    /// Hermite interpolation and 2x oversampling
+    #[default]
+    Medium,
+    /// Windowed sinc (Lanczos) interpolation and 4x oversampling
+    High,
+}
+
+impl Quality {
+    /// The interpolation method used to read audio at fractional positions, such as when pitching grains
+    pub fn resample_method(&self) -> ResampleMethod {
+        match self {
+            Quality::Low => ResampleMethod::Linear,
+            Quality::Medium => ResampleMethod::Hermite,
+            Quality::High => ResampleMethod::Lanczos(3),
+        }
+    }
+
+    /// The factor nonlinear processors such as saturators are oversampled by, `None` to run them at the sample rate
+    pub fn oversample_factor(&self) -> Option<OversampleFactor> {
+        match self {
+            Quality::Low => None,
+            Quality::Medium => Some(OversampleFactor::X2),
+            Quality::High => Some(OversampleFactor::X4),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delay_line::StereoDelay;
+    use crate::quality::Quality;
+    use crate::resample::interpolate_at;
+    use crate::test_util::{alias_ratio, BLOCK_LEN, SINE_BIN};
+    use std::f32::consts::PI;
+
+    /// Reads a sine at half its speed, then saturates it through the delay's dry path, at a quality level
+    fn process_at(quality: Quality) -> Vec<f32> {
+        // the source is at twice the frequency, so reading it at half steps gives the test sine
+        let source: Vec<i16> = (0..BLOCK_LEN + 2000)
+            .map(|n| {
+                (8000.0 * (4.0 * PI * SINE_BIN as f32 * n as f32 / BLOCK_LEN as f32).sin()) as i16
+            })
+            .collect();
+
+        let mut delay = StereoDelay::new(44100.0, 0.1, 0.1, 0.0, 0.0);
+        delay.set_saturation_factor(8.0);
+        delay.set_saturation_mix(1.0);
+        delay.set_quality(quality);
+
+        (0..BLOCK_LEN + 2000)
+            .map(|n| {
+                let xn = interpolate_at(&source, n as f32 * 0.5, quality.resample_method());
+                delay.process(xn, xn, false, true).0
+            })
+            .skip(2000)
+            .collect()
+    }
+
+    #[test]
+    fn test_quality_levels() {
+        let ratios: Vec<f32> = [Quality::Low, Quality::Medium, Quality::High]
+            .iter()
+            .map(|quality| alias_ratio(&process_at(*quality)))
+            .collect();
+
+        // each level is cleaner than the one below it
+        assert!(
+            ratios[0] > ratios[1] && ratios[1] > ratios[2],
+            "alias ratios {ratios:?}"
+        );
+    }
+}
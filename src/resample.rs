@@ -1,17 +1,19 @@
 //! A Module containing structs and functions for resampling audio
 //! Primarily used for pitch shifting.
 use crate::interpolators::{hermite_interpolate, lanczos_window, lerp};
+use crate::samples::AudioSample;
 
 /// Struct performing linear interpolation given an input slice and pitch factor to resample by.
-pub struct LinearResampler<'a> {
-    buffer: &'a [i16],
+/// Reads i16 samples by default, or any other `AudioSample` type such as f32.
+pub struct LinearResampler<'a, S: AudioSample = i16> {
+    buffer: &'a [S],
     position: f64,
     pitch_factor: f64,
 }
 
-impl<'a> LinearResampler<'a> {
+impl<'a, S: AudioSample> LinearResampler<'a, S> {
     /// Constructor for linear resampler which takes an input slice and pitch factor to resample by
-    pub fn new(collection: &'a [i16], pitch_factor: f64) -> Self {
+    pub fn new(collection: &'a [S], pitch_factor: f64) -> Self {
         Self {
             buffer: collection,
             position: 0.0,
@@ -25,7 +27,7 @@ impl<'a> LinearResampler<'a> {
     }
 
     /// Setter for buffer by a lifetime annotated slice
-    pub fn set_buffer(&mut self, buffer: &'a [i16]) {
+    pub fn set_buffer(&mut self, buffer: &'a [S]) {
         self.buffer = buffer;
     }
 
@@ -34,6 +36,17 @@ impl<'a> LinearResampler<'a> {
         self.position
     }
 
+    /// Setter for the current position of the resampler, wrapped to within the buffer
+    pub fn set_position(&mut self, position: f64) {
+        let loop_len = (self.buffer.len().max(2) - 1) as f64;
+        self.position = position.rem_euclid(loop_len);
+    }
+
+    /// The number of samples the position moves through before looping
+    pub fn loop_len(&self) -> usize {
+        self.buffer.len().saturating_sub(1)
+    }
+
     /// increments the resampler and loops index if over the length of the buffer.
     ///
     /// Returns true if the buffer was looped
@@ -50,9 +63,45 @@ impl<'a> LinearResampler<'a> {
     pub fn get_pitch_freq(&self) -> f64 {
         self.pitch_factor
     }
+
+    /// The number of whole input samples the position has moved past, which are no longer needed
+    pub fn consumed_input(&self) -> usize {
+        self.position.floor() as usize
+    }
+
+    /// Resamples as much of a block of streamed input as possible, for real time pitch shifting of a stream
+    /// rather than a whole buffer.
+    ///
+    /// The position is relative to the start of `input`, which should begin with the first sample not yet consumed.
+    /// Output is produced until either `output` is full or the input runs out, then the consumed input samples
+    /// are removed from the position, so the caller can drop them from the front of its buffer before
+    /// appending more input for the next call.
+    ///
+    /// Returns a tuple of (input samples consumed, output samples produced)
+    pub fn resample_available(&mut self, input: &[S], output: &mut [f32]) -> (usize, usize) {
+        let mut produced = 0;
+        for sample in output.iter_mut() {
+            let index = self.position.floor() as usize;
+            // interpolating needs the sample after the index too
+            if index + 1 >= input.len() {
+                break;
+            }
+            *sample = lerp(
+                input[index].to_f32(),
+                input[index + 1].to_f32(),
+                self.position.fract() as f32,
+            );
+            self.position += self.pitch_factor;
+            produced += 1;
+        }
+
+        let consumed = self.consumed_input().min(input.len());
+        self.position -= consumed as f64;
+        (consumed, produced)
+    }
 }
 
-impl<'a> Iterator for LinearResampler<'a> {
+impl<'a, S: AudioSample> Iterator for LinearResampler<'a, S> {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -63,8 +112,8 @@ impl<'a> Iterator for LinearResampler<'a> {
         // performs linear interpolation between that index and the next, by the fractional part
         let index = self.position.floor() as usize;
         let sample = lerp(
-            self.buffer[index] as f32,
-            self.buffer[index + 1] as f32,
+            self.buffer[index].to_f32(),
+            self.buffer[index + 1].to_f32(),
             self.position.fract() as f32,
         );
         // position increased by pitch factor in order to stretch the sample by the amount of pitch factor.
@@ -102,6 +151,11 @@ impl<'a> LanczosResampler<'a> {
     pub fn set_buffer(&mut self, buffer: &'a [i16]) {
         self.buffer = buffer;
     }
+
+    /// The number of whole input samples the position has moved past
+    pub fn consumed_input(&self) -> usize {
+        self.position.floor() as usize
+    }
 }
 
 impl<'a> Iterator for LanczosResampler<'a> {
@@ -171,6 +225,11 @@ impl<'a> HermiteResampler<'a> {
     pub fn set_buffer(&mut self, buffer: &'a [i16]) {
         self.buffer = buffer;
     }
+
+    /// The number of whole input samples the position has moved past
+    pub fn consumed_input(&self) -> usize {
+        self.position.floor() as usize
+    }
 }
 
 impl<'a> Iterator for HermiteResampler<'a> {
@@ -228,6 +287,75 @@ impl<'a> Iterator for HermiteResampler<'a> {
     }
 }
 
+/// An enum of the available interpolation methods, used to choose a resampler by value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResampleMethod {
+    /// Linear interpolation, using `LinearResampler`
+    Linear,
+    /// Lanczos interpolation with the given window size, using `LanczosResampler`
+    Lanczos(u16),
+    /// Cubic Hermite spline interpolation, using `HermiteResampler`
+    Hermite,
+}
+
+/// Reads a buffer at a single fractional index, interpolating between the samples around it with a method.
+///
+/// Samples beyond either end of the buffer are treated as repeats of the first or last sample.
+/// # Parameters
+/// * `buffer`: The buffer to read, which must not be empty
+/// * `index`: The fractional index to read at, from 0 to the last index of the buffer
+/// * `method`: The interpolation method used
+pub fn interpolate_at<S: AudioSample>(buffer: &[S], index: f32, method: ResampleMethod) -> f32 {
+    let last = buffer.len() as isize - 1;
+    let base = index.floor() as isize;
+    let t = index - base as f32;
+    let sample = |offset: isize| buffer[(base + offset).clamp(0, last) as usize].to_f32();
+
+    match method {
+        ResampleMethod::Linear => lerp(sample(0), sample(1), t),
+        ResampleMethod::Hermite => {
+            hermite_interpolate(sample(-1), sample(0), sample(1), sample(2), 1.0, t)
+        }
+        ResampleMethod::Lanczos(window_size) => {
+            let window_size = window_size as isize;
+            let (sum, total_weight) =
+                (1 - window_size..=window_size).fold((0.0, 0.0), |(sum, total_weight), offset| {
+                    let weight = lanczos_window(t - offset as f32, window_size as f32);
+                    (sum + sample(offset) * weight, total_weight + weight)
+                });
+            sum / total_weight
+        }
+    }
+}
+
+/// Resamples an input buffer so that the output is exactly `target_len` samples long.
+///
+/// The resampling ratio is derived from the length goal rather than a pitch,
+/// which is useful for fitting a loop to a duration such as a bar length.
+/// The first and last output samples land exactly on the first and last input samples,
+/// so the end of the input is never interpolated back toward its start.
+/// # Parameters
+/// * `input`: The buffer to resample
+/// * `target_len`: The number of samples in the returned buffer
+/// * `method`: The interpolation method used for resampling
+pub fn resample_to_length(input: &[i16], target_len: usize, method: ResampleMethod) -> Vec<i16> {
+    // the resamplers need at least two samples to interpolate between
+    match input.len() {
+        0 => return vec![0; target_len],
+        1 => return vec![input[0]; target_len],
+        _ => (),
+    }
+    if target_len == 0 {
+        return Vec::new();
+    }
+
+    // align the endpoints, so the step covers the gaps between samples rather than the samples themselves
+    let ratio = (input.len() - 1) as f64 / (target_len - 1).max(1) as f64;
+    (0..target_len)
+        .map(|n| interpolate_at(input, (n as f64 * ratio) as f32, method) as i16)
+        .collect()
+}
+
 /// Returns the ration of the note `step` semitones above a root.
 /// Example:
 ///
@@ -239,7 +367,9 @@ pub fn semitone_to_hz_ratio(step: i8) -> f32 {
 
 #[cfg(test)]
 mod tests {
-    use crate::resample::{semitone_to_hz_ratio, LanczosResampler, LinearResampler};
+    use crate::resample::{
+        resample_to_length, semitone_to_hz_ratio, LanczosResampler, LinearResampler, ResampleMethod,
+    };
     use crate::samples::PhonicMode;
     use crate::{load_wav, write_wav};
     use plotters::prelude::*;
@@ -374,6 +504,42 @@ mod tests {
             .expect("could not draw plot");
     }
 
+    #[test_case(ResampleMethod::Linear, 1000)]
+    #[test_case(ResampleMethod::Linear, 4410)]
+    #[test_case(ResampleMethod::Lanczos(3), 3000)]
+    #[test_case(ResampleMethod::Hermite, 5000)]
+    fn test_resample_to_length(method: ResampleMethod, target_len: usize) {
+        let input: Vec<i16> = (0..2205)
+            .map(|n| ((n as f32 * 0.05).sin() * 10000.0) as i16)
+            .collect();
+
+        let output = resample_to_length(&input, target_len, method);
+        assert_eq!(output.len(), target_len);
+    }
+
+    #[test]
+    fn test_resample_to_length_keeps_ramp() {
+        let input: Vec<i16> = (0..1000).map(|n| n * 10).collect();
+
+        for method in [
+            ResampleMethod::Linear,
+            ResampleMethod::Lanczos(3),
+            ResampleMethod::Hermite,
+        ] {
+            for target_len in [250, 1000, 2500] {
+                let output = resample_to_length(&input, target_len, method);
+                assert_eq!(output.len(), target_len);
+                // a ramp must stay a ramp, without the end wrapping back toward the start
+                assert!(
+                    output.windows(2).all(|pair| pair[1] >= pair[0]),
+                    "{method:?} to {target_len} samples is not monotonic"
+                );
+                assert_eq!(output[0], 0);
+                assert!((*output.last().unwrap() - 9990).abs() <= 1);
+            }
+        }
+    }
+
     #[test]
     fn create_chromatic_steps() {
         let input: Vec<i16> = load_wav("tests/sine.wav").unwrap();
@@ -406,4 +572,42 @@ mod tests {
 
         write_wav("tests/debug/chromatic_sweep.wav", out, PhonicMode::Mono);
     }
+
+    #[test]
+    fn test_streamed_resampling() {
+        let input: Vec<i16> = (0..5000)
+            .map(|n| ((n as f32 * 0.05).sin() * 10000.0) as i16)
+            .collect();
+        let ratio = semitone_to_hz_ratio(4) as f64;
+
+        // the whole buffer at once, stopping before the resampler would loop
+        let mut one_shot = LinearResampler::new(&input, ratio);
+        let mut expected = Vec::new();
+        while one_shot.get_position() < (input.len() - 1) as f64 {
+            expected.push(one_shot.next().unwrap());
+        }
+        assert_eq!(one_shot.consumed_input(), input.len() - 1);
+
+        // the same input arriving in uneven chunks, with a small output block
+        let mut streamed = LinearResampler::new(&[], ratio);
+        let mut feed: Vec<i16> = Vec::new();
+        let mut output = Vec::new();
+        let mut block = [0.0; 64];
+        for chunk in input.chunks(137) {
+            feed.extend_from_slice(chunk);
+            loop {
+                let (consumed, produced) = streamed.resample_available(&feed, &mut block);
+                feed.drain(..consumed);
+                output.extend_from_slice(&block[..produced]);
+                if produced < block.len() {
+                    break;
+                }
+            }
+        }
+
+        assert_eq!(output.len(), expected.len());
+        for (streamed, whole) in output.iter().zip(expected.iter()) {
+            assert!((streamed - whole).abs() < 0.01, "{streamed} != {whole}");
+        }
+    }
 }
@@ -2,20 +2,34 @@
 //! Currently not working due to un-found bug.
 //!
 //! Uses FDN architecture and is heavily based on the article "Let's write a reverb" by Geraint Luff of Signal Smith audio
+//!
+//! ReverseReverb time-reverses windows of the reverb tail, so the tail swells up into the dry signal.
+//!
+//! Decorrelator turns the mono reverb output into stereo, by passing it through different allpass chains per side.
 
+use crate::delay_buffer::{DelayBuffer, MAX_DRY_COMPENSATION_SAMPLES};
 use crate::diffusion::Diffuser;
+use crate::filter::AllpassFilter;
 use crate::multi_channel::MultiDelayLine;
-use ndarray::arr1;
+use ndarray::Array1;
 
 /// Struct combining multi delay, and diffusers into an FDN reverb.
 ///
 /// Has a single multi delay line used with feedback to increase echo density
 ///
 /// Has a vector of Diffusers, usually between 3 - 7, Used to blend / smear audio to create the reverb effect.
+///
+/// Has a delay buffer for the dry signal, which can delay it to line up with latency in the wet path.
+///
+/// Stores the channel count of each diffuser and the delay, so signals can be widened or narrowed between stages.
 /// CURRENTLY WIP.
 pub struct Reverb {
     delay: MultiDelayLine,
     diffusers: Vec<Diffuser>,
+    dry_delay: DelayBuffer,
+    dry_compensation_samples: usize,
+    diffuser_channels: Vec<u8>,
+    delay_channels: u8,
 }
 
 impl Default for Reverb {
@@ -37,10 +51,46 @@ impl Default for Reverb {
                 Diffuser::new(8, 0.080),
                 Diffuser::new(8, 0.160),
             ],
+            dry_delay: DelayBuffer::new(MAX_DRY_COMPENSATION_SAMPLES + 1),
+            dry_compensation_samples: 0,
+            diffuser_channels: vec![8; 4],
+            delay_channels: 8,
         }
     }
 }
 
+/// Widens or narrows a multichannel signal to a different channel count, preserving its total energy.
+///
+/// When widening, channels are repeated in order, and when narrowing, every channel is summed into
+/// the output channel at its index modulo the new width.
+/// Energy is preserved assuming the summed channels are uncorrelated, as they are after diffusion.
+fn reconcile_channels(xn: Array1<f32>, width: usize) -> Array1<f32> {
+    let input_width = xn.len();
+    if input_width == width {
+        return xn;
+    }
+
+    let mut output = Array1::zeros(width);
+    let ratio = match input_width < width {
+        true => {
+            for (index, sample) in output.iter_mut().enumerate() {
+                *sample = xn[index % input_width];
+            }
+            width / input_width
+        }
+        false => {
+            for (index, sample) in xn.iter().enumerate() {
+                output[index % width] += sample;
+            }
+            input_width / width
+        }
+    };
+    // repeating a channel n times, or summing n uncorrelated channels, scales the energy by n
+    let scale = 1.0 / (ratio as f32).sqrt();
+    output.iter_mut().for_each(|sample| *sample *= scale);
+    output
+}
+
 impl Reverb {
     /// Constructor for the reverb struct.
     ///
@@ -65,35 +115,229 @@ impl Reverb {
             diffusers: (0..diffuser_count)
                 .map(|index| Diffuser::new(channels, diffuser_start * (index + 1) as f32))
                 .collect(),
+            dry_delay: DelayBuffer::new(MAX_DRY_COMPENSATION_SAMPLES + 1),
+            dry_compensation_samples: 0,
+            diffuser_channels: vec![channels; diffuser_count],
+            delay_channels: channels,
         }
     }
 
+    /// Constructor for a reverb whose diffusers each have their own channel count,
+    /// such as fewer channels early in the series and more later, to tune how quickly the diffusion builds up.
+    ///
+    /// The signal is widened or narrowed between stages with differing channel counts, preserving its energy.
+    /// # Parameters
+    /// * `channel_counts`: The channel count of each diffuser in the series, each must be a power of 2
+    /// * `start_time`: The maximum time of the first diffuser in seconds, each later diffuser's time increases by this
+    /// * `delay_channels`: The channel count of the feedback delay, must be a power of 2 and at most 8
+    pub fn new_graduated(channel_counts: Vec<u8>, start_time: f32, delay_channels: u8) -> Self {
+        Self {
+            delay: MultiDelayLine::new(
+                vec![
+                    0.13658298, 0.17436438, 0.10935726, 0.13564646, 0.10045976, 0.19373563,
+                    0.14323634, 0.11213523,
+                ],
+                0.85,
+                1.0,
+                delay_channels,
+                44100,
+            ),
+            diffusers: channel_counts
+                .iter()
+                .enumerate()
+                .map(|(index, channels)| Diffuser::new(*channels, start_time * (index + 1) as f32))
+                .collect(),
+            dry_delay: DelayBuffer::new(MAX_DRY_COMPENSATION_SAMPLES + 1),
+            dry_compensation_samples: 0,
+            diffuser_channels: channel_counts,
+            delay_channels,
+        }
+    }
+
+    /// Setter for the number of samples the dry signal is delayed by before mixing,
+    /// which should match the latency of the wet path. Limited to `MAX_DRY_COMPENSATION_SAMPLES`
+    pub fn set_dry_compensation_samples(&mut self, samples: usize) {
+        self.dry_compensation_samples = samples.min(MAX_DRY_COMPENSATION_SAMPLES);
+    }
+
     /// Process a single float by duplicating it to all channels and performing the reverb algorithm
     /// First the sample is passed through the diffuser series.
     ///
     /// Then it is delayed with feedback and mixed down with the dry signal by the mix parameter.
     pub fn process(&mut self, xn: f32, mix: f32) -> f32 {
         let read_sample = xn;
-        let mut read_sample_array = arr1(&[read_sample; 8]);
+        let input_width = *self
+            .diffuser_channels
+            .first()
+            .unwrap_or(&self.delay_channels) as usize;
+        let mut read_sample_array = Array1::from_elem(input_width, read_sample);
 
-        for diffuser in &mut self.diffusers {
+        for (diffuser, channels) in self.diffusers.iter_mut().zip(self.diffuser_channels.iter()) {
             let write_sample_array;
-            let diffused = diffuser.diffuse(read_sample_array.clone());
+            let input = reconcile_channels(read_sample_array, *channels as usize);
+            let diffused = diffuser.diffuse(input);
             write_sample_array = diffused;
             read_sample_array = write_sample_array.clone();
         }
 
+        let read_sample_array = reconcile_channels(read_sample_array, self.delay_channels as usize);
         let delayed = self.delay.process_with_feedback(read_sample_array, true);
 
-        ((1.0 - mix) * xn) + (mix * delayed.sum())
+        // the dry signal is delayed to line up with any latency in the wet path
+        self.dry_delay.write(xn);
+        let dry = self.dry_delay.read(self.dry_compensation_samples);
+
+        ((1.0 - mix) * dry) + (mix * delayed.sum())
+    }
+}
+
+/// A reverse reverb, which plays the reverb tail of its input backwards so it swells up to the dry signal.
+///
+/// The wet output of a `Reverb` is captured a window at a time, then each window is played back reversed
+/// while the next is captured. The dry signal is delayed by two windows, one to capture and one to play back,
+/// so the reversed tail of a transient ends just before the transient itself. This delay is the latency
+/// of the effect, given by `latency_samples`.
+/// ## Attributes:
+/// * `reverb`: The reverb producing the tail which is reversed
+///
+/// * `capture`: The window of wet output currently being recorded
+///
+/// * `playback`: The previous window of wet output, reversed, currently being played
+///
+/// * `position`: The index into both windows of the current sample
+///
+/// * `dry_delay`: Delays the dry signal to line up with the end of its reversed tail
+///
+/// * `mix`: The balance between the dry signal (0) and the reversed tail (1)
+pub struct ReverseReverb {
+    reverb: Reverb,
+    capture: Vec<f32>,
+    playback: Vec<f32>,
+    position: usize,
+    dry_delay: DelayBuffer,
+    mix: f32,
+}
+
+impl ReverseReverb {
+    /// Constructor for the reverse reverb, using the default reverb and an even mix of dry and reversed tail.
+    /// # Parameters
+    /// * `window_ms`: The length of reverb tail which is reversed, in milliseconds
+    pub fn new(window_ms: f32) -> Self {
+        let mut instance = Self {
+            reverb: Reverb::default(),
+            capture: Vec::new(),
+            playback: Vec::new(),
+            position: 0,
+            dry_delay: DelayBuffer::new(1),
+            mix: 0.5,
+        };
+        instance.set_window(window_ms);
+        instance
+    }
+
+    /// Setter for the length of reverb tail which is reversed, in milliseconds.
+    /// Reallocates and clears the window buffers, so shouldn't be called on the audio thread.
+    pub fn set_window(&mut self, window_ms: f32) {
+        let window_samples = ((window_ms * 0.001 * 44100.0) as usize).max(1);
+        self.capture = vec![0.0; window_samples];
+        self.playback = vec![0.0; window_samples];
+        self.position = 0;
+        self.dry_delay = DelayBuffer::new(2 * window_samples + 1);
+    }
+
+    /// Setter for the balance between the dry signal (0) and the reversed tail (1)
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// The number of samples the dry signal is delayed by, two windows
+    pub fn latency_samples(&self) -> usize {
+        2 * self.capture.len()
+    }
+
+    /// Processes a single sample through the reverb, returning the delayed dry signal mixed with the reversed tail
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let wet = self.playback[self.position];
+        self.capture[self.position] = self.reverb.process(xn, 1.0);
+
+        self.position += 1;
+        if self.position == self.capture.len() {
+            self.position = 0;
+            self.capture.reverse();
+            std::mem::swap(&mut self.capture, &mut self.playback);
+        }
+
+        self.dry_delay.write(xn);
+        let dry = self.dry_delay.read(self.latency_samples());
+
+        ((1.0 - self.mix) * dry) + (self.mix * wet)
+    }
+}
+
+/// The gain of the feedback and feedforward paths of each decorrelation allpass
+const DECORRELATION_GAIN: f32 = 0.6;
+
+/// The delays in samples of the left channel's allpass chain
+const LEFT_ALLPASS_DELAYS: [usize; 4] = [131, 211, 293, 389];
+
+/// The delays in samples of the right channel's allpass chain, chosen to share no factors with the left
+const RIGHT_ALLPASS_DELAYS: [usize; 4] = [149, 199, 313, 367];
+
+/// A struct which creates a stereo signal from a mono one, such as the output of `Reverb`,
+/// by passing it through a different chain of allpass filters for each side.
+///
+/// Both sides keep the spectrum of the input, but their phases differ, so they sound wide rather than centered.
+/// ## Attributes:
+/// * `left`: The allpass chain producing the left channel
+/// * `right`: The allpass chain producing the right channel
+pub struct Decorrelator {
+    left: Vec<AllpassFilter>,
+    right: Vec<AllpassFilter>,
+}
+
+impl Default for Decorrelator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decorrelator {
+    /// Constructor for the decorrelator, with 4 allpasses per side
+    pub fn new() -> Self {
+        Self {
+            left: LEFT_ALLPASS_DELAYS
+                .iter()
+                .map(|delay| AllpassFilter::new(*delay, DECORRELATION_GAIN))
+                .collect(),
+            right: RIGHT_ALLPASS_DELAYS
+                .iter()
+                .map(|delay| AllpassFilter::new(*delay, DECORRELATION_GAIN))
+                .collect(),
+        }
+    }
+
+    /// Takes a mono f32 input and returns a decorrelated (left, right) pair
+    pub fn process(&mut self, mono: f32) -> (f32, f32) {
+        let left = self
+            .left
+            .iter_mut()
+            .fold(mono, |sample, allpass| allpass.process(sample));
+        let right = self
+            .right
+            .iter_mut()
+            .fold(mono, |sample, allpass| allpass.process(sample));
+        (left, right)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::reverb::Reverb;
+    use crate::reverb::{reconcile_channels, Decorrelator, Reverb, ReverseReverb};
     use crate::samples::PhonicMode;
     use crate::{load_wav, write_wav};
+    use ndarray::arr1;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     #[ignore]
@@ -112,4 +356,101 @@ mod tests {
             PhonicMode::Stereo,
         );
     }
+
+    #[test]
+    fn test_reverse_swell() {
+        let mut reverse = ReverseReverb::new(500.0);
+        let latency = reverse.latency_samples();
+        assert_eq!(latency, 44100);
+
+        let output: Vec<f32> = (0..latency + 100)
+            .map(|n| {
+                let impulse = match n {
+                    0 => 1.0,
+                    _ => 0.0,
+                };
+                reverse.process(impulse)
+            })
+            .collect();
+
+        // nothing is heard while the first window is captured
+        assert!(output[..latency / 2].iter().all(|sample| *sample == 0.0));
+
+        // the reversed tail swells up over the second window, from the end of the tail towards its densest part
+        let energy = |samples: &[f32]| samples.iter().map(|x| x * x).sum::<f32>();
+        let quarter = latency / 8;
+        let first_quarter = energy(&output[latency / 2..latency / 2 + quarter]);
+        let second_quarter = energy(&output[latency / 2 + quarter..latency / 2 + 2 * quarter]);
+        assert!(
+            second_quarter > first_quarter,
+            "{first_quarter} then {second_quarter}"
+        );
+
+        // before the dry hit, which arrives after the latency
+        let loudest = (0..output.len())
+            .max_by(|&a, &b| output[a].abs().total_cmp(&output[b].abs()))
+            .unwrap();
+        assert_eq!(loudest, latency);
+    }
+
+    #[test]
+    fn test_reconcile_channels() {
+        let energy = |xn: &ndarray::Array1<f32>| xn.iter().map(|x| x * x).sum::<f32>();
+        let input = arr1(&[1.0, -0.5]);
+
+        let widened = reconcile_channels(input.clone(), 8);
+        assert_eq!(widened.len(), 8);
+        assert!((energy(&widened) - energy(&input)).abs() < 1e-5);
+
+        let narrowed = reconcile_channels(arr1(&[0.5; 8]), 2);
+        assert_eq!(narrowed.len(), 2);
+        assert!((energy(&narrowed) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_graduated_density() {
+        let mut reverb = Reverb::new_graduated(vec![2, 4, 8], 0.02, 8);
+
+        let output: Vec<f32> = (0..44100)
+            .map(|n| {
+                let impulse = match n {
+                    0 => 1.0,
+                    _ => 0.0,
+                };
+                reverb.process(impulse, 1.0)
+            })
+            .collect();
+
+        // the fraction of samples carrying an echo rises as the diffusers and feedback multiply the reflections
+        let density = |samples: &[f32]| {
+            samples.iter().filter(|x| x.abs() > 1e-6).count() as f32 / samples.len() as f32
+        };
+        let early = density(&output[..4410]);
+        let middle = density(&output[4410..13230]);
+        let late = density(&output[22050..]);
+        assert!(
+            early < middle && middle <= late,
+            "densities {early}, {middle}, {late}"
+        );
+    }
+
+    #[test]
+    fn test_decorrelation() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut decorrelator = Decorrelator::new();
+
+        let input: Vec<f32> = (0..44100).map(|_| rng.gen_range(-0.5..0.5)).collect();
+        let (left, right): (Vec<f32>, Vec<f32>) =
+            input.iter().map(|xn| decorrelator.process(*xn)).unzip();
+
+        let energy = |samples: &[f32]| samples.iter().map(|x| x * x).sum::<f32>();
+        let cross: f32 = left.iter().zip(right.iter()).map(|(l, r)| l * r).sum();
+        let correlation = cross / (energy(&left) * energy(&right)).sqrt();
+
+        assert!(correlation.abs() < 0.2, "correlation was {correlation}");
+        for side in [&left, &right] {
+            let ratio = energy(side) / energy(&input);
+            assert!((ratio - 1.0).abs() < 0.05, "energy ratio was {ratio}");
+        }
+    }
 }
@@ -1,11 +1,15 @@
 #![allow(dead_code)]
 #![warn(missing_docs)]
 //! A module that contains structs for working with stereo and mono samples as floats or integers.
+//! MultiSamples holds interleaved samples with any number of channels, for the multichannel delay networks.
 //! Contains methods to interleave and un-interleave from a file.
 //! Generic functions used to accept floats or integers.
+//! Contains metering helpers which measure the level of a set of samples.
+
+use crate::interpolators::lanczos_window;
 
 /// An enum used to store state of either stereophonic or monophonic in audio structs
-#[derive(Default)]
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
 pub enum PhonicMode {
     #[default]
     /// Stereophonic (2 channels) samples
@@ -25,21 +29,54 @@ pub trait Samples<T> {
     /// Create a stereo samples struct from mono input sample vector
     fn from_mono(samples: &[T]) -> Self;
 
-    /// Create a stereo samples struct from 2 stereo input sample vectors
+    /// Create a stereo samples struct from 2 stereo input sample vectors.
+    /// If the channels differ in length, the longer channel is truncated to the length of the shorter one.
     fn from_stereo(left: &[T], right: &[T]) -> Self;
 }
 
-/// A generic helper function to interleave 2 vectors of equal length into a single vector
+/// A trait for the types a single sample of audio can be stored as, converting to and from f32 for processing.
+/// Implemented for i16 (as read from wav files) and f32, so buffer readers such as `Grain` can work with either.
+pub trait AudioSample: Copy + Default + 'static {
+    /// Converts the sample to an f32 in the same scale, with no normalization
+    fn to_f32(self) -> f32;
+
+    /// Converts an f32 to a sample, saturating at the limits of the type
+    fn from_f32(value: f32) -> Self;
+}
+
+impl AudioSample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value as i16
+    }
+}
+
+impl AudioSample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+/// A generic helper function to interleave 2 vectors into a single vector
 /// T can be any type but must implement copy. Should only be used in this context with number types
+///
+/// Channels processed independently can end up a sample or so apart in length,
+/// so rather than panicking, the output stops at the end of the shorter channel.
 /// # Parameters
 /// * `left`: a vector of T
 /// * `right`: a vector of T
 fn interleave<T: Copy>(left: &[T], right: &[T]) -> Vec<T> {
-    assert_eq!(left.len(), right.len());
-    let mut output: Vec<T> = Vec::new();
-    for index in 0..left.len() {
-        output.push(left[index]);
-        output.push(right[index]);
+    let mut output: Vec<T> = Vec::with_capacity(2 * left.len().min(right.len()));
+    for (left_sample, right_sample) in left.iter().zip(right.iter()) {
+        output.push(*left_sample);
+        output.push(*right_sample);
     }
     output
 }
@@ -60,6 +97,58 @@ impl IntSamples {
     pub fn samples(&self) -> Vec<i16> {
         self.samples.clone()
     }
+
+    /// Returns the samples of the left channel, the first of each interleaved pair
+    pub fn left(&self) -> Vec<i16> {
+        self.samples.chunks_exact(2).map(|frame| frame[0]).collect()
+    }
+
+    /// Returns the samples of the right channel, the second of each interleaved pair
+    pub fn right(&self) -> Vec<i16> {
+        self.samples.chunks_exact(2).map(|frame| frame[1]).collect()
+    }
+
+    /// Returns a mono downmix, the average of the left and right channels.
+    /// The channels are summed as i32, so loud samples don't overflow
+    pub fn to_mono(&self) -> Vec<i16> {
+        self.samples
+            .chunks_exact(2)
+            .map(|frame| ((frame[0] as i32 + frame[1] as i32) / 2) as i16)
+            .collect()
+    }
+
+    /// Returns the largest absolute sample value, with i16::MIN measured as i16::MAX
+    pub fn peak(&self) -> i16 {
+        self.samples
+            .iter()
+            .map(|sample| sample.saturating_abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the root mean square level of the samples, in the same scale as the samples
+    pub fn rms(&self) -> f32 {
+        rms(
+            self.samples.iter().map(|sample| *sample as f32),
+            self.samples.len(),
+        )
+    }
+
+    /// Scales the samples so the loudest one reaches a target peak, clamped to the i16 range.
+    /// Silent samples are left unchanged, as they can't be scaled up to any peak
+    /// # Parameters
+    /// * `target_peak`: The peak level to normalize to, as a fraction of full scale (1.0 is i16::MAX)
+    pub fn normalize(&mut self, target_peak: f32) {
+        let peak = self.peak();
+        if peak == 0 {
+            return;
+        }
+
+        let gain = target_peak * i16::MAX as f32 / peak as f32;
+        for sample in self.samples.iter_mut() {
+            *sample = (*sample as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
 }
 
 /// A struct storing a vector of float samples with associated methods and constructors
@@ -78,6 +167,123 @@ impl FloatSamples {
     pub fn samples(&self) -> Vec<f32> {
         self.samples.clone()
     }
+
+    /// Returns the samples of the left channel, the first of each interleaved pair
+    pub fn left(&self) -> Vec<f32> {
+        self.samples.chunks_exact(2).map(|frame| frame[0]).collect()
+    }
+
+    /// Returns the samples of the right channel, the second of each interleaved pair
+    pub fn right(&self) -> Vec<f32> {
+        self.samples.chunks_exact(2).map(|frame| frame[1]).collect()
+    }
+
+    /// Returns a mono downmix, the average of the left and right channels
+    pub fn to_mono(&self) -> Vec<f32> {
+        self.samples
+            .chunks_exact(2)
+            .map(|frame| (frame[0] + frame[1]) * 0.5)
+            .collect()
+    }
+
+    /// Returns the largest absolute sample value
+    pub fn peak(&self) -> f32 {
+        self.samples
+            .iter()
+            .fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    /// Returns the root mean square level of the samples
+    pub fn rms(&self) -> f32 {
+        rms(self.samples.iter().copied(), self.samples.len())
+    }
+
+    /// Scales the samples so the loudest one reaches a target peak.
+    /// Silent samples are left unchanged, as they can't be scaled up to any peak
+    /// # Parameters
+    /// * `target_peak`: The peak level to normalize to, where 1.0 is full scale
+    pub fn normalize(&mut self, target_peak: f32) {
+        let peak = self.peak();
+        if peak == 0.0 {
+            return;
+        }
+
+        let gain = target_peak / peak;
+        for sample in self.samples.iter_mut() {
+            *sample *= gain;
+        }
+    }
+}
+
+/// A struct storing a vector of interleaved samples with any number of channels,
+/// for round tripping audio through multichannel processors such as `MultiDelayLine`
+/// ## Attributes:
+/// * `samples`: The interleaved samples, one from each channel in turn
+///
+/// * `channels`: The number of channels the samples are interleaved from
+pub struct MultiSamples<T> {
+    samples: Vec<T>,
+    channels: usize,
+}
+
+impl<T: Copy> MultiSamples<T> {
+    /// Constructs a MultiSamples instance from interleaved samples and the number of channels they hold.
+    /// Panics if there are no channels or the samples don't divide evenly into frames
+    pub fn new(samples: Vec<T>, channels: usize) -> Self {
+        assert!(channels > 0, "samples must have at least one channel");
+        assert_eq!(
+            samples.len() % channels,
+            0,
+            "interleaved samples must contain a whole number of frames"
+        );
+        Self { samples, channels }
+    }
+
+    /// Constructs a MultiSamples instance by interleaving a vector of samples for each channel.
+    /// If the channels differ in length, the longer channels are truncated to the length of the shortest one.
+    pub fn from_channels(channels: &[Vec<T>]) -> Self {
+        let frame_count = channels
+            .iter()
+            .map(|channel| channel.len())
+            .min()
+            .unwrap_or(0);
+        let samples = (0..frame_count)
+            .flat_map(|frame| channels.iter().map(move |channel| channel[frame]))
+            .collect();
+        Self::new(samples, channels.len().max(1))
+    }
+
+    /// Gets a copy of the interleaved samples for processing
+    pub fn samples(&self) -> Vec<T> {
+        self.samples.clone()
+    }
+
+    /// Getter for the number of channels
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Returns a vector of frames, each holding one sample from every channel in order
+    pub fn get_frames(&self) -> Vec<Vec<T>> {
+        self.samples
+            .chunks_exact(self.channels)
+            .map(|frame| frame.to_vec())
+            .collect()
+    }
+
+    /// De-interleaves the samples, returning a vector of samples for each channel
+    pub fn to_channels(&self) -> Vec<Vec<T>> {
+        (0..self.channels)
+            .map(|channel| {
+                self.samples
+                    .iter()
+                    .skip(channel)
+                    .step_by(self.channels)
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
 }
 
 // the default preference will be to work with stereo samples as either i16 or f64
@@ -139,9 +345,57 @@ impl Samples<f32> for FloatSamples {
     }
 }
 
+/// Returns the root mean square of a number of samples, or 0 if there are none
+/// # Parameters
+/// * `samples`: An iterator over the samples as f32
+/// * `len`: The number of samples in the iterator
+fn rms(samples: impl Iterator<Item = f32>, len: usize) -> f32 {
+    match len {
+        0 => 0.0,
+        _ => (samples.map(|sample| sample * sample).sum::<f32>() / len as f32).sqrt(),
+    }
+}
+
+/// Measures the true (inter-sample) peak of a vector of float samples.
+///
+/// The samples are upsampled by the oversample factor using Lanczos interpolation (the same kernel as `LanczosResampler`)
+/// and the maximum absolute value is returned. This catches peaks between samples which would clip on D/A conversion.
+/// # Parameters
+/// * `samples`: A slice of f32 samples, treated as a single channel
+/// * `oversample`: The number of points to measure per input sample, 4 is usually sufficient. 1 gives the sample peak
+pub fn true_peak(samples: &[f32], oversample: usize) -> f32 {
+    // Lanczos window size, larger is more accurate but slower
+    const WINDOW_SIZE: isize = 4;
+    let oversample = oversample.max(1);
+    let mut peak: f32 = 0.0;
+
+    for (index, sample) in samples.iter().enumerate() {
+        // the original sample is always measured
+        peak = peak.max(sample.abs());
+
+        for step in 1..oversample {
+            let position = index as f32 + (step as f32 / oversample as f32);
+
+            let mut sum = 0.0;
+            let mut total_weight = 0.0;
+            for i in (index as isize - WINDOW_SIZE + 1)..=(index as isize + WINDOW_SIZE) {
+                // ignores indices outside the buffer range.
+                if i >= 0 && i < samples.len() as isize {
+                    let weight = lanczos_window(position - i as f32, WINDOW_SIZE as f32);
+                    sum += samples[i as usize] * weight;
+                    total_weight += weight;
+                }
+            }
+            peak = peak.max((sum / total_weight).abs());
+        }
+    }
+    peak
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::samples::{FloatSamples, IntSamples, Samples};
+    use crate::samples::{true_peak, FloatSamples, IntSamples, MultiSamples, Samples};
+    use std::f32::consts::PI;
 
     #[test]
     fn test_int_new() {
@@ -161,6 +415,15 @@ mod tests {
         assert_eq!(samples.samples, [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5])
     }
 
+    #[test]
+    fn test_from_stereo_unequal_lengths() {
+        let samples = IntSamples::from_stereo(&[0, 1, 2, 3], &[5, 6, 7]);
+        assert_eq!(samples.samples, [0, 5, 1, 6, 2, 7]);
+
+        let samples = FloatSamples::from_stereo(&[0.0], &[0.5, 1.0]);
+        assert_eq!(samples.samples, [0.0, 0.5]);
+    }
+
     #[test]
 
     fn test_int_get_frames() {
@@ -220,4 +483,97 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_multi_samples() {
+        let channels = vec![
+            vec![0, 1, 2],
+            vec![10, 11, 12],
+            vec![20, 21, 22],
+            vec![30, 31, 32],
+        ];
+        let samples = MultiSamples::from_channels(&channels);
+        assert_eq!(samples.channels(), 4);
+        assert_eq!(
+            samples.samples,
+            [0, 10, 20, 30, 1, 11, 21, 31, 2, 12, 22, 32]
+        );
+        assert_eq!(samples.get_frames()[1], [1, 11, 21, 31]);
+        assert_eq!(samples.to_channels(), channels);
+
+        // channels of different lengths are truncated to the shortest
+        let samples = MultiSamples::from_channels(&[vec![0.0, 1.0], vec![0.5]]);
+        assert_eq!(samples.to_channels(), [vec![0.0], vec![0.5]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_multi_samples_partial_frame_panics() {
+        MultiSamples::new(vec![0; 7], 4);
+    }
+
+    #[test]
+    fn test_channel_helpers() {
+        let samples = IntSamples::new(vec![i16::MAX, i16::MAX, 100, -300, -4, 2]);
+        assert_eq!(samples.left(), [i16::MAX, 100, -4]);
+        assert_eq!(samples.right(), [i16::MAX, -300, 2]);
+        // two full scale channels average to full scale rather than wrapping around
+        assert_eq!(samples.to_mono(), [i16::MAX, -100, -1]);
+
+        let samples = FloatSamples::new(vec![1.0, 0.0, -0.5, 0.25]);
+        assert_eq!(samples.left(), [1.0, -0.5]);
+        assert_eq!(samples.right(), [0.0, 0.25]);
+        assert_eq!(samples.to_mono(), [0.5, -0.125]);
+    }
+
+    #[test]
+    fn test_int_metering() {
+        let mut samples = IntSamples::new(vec![0, 1000, -2000, 500, i16::MIN]);
+        assert_eq!(samples.peak(), i16::MAX);
+
+        samples = IntSamples::new(vec![1000, -1000, 1000, -1000]);
+        assert_eq!(samples.rms(), 1000.0);
+
+        // the peak is scaled to the target, and every other sample by the same gain
+        samples = IntSamples::new(vec![0, 1000, -2000, 500]);
+        samples.normalize(0.5);
+        assert_eq!(samples.peak(), 16383);
+        assert_eq!(samples.samples, [0, 8191, -16383, 4095]);
+
+        // silence stays silent rather than dividing by 0
+        let mut silence = IntSamples::new(vec![0; 8]);
+        silence.normalize(1.0);
+        assert_eq!(silence.samples, [0; 8]);
+        assert_eq!(IntSamples::new(vec![]).rms(), 0.0);
+    }
+
+    #[test]
+    fn test_float_metering() {
+        let mut samples = FloatSamples::new(vec![0.0, 0.25, -0.5, 0.125]);
+        assert_eq!(samples.peak(), 0.5);
+
+        samples.normalize(1.0);
+        assert_eq!(samples.samples, [0.0, 0.5, -1.0, 0.25]);
+
+        // a full scale sine has an RMS of 1 / sqrt(2)
+        let sine = FloatSamples::new(
+            (0..4410)
+                .map(|n| (2.0 * PI * n as f32 / 441.0).sin())
+                .collect(),
+        );
+        assert!((sine.rms() - 0.5_f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_true_peak() {
+        // a sine at a quarter of the sample rate, offset by 45 degrees so every sample misses the crest
+        let samples: Vec<f32> = (0..64)
+            .map(|n| ((0.5 * PI * n as f32) + (0.25 * PI)).sin())
+            .collect();
+        let sample_peak = true_peak(&samples, 1);
+        let oversampled_peak = true_peak(&samples, 4);
+
+        assert!((sample_peak - 0.7071).abs() < 0.001);
+        assert!(oversampled_peak > sample_peak + 0.2);
+    }
 }
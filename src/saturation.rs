@@ -1,13 +1,38 @@
 //! Module containing a struct that performs saturation on a given input, with a threshold level and mixes the output
+//! ClipMode selects between hard clipping at the threshold, a smooth tanh curve approaching it and a cubic soft clipper
+//! MultibandSaturator splits the input into 3 bands with crossovers and saturates each band independently
+//! EmphasizedSaturator boosts the highs before saturating and cuts them after, concentrating distortion in the highs
+use crate::filter::{Crossover, ShelfFilter, ShelfType};
 use std::ops::Neg;
 
-/// A struct which stores 2 fields and uses them to saturate (clip) an input
+/// An enum of the curves a `Saturator` can clip with
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum ClipMode {
+    /// Signals above the threshold are cut off flat at the threshold
+    #[default]
+    Hard,
+    /// Signals are bent smoothly towards the threshold with a tanh curve, never quite reaching it
+    Soft,
+    /// Signals are bent towards the threshold by the cubic `x - x^3 / 3`, reaching it exactly at 1.5 times the
+    /// threshold and clipping flat above it. Quiet signals pass at unity like the other modes, and the curve is
+    /// gentler than hard clipping while bending less gradually than tanh
+    Cubic,
+}
+
+/// A struct which stores 3 fields and uses them to saturate (clip) an input
 /// ## Attributes:
 /// * `threshold`: The amplitude (f32) at which signals will be clipped
 /// * `mix_ratio`: Ratio between 1 and 0 of how much saturated signal is mixed in (1 is full clipping and 0 is dry)
+/// * `clip_mode`: The curve used to clip the signal, hard by default
+/// * `bias`: An offset added before clipping so positive and negative excursions clip differently, 0 is symmetric
+/// * `drive`: The gain applied to the input before clipping, 1 is unchanged
+#[derive(Clone)]
 pub struct Saturator {
     threshold: f32,
     mix_ratio: f32,
+    clip_mode: ClipMode,
+    bias: f32,
+    drive: f32,
 }
 
 impl Saturator {
@@ -16,9 +41,17 @@ impl Saturator {
         Self {
             threshold,
             mix_ratio,
+            clip_mode: ClipMode::Hard,
+            bias: 0.0,
+            drive: 1.0,
         }
     }
 
+    /// Setter for the clipping curve
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.clip_mode = clip_mode;
+    }
+
     /// Setter for saturator ...
     pub fn set_threshold(&mut self, threshold: f32) {
         self.threshold = threshold;
@@ -29,23 +62,219 @@ impl Saturator {
         self.mix_ratio = mix_ratio.clamp(0.0, 1.0);
     }
 
+    /// Setter for the asymmetry of the clipping, a bias in the same units as the threshold which is added before
+    /// clipping. The clipped bias is subtracted afterwards, so silence stays silent, while the uneven clipping of
+    /// the two halves of the waveform adds even harmonics. A bias of 0 gives symmetric clipping.
+    pub fn set_asymmetry(&mut self, bias: f32) {
+        self.bias = bias;
+    }
+
+    /// Setter for the drive, the gain the input is pushed into the clipping curve with, which must be above 0.
+    /// The saturated signal is divided by the drive, so quiet signals come out at the same level whatever the drive
+    /// in every clip mode. Pushing harder adds distortion and squashes peaks, rather than only getting louder
+    pub fn set_drive(&mut self, drive: f32) {
+        self.drive = drive.max(f32::EPSILON);
+    }
+
+    /// Applies the clipping curve to a sample, with no bias or mixing
+    fn clip(&self, xn: f32) -> f32 {
+        match self.clip_mode {
+            ClipMode::Hard => match xn {
+                xn if xn > self.threshold => self.threshold,
+                xn if xn < self.threshold.neg() => self.threshold.neg(),
+                _ => xn,
+            },
+            ClipMode::Soft => self.threshold * (xn / self.threshold).tanh(),
+            ClipMode::Cubic => {
+                // the input is scaled by 2/3 and the output by 1.5, so quiet signals pass at unity gain
+                // while the curve still peaks at the threshold rather than at 2/3 of it
+                let x = (xn / (1.5 * self.threshold)).clamp(-1.0, 1.0);
+                self.threshold * 1.5 * (x - x.powi(3) / 3.0)
+            }
+        }
+    }
+
     /// Takes an f32 input using saturation
     pub fn process(&self, xn: f32) -> f32 {
-        let value = match xn {
-            xn if xn > self.threshold => self.threshold,
-            xn if xn < self.threshold.neg() => self.threshold.neg(),
-            _ => xn,
-        };
+        // every clip mode passes quiet signals at unity, so undoing the drive keeps their level unchanged
+        let makeup = 1.0 / self.drive;
+        let value = makeup * (self.clip(xn * self.drive + self.bias) - self.clip(self.bias));
         (self.mix_ratio * value) + ((1.0 - self.mix_ratio) * xn)
     }
 }
 
+/// An enum of the frequency bands of a `MultibandSaturator`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Band {
+    /// The band below the low crossover frequency
+    Low,
+    /// The band between the low and high crossover frequencies
+    Mid,
+    /// The band above the high crossover frequency
+    High,
+}
+
+/// A struct which splits an input into low, mid and high bands and saturates each with its own `Saturator`,
+/// before summing the bands back together.
+/// ## Attributes:
+/// * `low_crossover`: The crossover splitting the low band from the rest of the signal
+/// * `high_crossover`: The crossover splitting the remainder into the mid and high bands
+/// * `low_compensation`: A crossover at the high frequency whose bands are summed, giving the low band the same
+///   phase shift as the mid and high bands so that all three recombine with a flat magnitude response
+/// * `saturators`: A saturator for each band, in the order low, mid, high
+pub struct MultibandSaturator {
+    low_crossover: Crossover,
+    high_crossover: Crossover,
+    low_compensation: Crossover,
+    saturators: [Saturator; 3],
+}
+
+impl MultibandSaturator {
+    /// Constructor given the crossover frequencies in Hz and sample rate.
+    /// Every band starts with the given threshold and a mix of 0, so the saturator is transparent by default.
+    pub fn new(low_freq: f32, high_freq: f32, threshold: f32, sample_rate: f32) -> Self {
+        Self {
+            low_crossover: Crossover::new(low_freq, sample_rate),
+            high_crossover: Crossover::new(high_freq, sample_rate),
+            low_compensation: Crossover::new(high_freq, sample_rate),
+            saturators: [
+                Saturator::new(threshold, 0.0),
+                Saturator::new(threshold, 0.0),
+                Saturator::new(threshold, 0.0),
+            ],
+        }
+    }
+
+    /// Returns the index into `saturators` for a band
+    fn band_index(band: Band) -> usize {
+        match band {
+            Band::Low => 0,
+            Band::Mid => 1,
+            Band::High => 2,
+        }
+    }
+
+    /// Setter for the saturation threshold of a single band
+    pub fn set_threshold(&mut self, band: Band, threshold: f32) {
+        self.saturators[Self::band_index(band)].set_threshold(threshold);
+    }
+
+    /// Setter for the mix ratio of a single band (must be between 0 and 1)
+    pub fn set_mix_ratio(&mut self, band: Band, mix_ratio: f32) {
+        self.saturators[Self::band_index(band)].set_mix_ratio(mix_ratio);
+    }
+
+    /// Setter for the crossover frequencies in Hz
+    pub fn set_crossovers(&mut self, low_freq: f32, high_freq: f32, sample_rate: f32) {
+        self.low_crossover.set_crossover(low_freq, sample_rate);
+        self.high_crossover.set_crossover(high_freq, sample_rate);
+        self.low_compensation.set_crossover(high_freq, sample_rate);
+    }
+
+    /// Takes an f32 input, splits it into bands, saturates each band and returns their sum
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let (low, rest) = self.low_crossover.process(xn);
+        let (mid, high) = self.high_crossover.process(rest);
+        let (low_below, low_above) = self.low_compensation.process(low);
+        let low = low_below + low_above;
+
+        self.saturators[0].process(low)
+            + self.saturators[1].process(mid)
+            + self.saturators[2].process(high)
+    }
+}
+
+/// A struct which wraps a `Saturator` with a high shelf boost before it (pre-emphasis) and a matching cut after it
+/// (de-emphasis), so the highs are pushed into clipping harder and then tamed, leaving an unclipped signal unchanged.
+/// ## Attributes:
+/// * `saturator`: The saturator applied to the emphasized signal
+/// * `pre_emphasis`: The high shelf boosting the highs before saturation
+/// * `de_emphasis`: The high shelf cutting the highs by the same amount after saturation
+pub struct EmphasizedSaturator {
+    saturator: Saturator,
+    pre_emphasis: ShelfFilter,
+    de_emphasis: ShelfFilter,
+}
+
+impl EmphasizedSaturator {
+    /// Constructor given the saturator threshold and mix ratio, the corner frequency of the emphasis shelves in Hz
+    /// and the sample rate. The emphasis starts at 0dB, so it behaves like a plain `Saturator` by default.
+    pub fn new(threshold: f32, mix_ratio: f32, corner_freq: f32, sample_rate: f32) -> Self {
+        Self {
+            saturator: Saturator::new(threshold, mix_ratio),
+            pre_emphasis: ShelfFilter::new(ShelfType::HighShelf, corner_freq, 0.0, sample_rate),
+            de_emphasis: ShelfFilter::new(ShelfType::HighShelf, corner_freq, 0.0, sample_rate),
+        }
+    }
+
+    /// Setter for the emphasis in dB, the highs are boosted by this before saturation and cut by it after
+    pub fn set_emphasis(&mut self, db: f32) {
+        self.pre_emphasis.set_gain_db(db);
+        self.de_emphasis.set_gain_db(-db);
+    }
+
+    /// Setter for the corner frequency of both emphasis shelves in Hz
+    pub fn set_corner(&mut self, corner_freq: f32) {
+        self.pre_emphasis.set_corner(corner_freq);
+        self.de_emphasis.set_corner(corner_freq);
+    }
+
+    /// Setter for the saturation threshold
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.saturator.set_threshold(threshold);
+    }
+
+    /// Setter for the mix ratio (must be between 0 and 1)
+    pub fn set_mix_ratio(&mut self, mix_ratio: f32) {
+        self.saturator.set_mix_ratio(mix_ratio);
+    }
+
+    /// Setter for the clipping curve
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.saturator.set_clip_mode(clip_mode);
+    }
+
+    /// Takes an f32 input, boosts its highs, saturates it, then cuts the highs back down
+    pub fn process(&mut self, xn: f32) -> f32 {
+        let emphasized = self.pre_emphasis.process(xn);
+        let saturated = self.saturator.process(emphasized);
+        self.de_emphasis.process(saturated)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::delay_line::StereoDelay;
     use crate::samples::{IntSamples, PhonicMode, Samples};
-    use crate::saturation::Saturator;
+    use crate::saturation::{Band, ClipMode, EmphasizedSaturator, MultibandSaturator, Saturator};
     use crate::{load_wav, write_wav};
+    use std::f32::consts::PI;
+
+    /// Renders a second of a sine through a multiband saturator which only saturates the high band,
+    /// returning the RMS of the difference between its output and that of a transparent multiband saturator.
+    /// The crossovers shift the phase of the signal, so the clean output is used as the reference rather than the input
+    fn high_band_distortion(frequency: f32) -> f32 {
+        let mut saturator = MultibandSaturator::new(200.0, 2000.0, 1.0, 44100.0);
+        saturator.set_threshold(Band::High, 0.1);
+        saturator.set_mix_ratio(Band::High, 1.0);
+        let mut clean = MultibandSaturator::new(200.0, 2000.0, 1.0, 44100.0);
+
+        let squared_error: f32 = (0..44100)
+            .map(|n| {
+                let xn = (2.0 * PI * frequency * n as f32 / 44100.0).sin();
+                (saturator.process(xn) - clean.process(xn)).powi(2)
+            })
+            .sum();
+        (squared_error / 44100.0).sqrt()
+    }
+
+    #[test]
+    fn test_multiband_high_only() {
+        // highs are clipped heavily, adding harmonics
+        assert!(high_band_distortion(8000.0) > 0.3);
+        // lows barely reach the high band, so stay clean
+        assert!(high_band_distortion(30.0) < 0.01);
+    }
 
     #[test]
     fn generate_saturation_example() {
@@ -84,4 +313,142 @@ mod tests {
 
         write_wav("tests/debug/saturated_delay.wav", out, PhonicMode::Stereo);
     }
+
+    #[test]
+    fn test_soft_clip_hot_signal() {
+        let mut saturator = Saturator::new(1.0, 1.0);
+        saturator.set_clip_mode(ClipMode::Soft);
+
+        // a sine driven 12dB over full scale
+        let output: Vec<f32> = (0..44100)
+            .map(|n| saturator.process(4.0 * (2.0 * PI * 100.0 * n as f32 / 44100.0).sin()))
+            .collect();
+        let peak = output.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+        assert!(peak <= 1.0 && peak > 0.99, "peak was {peak}");
+
+        // quiet signals pass almost untouched
+        assert!((saturator.process(0.05) - 0.05).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_cubic_clip() {
+        let mut saturator = Saturator::new(2.0, 1.0);
+        saturator.set_clip_mode(ClipMode::Cubic);
+
+        // quiet signals pass at unity gain, like hard and soft clipping
+        assert!((saturator.process(0.01) - 0.01).abs() < 1e-5);
+
+        // the curve reaches the threshold exactly at 1.5 times it and is flat beyond
+        assert!((saturator.process(3.0) - 2.0).abs() < 1e-6);
+        assert!((saturator.process(8.0) - 2.0).abs() < 1e-6);
+        assert!((saturator.process(-8.0) + 2.0).abs() < 1e-6);
+
+        // in between it bends smoothly, and the dry mix is blended back in
+        assert!((saturator.process(1.0) - 26.0 / 27.0).abs() < 1e-6);
+        saturator.set_mix_ratio(0.5);
+        assert!((saturator.process(1.0) - 53.0 / 54.0).abs() < 1e-6);
+    }
+
+    /// Returns the magnitude of a harmonic of a 100Hz sine at full scale after a second through the saturator
+    fn harmonic_magnitude(saturator: &Saturator, harmonic: f32) -> f32 {
+        let (re, im) = (0..44100).fold((0.0_f32, 0.0_f32), |(re, im), n| {
+            let t = n as f32 / 44100.0;
+            let yn = saturator.process((2.0 * PI * 100.0 * t).sin());
+            let phase = 2.0 * PI * 100.0 * harmonic * t;
+            (re + yn * phase.cos(), im + yn * phase.sin())
+        });
+        (re * re + im * im).sqrt() / 44100.0
+    }
+
+    #[test]
+    fn test_asymmetry() {
+        let symmetric = Saturator::new(0.5, 1.0);
+        let mut asymmetric = Saturator::new(0.5, 1.0);
+
+        // a bias of 0 behaves exactly as before
+        asymmetric.set_asymmetry(0.0);
+        for xn in [-1.0, -0.3, 0.0, 0.2, 0.7] {
+            assert_eq!(asymmetric.process(xn), symmetric.process(xn));
+        }
+
+        // biased clipping leaves silence alone, clips the two halves differently and adds a second harmonic
+        asymmetric.set_asymmetry(0.2);
+        assert_eq!(asymmetric.process(0.0), 0.0);
+        assert!((asymmetric.process(1.0) - 0.3).abs() < 1e-6);
+        assert!((asymmetric.process(-1.0) + 0.7).abs() < 1e-6);
+        assert!(harmonic_magnitude(&symmetric, 2.0) < 0.001);
+        assert!(harmonic_magnitude(&asymmetric, 2.0) > 0.01);
+    }
+
+    /// Returns the RMS of a second of a 100Hz sine at an amplitude through the saturator
+    fn output_level(saturator: &Saturator, amplitude: f32) -> f32 {
+        let squared: f32 = (0..44100)
+            .map(|n| {
+                let xn = amplitude * (2.0 * PI * 100.0 * n as f32 / 44100.0).sin();
+                saturator.process(xn).powi(2)
+            })
+            .sum();
+        (squared / 44100.0).sqrt()
+    }
+
+    #[test]
+    fn test_drive() {
+        for mode in [ClipMode::Hard, ClipMode::Soft, ClipMode::Cubic] {
+            let mut saturator = Saturator::new(1.0, 1.0);
+            saturator.set_clip_mode(mode);
+            let clean_quiet = output_level(&saturator, 0.01);
+            let clean_loud = output_level(&saturator, 1.0);
+            let clean_distortion =
+                harmonic_magnitude(&saturator, 3.0) / harmonic_magnitude(&saturator, 1.0);
+
+            saturator.set_drive(8.0);
+            let driven_distortion =
+                harmonic_magnitude(&saturator, 3.0) / harmonic_magnitude(&saturator, 1.0);
+
+            // quiet signals keep their level, and louder ones are squashed rather than boosted
+            let quiet_db = 20.0 * (output_level(&saturator, 0.01) / clean_quiet).log10();
+            assert!(
+                quiet_db.abs() < 0.1,
+                "{mode:?} quiet level changed by {quiet_db}dB"
+            );
+            assert!(
+                output_level(&saturator, 1.0) <= clean_loud,
+                "{mode:?} got louder"
+            );
+            // more drive adds distortion
+            assert!(
+                driven_distortion > 2.0 * clean_distortion + 0.01,
+                "{mode:?} distortion {clean_distortion} to {driven_distortion}"
+            );
+        }
+    }
+
+    /// Renders a second of a sine at half scale through a saturator clipping at 0.6, with the given emphasis,
+    /// returning the RMS of the difference between the output and input, once the filters have settled
+    fn emphasized_distortion(frequency: f32, emphasis_db: f32) -> f32 {
+        let mut saturator = EmphasizedSaturator::new(0.6, 1.0, 2000.0, 44100.0);
+        saturator.set_emphasis(emphasis_db);
+
+        let squared_error: f32 = (0..44100)
+            .map(|n| {
+                let xn = 0.5 * (2.0 * PI * frequency * n as f32 / 44100.0).sin();
+                (saturator.process(xn) - xn).powi(2)
+            })
+            .skip(4410)
+            .sum();
+        (squared_error / (44100.0 - 4410.0)).sqrt()
+    }
+
+    #[test]
+    fn test_emphasis_biases_distortion() {
+        // flat, the sine never reaches the threshold at any frequency
+        assert!(emphasized_distortion(6000.0, 0.0) < 0.001);
+        assert!(emphasized_distortion(100.0, 0.0) < 0.001);
+
+        // with 12dB of emphasis the highs are driven into clipping, while the lows stay clean
+        let high = emphasized_distortion(6000.0, 12.0);
+        let low = emphasized_distortion(100.0, 12.0);
+        assert!(high > 0.05, "high band distortion was {high}");
+        assert!(low < 0.01, "low band distortion was {low}");
+    }
 }
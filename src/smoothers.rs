@@ -1,4 +1,5 @@
-//! Trait and Structs for performing window functional smoothing on f32 samples
+//! Trait and Structs for performing window functional smoothing on f32 samples.
+//! Also contains a OnePoleSmoother, which smooths parameter changes rather than windowing samples.
 use std::f32::consts::PI;
 
 /// Trait for a smoother object, with associated window length and a method to get the next sample from the window.
@@ -30,6 +31,62 @@ impl Smoother for NoSmoother {
     fn set_length(&mut self, _: usize) {}
 }
 
+/// The fraction of a `GrainWindow::Tukey` window spent tapering, split between the start and end
+const DEFAULT_TUKEY_ALPHA: f32 = 0.5;
+
+/// The standard deviation of a `GrainWindow::Gaussian` window, as a fraction of half the window length
+const DEFAULT_GAUSSIAN_SIGMA: f32 = 0.4;
+
+/// The narrowest Gaussian window allowed, as narrower curves are rounded to silence across most of the window
+const MIN_GAUSSIAN_SIGMA: f32 = 0.01;
+
+/// Renders a window function over `length` samples into a buffer, where the function takes the position through the window from 0 to 1.
+/// The buffer is refilled in place, so it only allocates when growing beyond its capacity.
+fn render_window(discrete: &mut Vec<f32>, length: usize, function: impl Fn(f32) -> f32) {
+    let delta: f32 = 1.0 / (length as f32);
+    discrete.clear();
+    discrete.extend((0..length).map(|index| function(index as f32 * delta)));
+}
+
+/// Reads a rendered window at an index, holding the last value for indices past the end
+fn read_window(discrete: &[f32], index: usize) -> f32 {
+    match index >= discrete.len() {
+        true => discrete[discrete.len() - 1],
+        false => discrete[index],
+    }
+}
+
+/// An enum of the window shapes available for grains, used to choose a smoother by value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GrainWindow {
+    /// A raised cosine window, using `HannSmoother`
+    Hann,
+    /// A linear fade in and out, using `TriangularSmoother`
+    Triangular,
+    /// A narrower raised cosine window with lower sidelobes than Hann, using `BlackmanSmoother`
+    Blackman,
+    /// A flat window with raised cosine tapers at each end, using `TukeySmoother`
+    Tukey,
+    /// A bell curve window, truncated so its edges reach 0, using `GaussianSmoother`
+    Gaussian,
+    /// No windowing at all, using `NoSmoother`
+    Rectangular,
+}
+
+impl GrainWindow {
+    /// Returns a new smoother object for the window shape, with an unset length
+    pub fn smoother(&self) -> Box<dyn Smoother> {
+        match self {
+            GrainWindow::Hann => Box::new(HannSmoother::new()),
+            GrainWindow::Triangular => Box::new(TriangularSmoother::new()),
+            GrainWindow::Blackman => Box::new(BlackmanSmoother::new()),
+            GrainWindow::Tukey => Box::new(TukeySmoother::new(DEFAULT_TUKEY_ALPHA)),
+            GrainWindow::Gaussian => Box::new(GaussianSmoother::new(DEFAULT_GAUSSIAN_SIGMA)),
+            GrainWindow::Rectangular => Box::new(NoSmoother::new()),
+        }
+    }
+}
+
 /// A struct which performs Hann window smoothing, using a discrete vector of samples of the window function
 #[derive(Default)]
 pub struct HannSmoother {
@@ -50,31 +107,333 @@ impl HannSmoother {
 impl Smoother for HannSmoother {
     /// Getter for the next sample from the discrete function
     fn get_index(&self, index: usize) -> f32 {
-        if index >= self.length {
-            self.discrete[self.length - 1]
-        } else {
-            self.discrete[index]
-        }
+        read_window(&self.discrete, index)
     }
 
     /// Setter for the length of the window function.
-    /// Also recomputes the discrete function with the new length, so should be used sparingly.
+    /// Also recomputes the discrete function when the length changes, reusing the existing buffer.
     fn set_length(&mut self, length: usize) {
-        self.discrete.clear();
+        if length == self.length {
+            return;
+        }
         self.length = length;
-        let delta: f32 = 1.0 / (length as f32);
-        for index in 0..length {
-            let cos_1: f32 = (PI * ((index as f32 * delta) - 0.5)).cos();
-            self.discrete.push(cos_1.powi(2))
+        render_window(&mut self.discrete, length, |x| {
+            (PI * (x - 0.5)).cos().powi(2)
+        });
+    }
+}
+
+/// A struct which performs triangular window smoothing, fading linearly in to the middle of the window and back out
+#[derive(Default)]
+pub struct TriangularSmoother {
+    discrete: Vec<f32>,
+}
+
+impl TriangularSmoother {
+    /// Constructor for the triangular window smoother, with an uninitialized discrete buffer
+    pub fn new() -> Self {
+        Self {
+            discrete: Vec::new(),
         }
     }
 }
 
+impl Smoother for TriangularSmoother {
+    /// Getter for the next sample from the discrete function
+    fn get_index(&self, index: usize) -> f32 {
+        read_window(&self.discrete, index)
+    }
+
+    /// Setter for the length of the window function, recomputing the discrete function when the length changes
+    fn set_length(&mut self, length: usize) {
+        if length == self.discrete.len() {
+            return;
+        }
+        render_window(&mut self.discrete, length, |x| 1.0 - (2.0 * x - 1.0).abs());
+    }
+}
+
+/// A struct which performs Blackman window smoothing, tighter than Hann with a faster fall off at the edges
+#[derive(Default)]
+pub struct BlackmanSmoother {
+    discrete: Vec<f32>,
+}
+
+impl BlackmanSmoother {
+    /// Constructor for the Blackman window smoother, with an uninitialized discrete buffer
+    pub fn new() -> Self {
+        Self {
+            discrete: Vec::new(),
+        }
+    }
+}
+
+impl Smoother for BlackmanSmoother {
+    /// Getter for the next sample from the discrete function
+    fn get_index(&self, index: usize) -> f32 {
+        read_window(&self.discrete, index)
+    }
+
+    /// Setter for the length of the window function, recomputing the discrete function when the length changes
+    fn set_length(&mut self, length: usize) {
+        if length == self.discrete.len() {
+            return;
+        }
+        render_window(&mut self.discrete, length, |x| {
+            0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()
+        });
+    }
+}
+
+/// A struct which performs Tukey window smoothing, which is flat in the middle with raised cosine tapers at each end.
+/// ## Attributes
+/// * `alpha`: The fraction of the window spent tapering, between 0 (rectangular) and 1 (the same as Hann)
+///
+/// * `discrete`: The rendered window function
+pub struct TukeySmoother {
+    alpha: f32,
+    discrete: Vec<f32>,
+}
+
+impl TukeySmoother {
+    /// Constructor for the Tukey window smoother, with the fraction of the window spent tapering clamped between 0 and 1
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(0.0, 1.0),
+            discrete: Vec::new(),
+        }
+    }
+}
+
+impl Smoother for TukeySmoother {
+    /// Getter for the next sample from the discrete function
+    fn get_index(&self, index: usize) -> f32 {
+        read_window(&self.discrete, index)
+    }
+
+    /// Setter for the length of the window function, recomputing the discrete function when the length changes
+    fn set_length(&mut self, length: usize) {
+        if length == self.discrete.len() {
+            return;
+        }
+        let alpha = self.alpha;
+        render_window(&mut self.discrete, length, |x| {
+            // the distance into the nearest taper, as a fraction of the taper length
+            let taper = x.min(1.0 - x) / (0.5 * alpha);
+            match taper < 1.0 {
+                true => 0.5 * (1.0 - (PI * taper).cos()),
+                false => 1.0,
+            }
+        });
+    }
+}
+
+/// A struct which performs Gaussian window smoothing, a bell curve centered on the middle of the window.
+/// The curve is truncated at the window edges, then offset and rescaled so it starts and ends at 0 and peaks at 1,
+/// avoiding clicks at grain boundaries whatever the sigma.
+/// ## Attributes
+/// * `sigma`: The standard deviation of the curve as a fraction of half the window length, smaller is narrower
+///
+/// * `discrete`: The rendered window function
+pub struct GaussianSmoother {
+    sigma: f32,
+    discrete: Vec<f32>,
+}
+
+impl GaussianSmoother {
+    /// Constructor for the Gaussian window smoother, with the standard deviation as a fraction of half the window length
+    pub fn new(sigma: f32) -> Self {
+        Self {
+            sigma: sigma.max(MIN_GAUSSIAN_SIGMA),
+            discrete: Vec::new(),
+        }
+    }
+
+    /// Setter for the standard deviation as a fraction of half the window length, independent of the window length.
+    /// Recomputes the discrete function at the current length.
+    pub fn set_sigma(&mut self, sigma: f32) {
+        self.sigma = sigma.max(MIN_GAUSSIAN_SIGMA);
+        let length = self.discrete.len();
+        self.render(length);
+    }
+
+    /// Fills the discrete buffer with the truncated Gaussian curve
+    fn render(&mut self, length: usize) {
+        let sigma = self.sigma;
+        let gaussian = |x: f32| (-0.5 * ((2.0 * x - 1.0) / sigma).powi(2)).exp();
+        let edge = gaussian(0.0);
+        render_window(&mut self.discrete, length, |x| {
+            (gaussian(x) - edge) / (1.0 - edge)
+        });
+    }
+}
+
+impl Smoother for GaussianSmoother {
+    /// Getter for the next sample from the discrete function
+    fn get_index(&self, index: usize) -> f32 {
+        read_window(&self.discrete, index)
+    }
+
+    /// Setter for the length of the window function, recomputing the discrete function when the length changes
+    fn set_length(&mut self, length: usize) {
+        if length == self.discrete.len() {
+            return;
+        }
+        self.render(length);
+    }
+}
+
+/// A one-pole parameter smoother, which approaches its target exponentially each sample to avoid zipper noise
+/// when a parameter jumps.
+/// ## Attributes
+/// * `current`: The smoothed value, as last returned by `next`
+///
+/// * `target`: The value being approached
+///
+/// * `coeff`: The one-pole coefficient, 0 means changes are instant
+pub struct OnePoleSmoother {
+    current: f32,
+    target: f32,
+    coeff: f32,
+}
+
+impl OnePoleSmoother {
+    /// Constructor for the smoother given the time constant in milliseconds and the sample rate in Hz, starting at 0
+    pub fn new(smoothing_ms: f32, sample_rate: f32) -> Self {
+        let mut smoother = Self {
+            current: 0.0,
+            target: 0.0,
+            coeff: 0.0,
+        };
+        smoother.set_smoothing_time(smoothing_ms, sample_rate);
+        smoother
+    }
+
+    /// Setter for the time constant in milliseconds, after which around 63% of a change has been made.
+    /// A time of 0 makes changes instant.
+    pub fn set_smoothing_time(&mut self, smoothing_ms: f32, sample_rate: f32) {
+        self.coeff = match smoothing_ms > 0.0 {
+            true => (-1.0 / (smoothing_ms * 0.001 * sample_rate)).exp(),
+            false => 0.0,
+        };
+    }
+
+    /// Setter for the value to approach
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Jumps straight to a value without smoothing, for initialising parameters
+    pub fn reset(&mut self, value: f32) {
+        self.current = value;
+        self.target = value;
+    }
+
+    /// Advances the smoothed value one sample towards the target and returns it
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> f32 {
+        self.current = self.target + self.coeff * (self.current - self.target);
+        self.current
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::samples::PhonicMode;
-    use crate::smoothers::{HannSmoother, Smoother};
+    use crate::smoothers::{
+        GaussianSmoother, GrainWindow, HannSmoother, NoSmoother, OnePoleSmoother, Smoother,
+        TukeySmoother,
+    };
     use crate::{load_wav, write_wav};
+    use test_case::test_case;
+
+    #[test_case(GrainWindow::Hann, 0.0 ; "hann")]
+    #[test_case(GrainWindow::Triangular, 0.0 ; "triangular")]
+    #[test_case(GrainWindow::Blackman, 0.0 ; "blackman")]
+    #[test_case(GrainWindow::Tukey, 0.0 ; "tukey")]
+    #[test_case(GrainWindow::Gaussian, 0.0 ; "gaussian")]
+    #[test_case(GrainWindow::Rectangular, 1.0 ; "rectangular")]
+    fn test_window_shapes(window: GrainWindow, edge: f32) {
+        let mut smoother = window.smoother();
+        smoother.set_length(1000);
+
+        // every window is at full level in the middle, and symmetrical
+        assert!((smoother.get_index(500) - 1.0).abs() < 1e-4);
+        assert!((smoother.get_index(0) - edge).abs() < 0.01);
+        for index in 1..500 {
+            assert!((smoother.get_index(index) - smoother.get_index(1000 - index)).abs() < 1e-4);
+        }
+
+        // the Tukey window is flat in the middle half
+        if window == GrainWindow::Tukey {
+            assert!((250..=750).all(|index| smoother.get_index(index) > 0.9999));
+        }
+    }
+
+    #[test]
+    fn test_gaussian_sigma() {
+        let mut gaussian = GaussianSmoother::new(0.4);
+        gaussian.set_length(1000);
+        let wide = gaussian.get_index(250);
+
+        // narrowing the curve keeps the length, edges and peak, but lowers the shoulders
+        gaussian.set_sigma(0.2);
+        assert_eq!(gaussian.discrete.len(), 1000);
+        assert!(gaussian.get_index(0).abs() < 1e-6);
+        assert!((gaussian.get_index(500) - 1.0).abs() < 1e-4);
+        assert!(gaussian.get_index(250) < wide);
+    }
+
+    #[test]
+    fn test_set_length_reuses_buffer() {
+        let mut hann = HannSmoother::new();
+        hann.set_length(1000);
+        let buffer = hann.discrete.as_ptr();
+
+        // an unchanged length is not recomputed, and a shorter one is rendered into the same allocation
+        hann.set_length(1000);
+        assert_eq!(hann.discrete.as_ptr(), buffer);
+        hann.set_length(500);
+        assert_eq!(hann.discrete.as_ptr(), buffer);
+        assert_eq!(hann.discrete.len(), 500);
+        assert!((hann.get_index(250) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_tukey_extremes() {
+        // with no taper the Tukey window is rectangular, and with a full taper it is a Hann window
+        let mut rectangular = TukeySmoother::new(0.0);
+        let mut full_taper = TukeySmoother::new(1.0);
+        let mut hann = HannSmoother::new();
+        rectangular.set_length(1000);
+        full_taper.set_length(1000);
+        hann.set_length(1000);
+        for index in 0..1000 {
+            assert_eq!(
+                rectangular.get_index(index),
+                NoSmoother::new().get_index(index)
+            );
+            assert!((full_taper.get_index(index) - hann.get_index(index)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_one_pole_smoother() {
+        let mut smoother = OnePoleSmoother::new(10.0, 44100.0);
+        smoother.reset(1.0);
+        smoother.set_target(2.0);
+
+        // the step is spread over time rather than jumping, reaching 63% after one time constant
+        let values: Vec<f32> = (0..441).map(|_| smoother.next()).collect();
+        assert!(values[0] < 1.01);
+        assert!(values.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!((values[440] - (2.0 - (-1.0_f32).exp())).abs() < 0.01);
+
+        // with no smoothing time changes are instant
+        smoother.set_smoothing_time(0.0, 44100.0);
+        smoother.set_target(-1.0);
+        assert_eq!(smoother.next(), -1.0);
+    }
 
     #[test]
     #[ignore]
@@ -0,0 +1,209 @@
+//! Module containing spectral processors which operate on a short time fourier transform of the input.
+//! SpectralFreezer holds the magnitude spectrum of a frame and resynthesises it with random phase.
+#![allow(dead_code)]
+#![warn(missing_docs)]
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// The seed for the random phases of frozen frames
+const PHASE_SEED: u64 = 0x5EED;
+
+/// A struct which performs an STFT on the input and resynthesises it with overlap-add.
+/// When frozen, the last analysed magnitude spectrum is held and resynthesised with randomised phase,
+/// sustaining the sound indefinitely.
+/// # Attributes
+/// * `frame_size`: The length of each FFT frame in samples, must be a power of 2
+/// * `hop_size`: The number of samples between successive frames, a quarter of the frame size
+/// * `window`: A discrete periodic Hann window of length `frame_size`, used for analysis and synthesis
+/// * `input`: A circular buffer storing the last `frame_size` input samples
+/// * `output`: A circular overlap-add buffer storing the resynthesised output
+/// * `write_index`: The index in `input` which the next sample will be written to
+/// * `hop_counter`: The number of samples since the last frame was processed
+/// * `spectrum`: A buffer for the complex spectrum of the current frame
+/// * `magnitudes`: The magnitude spectrum of the last frame analysed before freezing
+/// * `scratch`: Scratch space for the FFT, preallocated to avoid allocating on the audio thread
+/// * `forward`: The planned forward FFT
+/// * `inverse`: The planned inverse FFT
+/// * `frozen`: Whether the magnitude spectrum is currently being held
+/// * `rng`: A seeded random number generator for the phases of frozen frames, so freezes are reproducible
+pub struct SpectralFreezer {
+    frame_size: usize,
+    hop_size: usize,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    output: Vec<f32>,
+    write_index: usize,
+    hop_counter: usize,
+    spectrum: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    scratch: Vec<Complex<f32>>,
+    forward: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    frozen: bool,
+    rng: StdRng,
+}
+
+impl SpectralFreezer {
+    /// Constructor for the spectral freezer
+    /// # Parameters
+    /// * `frame_size`: The length of each FFT frame in samples, must be a power of 2
+    pub fn new(frame_size: usize) -> Self {
+        assert!(
+            frame_size.is_power_of_two() && frame_size >= 4,
+            "Frame size must be a power of 2"
+        );
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(frame_size);
+        let inverse = planner.plan_fft_inverse(frame_size);
+        let scratch_len = forward
+            .get_inplace_scratch_len()
+            .max(inverse.get_inplace_scratch_len());
+
+        let window = (0..frame_size)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / frame_size as f32).cos())
+            .collect();
+
+        Self {
+            frame_size,
+            hop_size: frame_size / 4,
+            window,
+            input: vec![0.0; frame_size],
+            output: vec![0.0; frame_size],
+            write_index: 0,
+            hop_counter: 0,
+            spectrum: vec![Complex::new(0.0, 0.0); frame_size],
+            magnitudes: vec![0.0; frame_size / 2 + 1],
+            scratch: vec![Complex::new(0.0, 0.0); scratch_len],
+            forward,
+            inverse,
+            frozen: false,
+            rng: StdRng::seed_from_u64(PHASE_SEED),
+        }
+    }
+
+    /// Setter for the freeze state. While frozen, the input is ignored and the held spectrum is resynthesised.
+    pub fn freeze(&mut self, on: bool) {
+        self.frozen = on;
+    }
+
+    /// Getter for the freeze state
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Function to process a single sample through the freezer, returning the output sample.
+    /// The output is delayed by one frame relative to the input.
+    pub fn process(&mut self, xn: f32) -> f32 {
+        self.input[self.write_index] = xn;
+        let yn = self.output[self.write_index];
+        self.output[self.write_index] = 0.0;
+
+        self.write_index = (self.write_index + 1) % self.frame_size;
+        self.hop_counter += 1;
+        if self.hop_counter >= self.hop_size {
+            self.hop_counter = 0;
+            self.process_frame();
+        }
+
+        yn
+    }
+
+    /// Analyse the most recent frame of input and overlap-add its resynthesis into the output buffer.
+    fn process_frame(&mut self) {
+        let n = self.frame_size;
+        let half = n / 2;
+
+        match self.frozen {
+            true => {
+                self.spectrum[0] = Complex::new(self.magnitudes[0], 0.0);
+                self.spectrum[half] = Complex::new(self.magnitudes[half], 0.0);
+                for k in 1..half {
+                    let phase = self.rng.gen_range(-PI..PI);
+                    let bin = Complex::from_polar(self.magnitudes[k], phase);
+                    self.spectrum[k] = bin;
+                    self.spectrum[n - k] = bin.conj();
+                }
+            }
+            false => {
+                // oldest sample first, which is the next one to be overwritten
+                for i in 0..n {
+                    let sample = self.input[(self.write_index + i) % n];
+                    self.spectrum[i] = Complex::new(sample * self.window[i], 0.0);
+                }
+                self.forward
+                    .process_with_scratch(&mut self.spectrum, &mut self.scratch);
+                for (magnitude, bin) in self.magnitudes.iter_mut().zip(self.spectrum.iter()) {
+                    *magnitude = bin.norm();
+                }
+            }
+        }
+
+        self.inverse
+            .process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        // A squared Hann window at 75% overlap sums to 1.5, the FFT round trip scales by n
+        let scale = 1.0 / (n as f32 * 1.5);
+        for i in 0..n {
+            let index = (self.write_index + i) % n;
+            self.output[index] += self.spectrum[i].re * self.window[i] * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spectral::SpectralFreezer;
+    use std::f32::consts::PI;
+
+    /// Freezes a second of a 1kHz sine, then returns the next second of frozen output
+    fn frozen_output() -> Vec<f32> {
+        let mut freezer = SpectralFreezer::new(2048);
+        for n in 0..44100 {
+            freezer.process((2.0 * PI * 1000.0 * n as f32 / 44100.0).sin());
+        }
+        freezer.freeze(true);
+        (0..44100).map(|_| freezer.process(0.0)).collect()
+    }
+
+    #[test]
+    fn test_freeze_is_reproducible() {
+        assert_eq!(frozen_output(), frozen_output());
+    }
+
+    #[test]
+    fn test_freeze_sustains_pitch() {
+        let mut freezer = SpectralFreezer::new(2048);
+        let frequency = 1000.0;
+
+        for n in 0..44100 {
+            let xn = (2.0 * PI * frequency * n as f32 / 44100.0).sin();
+            freezer.process(xn);
+        }
+
+        freezer.freeze(true);
+        // let the last unfrozen frames pass through the output
+        for _ in 0..4096 {
+            freezer.process(0.0);
+        }
+
+        let output: Vec<f32> = (0..44100).map(|_| freezer.process(0.0)).collect();
+
+        let rms = (output.iter().map(|x| x * x).sum::<f32>() / output.len() as f32).sqrt();
+        assert!(rms > 0.1, "Frozen output was silent, rms {rms}");
+
+        let crossings = output
+            .windows(2)
+            .filter(|pair| pair[0].signum() != pair[1].signum())
+            .count();
+        // a 1kHz tone crosses zero 2000 times per second
+        assert!(
+            (crossings as i32 - 2000).abs() < 100,
+            "Expected around 2000 zero crossings, found {crossings}"
+        );
+    }
+}
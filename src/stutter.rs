@@ -0,0 +1,127 @@
+#![allow(dead_code)]
+#![warn(missing_docs)]
+//! A module containing a stutter (glitch repeat) effect.
+//! Stutter keeps a history of its input in a delay buffer, and on trigger captures a short slice
+//! which is repeated a number of times at a given rate before the input is passed through again.
+
+use crate::delay_buffer::DelayBuffer;
+
+/// A struct which repeats a captured slice of its input, for rhythmic glitch effects.
+/// ## Attributes:
+/// * `history`: A circular buffer of the most recent input, which slices are captured from
+///
+/// * `slice`: The captured slice, preallocated to the maximum slice length so that triggering doesn't allocate
+///
+/// * `slice_len`: The length of the current slice in samples
+///
+/// * `period_samples`: The number of samples between the start of each repeat
+///
+/// * `repeats_remaining`: The number of repeats left to play, the input is passed through when this is 0
+///
+/// * `position`: The read position within the current repeat period
+///
+/// * `sample_rate`: The sample rate of the input in Hz
+pub struct Stutter {
+    history: DelayBuffer,
+    slice: Vec<f32>,
+    slice_len: usize,
+    period_samples: usize,
+    repeats_remaining: usize,
+    position: usize,
+    sample_rate: f32,
+}
+
+impl Stutter {
+    /// Constructor for the stutter effect
+    /// # Parameters
+    /// * `max_slice_ms`: The longest slice that can be captured, in milliseconds
+    /// * `sample_rate`: The sample rate of the input in Hz
+    pub fn new(max_slice_ms: f32, sample_rate: f32) -> Self {
+        let max_slice_samples = ((max_slice_ms * 0.001 * sample_rate) as usize).max(1);
+        Self {
+            // one extra sample as a delay of the full capacity would wrap onto the newest sample
+            history: DelayBuffer::new(max_slice_samples + 1),
+            slice: vec![0.0; max_slice_samples],
+            slice_len: 0,
+            period_samples: 1,
+            repeats_remaining: 0,
+            position: 0,
+            sample_rate,
+        }
+    }
+
+    /// Captures the most recent `slice_ms` of input and starts repeating it.
+    /// # Parameters
+    /// * `slice_ms`: The length of the slice in milliseconds, limited to the maximum given in the constructor
+    /// * `repeats`: The number of times the slice is played before the input is passed through again
+    /// * `rate_hz`: The number of repeats per second. If a repeat is shorter than the slice, the slice is cut short,
+    ///     and if it is longer, the gap is filled with silence.
+    pub fn trigger(&mut self, slice_ms: f32, repeats: usize, rate_hz: f32) {
+        self.slice_len =
+            ((slice_ms * 0.001 * self.sample_rate) as usize).clamp(1, self.slice.len());
+        // the oldest sample of the slice first, with a delay of 0 being the newest
+        let slice_len = self.slice_len;
+        for (index, sample) in self.slice.iter_mut().take(slice_len).enumerate() {
+            *sample = self.history.read(slice_len - 1 - index);
+        }
+
+        self.period_samples = ((self.sample_rate / rate_hz) as usize).max(1);
+        self.repeats_remaining = repeats;
+        self.position = 0;
+    }
+
+    /// Returns true while the captured slice is being repeated
+    pub fn is_active(&self) -> bool {
+        self.repeats_remaining > 0
+    }
+
+    /// Processes a single sample, passing it through unless a stutter is active,
+    /// in which case the next sample of the repeating slice is returned.
+    pub fn process(&mut self, xn: f32) -> f32 {
+        self.history.write(xn);
+
+        if !self.is_active() {
+            return xn;
+        }
+
+        let value = match self.position < self.slice_len {
+            true => self.slice[self.position],
+            false => 0.0,
+        };
+
+        self.position += 1;
+        if self.position >= self.period_samples {
+            self.position = 0;
+            self.repeats_remaining -= 1;
+        }
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stutter::Stutter;
+
+    #[test]
+    fn test_stutter_repeats() {
+        let mut stutter = Stutter::new(50.0, 44100.0);
+
+        // a ramp makes every captured sample unique
+        for n in 0..1000 {
+            assert_eq!(stutter.process(n as f32), n as f32);
+        }
+
+        // 10ms is 441 samples, and 100Hz repeats back to back
+        stutter.trigger(10.0, 3, 100.0);
+        let output: Vec<f32> = (0..(441 * 3 + 10)).map(|_| stutter.process(-1.0)).collect();
+
+        let expected_slice: Vec<f32> = (559..1000).map(|n| n as f32).collect();
+        for repeat in output.chunks(441).take(3) {
+            assert_eq!(repeat, expected_slice.as_slice());
+        }
+        // input is passed through once the repeats finish
+        assert!(output[441 * 3..].iter().all(|sample| *sample == -1.0));
+        assert!(!stutter.is_active());
+    }
+}
@@ -0,0 +1,29 @@
+//! Module containing helpers shared by the tests of several modules, only compiled when testing.
+//! alias_ratio() measures how much of a test sine's spectrum has aliased after it was processed.
+#![warn(missing_docs)]
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+/// The length of the analysed block, a sine at a whole number of bins has no spectral leakage
+pub(crate) const BLOCK_LEN: usize = 8192;
+/// The bin of the test sine, around 5kHz
+pub(crate) const SINE_BIN: usize = 929;
+
+/// Returns the fraction of a block's energy which isn't in a harmonic of the test sine,
+/// which can only have come from aliasing
+pub(crate) fn alias_ratio(output: &[f32]) -> f32 {
+    let mut spectrum: Vec<Complex<f32>> = output.iter().map(|x| Complex::new(*x, 0.0)).collect();
+    FftPlanner::new()
+        .plan_fft_forward(BLOCK_LEN)
+        .process(&mut spectrum);
+
+    let (harmonic, alias) = spectrum[1..BLOCK_LEN / 2].iter().enumerate().fold(
+        (0.0, 0.0),
+        |(harmonic, alias), (index, value)| match (index + 1) % SINE_BIN {
+            0 => (harmonic + value.norm_sqr(), alias),
+            _ => (harmonic, alias + value.norm_sqr()),
+        },
+    );
+    alias / (harmonic + alias)
+}
@@ -1,7 +1,8 @@
 //! A module containing useful structs and functions for time based conversions
 //! Contains functions / structs used to convert between seconds, samples and tempo based time.
+//! TransportSync follows the host's transport so that tempo synced effects can line up with the bar.
 
-use nih_plug::prelude::Enum;
+use nih_plug::prelude::{Enum, Transport};
 
 /// An enum used for time divisions relative to a bar.
 #[derive(Clone, Default, Enum, PartialEq)]
@@ -71,6 +72,11 @@ impl Timing {
     /// A method to calculate the amount of time in seconds that the instance of Timing takes to complete
     pub fn to_seconds(&self) -> f32 {
         let bar_length_seconds: f32 = 240.0 / self.bpm as f32; // 4 beats at the bpm in seconds is 60 / bpm (1 beat) x 4 or 240 / bpm
+        bar_length_seconds * self.to_bars()
+    }
+
+    /// A method to calculate the length of the timing as a fraction of a bar, which doesn't depend on the bpm
+    pub fn to_bars(&self) -> f32 {
         let divisor = match self.division {
             TimeDiv::Whole => 1.0,
             TimeDiv::Half => 2.0,
@@ -84,7 +90,7 @@ impl Timing {
             NoteModifier::Dotted => 3.0 / 2.0,
             NoteModifier::Triplet => 2.0 / 3.0,
         };
-        (bar_length_seconds / divisor) * scalar
+        scalar / divisor
     }
 
     /// Return the timing object as a number of samples at a sample rate (parameter)
@@ -133,9 +139,131 @@ impl Default for Timing {
     }
 }
 
+/// A struct which tracks the host's transport position sample by sample,
+/// so tempo synced effects can find where they should be within a bar rather than starting from an arbitrary phase.
+///
+/// The position is updated from the host at the start of each processing block, then advanced per sample within it.
+/// ## Attributes:
+/// * `position_beats`: The transport position in quarter note beats from the start of the song
+///
+/// * `tempo`: The tempo of the host in beats per minute
+///
+/// * `beats_per_bar`: The length of a bar in quarter note beats, from the time signature
+///
+/// * `playing`: Whether the transport is playing, the position only advances while it is
+///
+/// * `sample_rate`: The sample rate in Hz, used to convert samples to beats
+pub struct TransportSync {
+    position_beats: f64,
+    tempo: f64,
+    beats_per_bar: f64,
+    playing: bool,
+    sample_rate: f32,
+}
+
+impl Default for TransportSync {
+    fn default() -> Self {
+        Self {
+            position_beats: 0.0,
+            tempo: 120.0,
+            beats_per_bar: 4.0,
+            playing: false,
+            sample_rate: 44100.0,
+        }
+    }
+}
+
+impl TransportSync {
+    /// Constructor for a transport tracker at a sample rate, stopped at the start of the song at 120 BPM in 4/4
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    /// Updates the tracker from the host's transport, called at the start of each processing block.
+    ///
+    /// Information the host doesn't provide keeps its previous value,
+    /// so hosts without a position still advance from where the tracker was.
+    pub fn update(&mut self, transport: &Transport) {
+        if let Some(tempo) = transport.tempo {
+            self.tempo = tempo;
+        }
+        if let (Some(numerator), Some(denominator)) =
+            (transport.time_sig_numerator, transport.time_sig_denominator)
+        {
+            self.set_time_signature(numerator as u8, denominator as u8);
+        }
+        if let Some(position_beats) = transport.pos_beats() {
+            self.position_beats = position_beats;
+        }
+        self.playing = transport.playing;
+    }
+
+    /// Setter for the position in quarter note beats, the tempo in BPM and whether the transport is playing
+    pub fn set_position(&mut self, position_beats: f64, tempo: f64, playing: bool) {
+        self.position_beats = position_beats;
+        self.tempo = tempo;
+        self.playing = playing;
+    }
+
+    /// Setter for the time signature, which decides the length of a bar in quarter notes (6/8 is 3 beats)
+    pub fn set_time_signature(&mut self, numerator: u8, denominator: u8) {
+        self.beats_per_bar = numerator.max(1) as f64 * 4.0 / denominator.max(1) as f64;
+    }
+
+    /// Setter for sample rate in Hz
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Moves the position forward by a number of samples at the current tempo, if the transport is playing
+    pub fn advance(&mut self, samples: usize) {
+        if self.playing {
+            self.position_beats += samples as f64 * self.tempo / (60.0 * self.sample_rate as f64);
+        }
+    }
+
+    /// Getter for the position in quarter note beats
+    pub fn position_beats(&self) -> f64 {
+        self.position_beats
+    }
+
+    /// Getter for the tempo in BPM
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// Getter for whether the transport is playing
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    /// The position through the current bar, from 0 (the downbeat) towards 1
+    pub fn bar_phase(&self) -> f32 {
+        (self.position_beats / self.beats_per_bar).rem_euclid(1.0) as f32
+    }
+
+    /// The position through the current repeat of a time division, from 0 towards 1,
+    /// where every division starts on the downbeat of a bar
+    pub fn phase(&self, division: TimeDiv) -> f32 {
+        self.timing_phase(&Timing::new(division, 120, NoteModifier::Regular))
+    }
+
+    /// The position through the current repeat of a timing, including its note modifier, from 0 towards 1.
+    ///
+    /// Repeats are counted from the start of the song, so dotted and triplet timings which don't divide a bar
+    /// still line up with every other effect using the same timing.
+    pub fn timing_phase(&self, timing: &Timing) -> f32 {
+        let timing_beats = timing.to_bars() as f64 * self.beats_per_bar;
+        (self.position_beats / timing_beats).rem_euclid(1.0) as f32
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{TimeDiv, Timing};
+    use super::{TimeDiv, Timing, TransportSync};
     use crate::timing::NoteModifier;
     #[test]
     fn test_time_calculator() {
@@ -164,4 +292,33 @@ mod tests {
             Timing::new(TimeDiv::Whole, 30, NoteModifier::Dotted).to_samples(44100.0)
         )
     }
+
+    #[test]
+    fn test_transport_phase() {
+        let mut transport = TransportSync::new(44100.0);
+        // 1.5 bars into a song in 4/4
+        transport.set_position(6.0, 120.0, true);
+        assert_eq!(transport.bar_phase(), 0.5);
+        assert_eq!(transport.phase(TimeDiv::Whole), 0.5);
+        assert_eq!(transport.phase(TimeDiv::Half), 0.0);
+        assert_eq!(transport.phase(TimeDiv::Quarter), 0.0);
+
+        // a quarter of a beat at 120 BPM, an eighth of a second
+        transport.advance(5512);
+        assert!((transport.phase(TimeDiv::Quarter) - 0.25).abs() < 0.001);
+        assert!((transport.phase(TimeDiv::Eighth) - 0.5).abs() < 0.001);
+
+        // dotted quarters repeat every 1.5 beats, 6.25 beats in is 1/6 of the way through the fifth
+        let dotted = Timing::new(TimeDiv::Quarter, 120, NoteModifier::Dotted);
+        assert!((transport.timing_phase(&dotted) - 1.0 / 6.0).abs() < 0.001);
+
+        // in 3/4 a whole division is 3 beats long
+        transport.set_time_signature(3, 4);
+        transport.set_position(4.5, 120.0, false);
+        assert_eq!(transport.phase(TimeDiv::Whole), 0.5);
+
+        // the position holds while stopped
+        transport.advance(44100);
+        assert_eq!(transport.position_beats(), 4.5);
+    }
 }